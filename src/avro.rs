@@ -0,0 +1,467 @@
+//! Avro object container files and `.avsc` schemas, shared by the reader and
+//! the writer. Polars encodes and decodes the records; this covers what its
+//! Avro support lacks: the zstandard codec, named and documented schemas, and
+//! schema JSON with field defaults.
+
+use anyhow::{anyhow, bail, Context, Result};
+use avro_schema::file::{Block, CompressedBlock, Compression};
+use avro_schema::schema::{BytesLogical, IntLogical, LongLogical, Record, Schema as AvroSchema};
+use avro_schema::write::encode::zigzag_encode;
+use clap::ValueEnum;
+use polars::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::{Read, Write};
+
+const MAGIC: [u8; 4] = [b'O', b'b', b'j', 1];
+
+/// Avro block compression codecs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AvroCodec {
+    Deflate,
+    Snappy,
+    Zstd,
+}
+
+impl AvroCodec {
+    /// The codec's name in the `avro.codec` header entry.
+    fn name(self) -> &'static str {
+        match self {
+            AvroCodec::Deflate => "deflate",
+            AvroCodec::Snappy => "snappy",
+            AvroCodec::Zstd => "zstandard",
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Option<Self>> {
+        match name {
+            "null" => Ok(None),
+            "deflate" => Ok(Some(AvroCodec::Deflate)),
+            "snappy" => Ok(Some(AvroCodec::Snappy)),
+            "zstandard" => Ok(Some(AvroCodec::Zstd)),
+            other => bail!("Unsupported Avro codec '{other}'"),
+        }
+    }
+}
+
+/// The header of an object container file: the writer schema as JSON, the
+/// block codec and the sync marker that ends every block.
+pub struct Header {
+    pub schema: Value,
+    pub codec: Option<AvroCodec>,
+    pub marker: [u8; 16],
+}
+
+impl Header {
+    /// A header for a new file, with a random sync marker.
+    pub fn new(schema: Value, codec: Option<AvroCodec>) -> Self {
+        let mut marker = [0u8; 16];
+        getrandom::fill(&mut marker).expect("the operating system has no source of random bytes");
+        Self { schema, codec, marker }
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic).context("Not an Avro file")?;
+        if magic != MAGIC {
+            bail!("Not an Avro file: the Obj header is missing");
+        }
+
+        let mut schema = None;
+        let mut codec = None;
+        loop {
+            let count = read_long(reader)?;
+            if count == 0 {
+                break;
+            }
+            if count < 0 {
+                // a negative count is followed by the block's size in bytes
+                read_long(reader)?;
+            }
+            for _ in 0..count.unsigned_abs() {
+                let key = read_bytes(reader)?;
+                let value = read_bytes(reader)?;
+                match key.as_slice() {
+                    b"avro.schema" => {
+                        schema = Some(serde_json::from_slice(&value).context("Invalid schema in Avro header")?)
+                    }
+                    b"avro.codec" => codec = AvroCodec::from_name(&String::from_utf8_lossy(&value))?,
+                    _ => {}
+                }
+            }
+        }
+
+        let mut marker = [0u8; 16];
+        reader.read_exact(&mut marker).context("Truncated Avro header")?;
+        let schema = schema.ok_or_else(|| anyhow!("The Avro header has no schema"))?;
+        Ok(Self { schema, codec, marker })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let schema = serde_json::to_vec(&self.schema)?;
+        let mut entries = vec![("avro.schema", schema.as_slice())];
+        if let Some(codec) = self.codec {
+            entries.push(("avro.codec", codec.name().as_bytes()));
+        }
+
+        writer.write_all(&MAGIC)?;
+        write_long(entries.len() as i64, writer)?;
+        for (key, value) in entries {
+            write_bytes(key.as_bytes(), writer)?;
+            write_bytes(value, writer)?;
+        }
+        write_long(0, writer)?;
+        writer.write_all(&self.marker)?;
+        Ok(())
+    }
+
+    /// The record schema, as Polars' encoders and decoders see it.
+    pub fn record(&self) -> Result<Record> {
+        parse_record(&self.schema)
+    }
+
+    /// Compress a block of `rows` encoded records with the file's codec.
+    pub fn compress(&self, rows: usize, data: Vec<u8>) -> Result<Vec<u8>> {
+        let codec = match self.codec {
+            None => return Ok(data),
+            Some(AvroCodec::Zstd) => return Ok(zstd::bulk::compress(&data, 0)?),
+            Some(AvroCodec::Deflate) => Compression::Deflate,
+            Some(AvroCodec::Snappy) => Compression::Snappy,
+        };
+        let mut compressed = CompressedBlock::default();
+        avro_schema::write::compress(&mut Block::new(rows, data), &mut compressed, Some(codec))
+            .map_err(|e| anyhow!("Error compressing Avro block: {e}"))?;
+        Ok(compressed.data)
+    }
+
+    /// Write a block that is already compressed with the file's codec.
+    pub fn write_block<W: Write>(&self, writer: &mut W, rows: usize, data: &[u8]) -> Result<()> {
+        write_long(rows as i64, writer)?;
+        write_bytes(data, writer)?;
+        writer.write_all(&self.marker)?;
+        Ok(())
+    }
+
+    /// Read the next block following the header: its row count and its
+    /// still-compressed data, or `None` at the end of the file.
+    pub fn read_block<R: Read>(&self, reader: &mut R) -> Result<Option<(usize, Vec<u8>)>> {
+        let Some(rows) = read_long_or_eof(reader)? else {
+            return Ok(None);
+        };
+        let data = read_bytes(reader)?;
+        let mut marker = [0u8; 16];
+        reader.read_exact(&mut marker).context("Truncated Avro block")?;
+        if marker != self.marker {
+            bail!("Corrupt Avro file: a block does not end with the sync marker");
+        }
+        Ok(Some((rows as usize, data)))
+    }
+}
+
+/// Parse a record schema. `avro_schema` expects field defaults to be schemas
+/// rather than values, so they are left out; only schema resolution uses them.
+pub fn parse_record(schema: &Value) -> Result<Record> {
+    let mut schema = schema.clone();
+    strip_defaults(&mut schema);
+    match serde_json::from_value(schema).map_err(|e| anyhow!("Invalid Avro schema: {e}"))? {
+        AvroSchema::Record(record) => Ok(record),
+        _ => bail!("Invalid Avro schema: the top-level type must be a record"),
+    }
+}
+
+fn strip_defaults(schema: &mut Value) {
+    match schema {
+        Value::Object(map) => {
+            if let Some(Value::Array(fields)) = map.get_mut("fields") {
+                for field in fields.iter_mut().filter_map(Value::as_object_mut) {
+                    field.remove("default");
+                }
+            }
+            map.values_mut().for_each(strip_defaults);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_defaults),
+        _ => {}
+    }
+}
+
+/// Load a record schema from an `.avsc` file.
+pub fn load_schema(path: &str) -> Result<Value> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Cannot read Avro schema {path}"))?;
+    let schema = serde_json::from_str(&contents).with_context(|| format!("Invalid Avro schema {path}"))?;
+    parse_record(&schema).with_context(|| format!("Invalid Avro schema {path}"))?;
+    Ok(schema)
+}
+
+/// The Polars type of a field's values, and whether the field may be null.
+/// Nullable fields are unions of null and one other type, with null first,
+/// as that is the branch Polars encodes nulls with.
+pub fn field_dtype(name: &str, schema: &AvroSchema) -> Result<(DataType, bool)> {
+    match schema {
+        AvroSchema::Union(variants) => match variants.as_slice() {
+            [AvroSchema::Null, inner] => Ok((value_dtype(name, inner)?, true)),
+            [_, AvroSchema::Null] => bail!("Avro field '{name}': list \"null\" first in its union"),
+            _ => bail!("Avro field '{name}': only unions of null and one other type are supported"),
+        },
+        other => Ok((value_dtype(name, other)?, false)),
+    }
+}
+
+/// The Polars type of an Avro type's values. Timestamps are UTC instants,
+/// local timestamps are naive datetimes, and UUIDs are strings.
+pub fn value_dtype(name: &str, schema: &AvroSchema) -> Result<DataType> {
+    Ok(match schema {
+        AvroSchema::Boolean => DataType::Boolean,
+        AvroSchema::Int(None) => DataType::Int32,
+        AvroSchema::Int(Some(IntLogical::Date)) => DataType::Date,
+        AvroSchema::Int(Some(IntLogical::Time)) => DataType::Time,
+        AvroSchema::Long(None) => DataType::Int64,
+        AvroSchema::Long(Some(logical)) => match logical {
+            LongLogical::Time => DataType::Time,
+            LongLogical::TimestampMillis => DataType::Datetime(TimeUnit::Milliseconds, Some("UTC".into())),
+            LongLogical::TimestampMicros => DataType::Datetime(TimeUnit::Microseconds, Some("UTC".into())),
+            LongLogical::LocalTimestampMillis => DataType::Datetime(TimeUnit::Milliseconds, None),
+            LongLogical::LocalTimestampMicros => DataType::Datetime(TimeUnit::Microseconds, None),
+        },
+        AvroSchema::Float => DataType::Float32,
+        AvroSchema::Double => DataType::Float64,
+        AvroSchema::String(_) => DataType::String,
+        AvroSchema::Bytes(None) => DataType::Binary,
+        AvroSchema::Bytes(Some(BytesLogical::Decimal(precision, scale))) => DataType::Decimal(Some(*precision), Some(*scale)),
+        other => bail!("Avro field '{name}': type {other:?} is not supported"),
+    })
+}
+
+/// The Avro type a Polars column is written as, or `None` for lists and
+/// structs, which are left to Polars' own mapping. Small and unsigned
+/// integers widen to int or long, except `UInt64`, which a long cannot hold
+/// above `i64::MAX` and so becomes `decimal(20, 0)`. Nanosecond datetimes
+/// are truncated to microseconds, and times are written as time-micros.
+pub fn avro_type(dtype: &DataType) -> Option<AvroSchema> {
+    Some(match dtype {
+        DataType::Boolean => AvroSchema::Boolean,
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::UInt8 | DataType::UInt16 => AvroSchema::Int(None),
+        DataType::Int64 | DataType::UInt32 => AvroSchema::Long(None),
+        DataType::UInt64 => AvroSchema::Bytes(Some(BytesLogical::Decimal(20, 0))),
+        DataType::Float32 => AvroSchema::Float,
+        DataType::Float64 => AvroSchema::Double,
+        DataType::String => AvroSchema::String(None),
+        DataType::Binary => AvroSchema::Bytes(None),
+        DataType::Date => AvroSchema::Int(Some(IntLogical::Date)),
+        DataType::Time => AvroSchema::Long(Some(LongLogical::Time)),
+        DataType::Datetime(unit, tz) => AvroSchema::Long(Some(match (unit, tz) {
+            (TimeUnit::Milliseconds, Some(_)) => LongLogical::TimestampMillis,
+            (TimeUnit::Milliseconds, None) => LongLogical::LocalTimestampMillis,
+            (_, Some(_)) => LongLogical::TimestampMicros,
+            (_, None) => LongLogical::LocalTimestampMicros,
+        })),
+        DataType::Decimal(precision, scale) => {
+            AvroSchema::Bytes(Some(BytesLogical::Decimal(precision.unwrap_or(38), scale.unwrap_or(0))))
+        }
+        _ => return None,
+    })
+}
+
+/// Whether a string column holds UUIDs, in the canonical 8-4-4-4-12 hex form,
+/// and should be written with the uuid logical type. Polars has no UUID type,
+/// so this is how a uuid field read from Avro keeps its logical type through
+/// other formats. A column with no values is plain text.
+pub fn is_uuid_column(column: &Column) -> Result<bool> {
+    if column.dtype() != &DataType::String || column.null_count() == column.len() {
+        return Ok(false);
+    }
+    let is_uuid = |value: &str| {
+        value.len() == 36
+            && value.char_indices().all(|(i, c)| match i {
+                8 | 13 | 18 | 23 => c == '-',
+                _ => c.is_ascii_hexdigit(),
+            })
+    };
+    Ok(column.str()?.into_iter().flatten().all(is_uuid))
+}
+
+/// The column in the form Polars' Avro encoder expects for the field. Times
+/// are encoded from integers in the field's unit, and decimals from their
+/// bytes; other types pass through.
+pub fn to_physical(column: &Column, schema: &AvroSchema) -> Result<Column> {
+    let value = match schema {
+        AvroSchema::Union(variants) => variants.last(),
+        other => Some(other),
+    };
+    match column.dtype() {
+        DataType::Time => {
+            let nanos = column.as_materialized_series().cast(&DataType::Int64)?;
+            let physical = match value {
+                Some(AvroSchema::Int(Some(IntLogical::Time))) => (nanos / 1_000_000).cast(&DataType::Int32)?,
+                _ => nanos / 1_000,
+            };
+            Ok(physical.into_column())
+        }
+        DataType::Decimal(_, _) => {
+            // Polars' encoder drops the sign byte of values such as 0x9C40,
+            // which then read back as negative
+            let unscaled = column.as_materialized_series().decimal()?.physical().clone();
+            let bytes: BinaryChunked = unscaled.into_iter().map(|v| v.map(decimal_bytes)).collect();
+            Ok(bytes.with_name(column.name().clone()).into_column())
+        }
+        _ => Ok(column.clone()),
+    }
+}
+
+/// An unscaled decimal as Avro stores it: big-endian two's complement in as
+/// few bytes as keep the sign.
+fn decimal_bytes(value: i128) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let sign = if value < 0 { 0xff } else { 0 };
+    let mut start = 0;
+    while start < 15 && bytes[start] == sign && (bytes[start + 1] & 0x80 == sign & 0x80) {
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+fn write_long<W: Write>(n: i64, writer: &mut W) -> Result<()> {
+    zigzag_encode(n, writer).map_err(|e| anyhow!("{e}"))
+}
+
+fn write_bytes<W: Write>(bytes: &[u8], writer: &mut W) -> Result<()> {
+    write_long(bytes.len() as i64, writer)?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_long<R: Read>(reader: &mut R) -> Result<i64> {
+    read_long_or_eof(reader)?.ok_or_else(|| anyhow!("Unexpected end of Avro file"))
+}
+
+/// Read a zigzag varint, or `None` if the reader is already at its end.
+fn read_long_or_eof<R: Read>(reader: &mut R) -> Result<Option<i64>> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    let mut byte = [0u8];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            if shift == 0 {
+                return Ok(None);
+            }
+            bail!("Unexpected end of Avro file");
+        }
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 63 {
+            bail!("Corrupt Avro file: a number is too long");
+        }
+    }
+    Ok(Some((value >> 1) as i64 ^ -((value & 1) as i64)))
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = read_long(reader)?;
+    if len < 0 {
+        bail!("Corrupt Avro file: negative length");
+    }
+    let mut bytes = vec![0u8; len as usize];
+    reader.read_exact(&mut bytes).context("Unexpected end of Avro file")?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_schema::schema::StringLogical;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_header_round_trip() -> Result<()> {
+        let schema = serde_json::json!({
+            "type": "record",
+            "name": "User",
+            "fields": [{"name": "id", "type": "long", "default": 0}]
+        });
+        let header = Header::new(schema.clone(), Some(AvroCodec::Zstd));
+        let mut out = Vec::new();
+        header.write(&mut out)?;
+        header.write_block(&mut out, 2, &header.compress(2, vec![2, 4])?)?;
+
+        let mut reader = Cursor::new(out);
+        let read = Header::read(&mut reader)?;
+        assert_eq!(read.schema, schema);
+        assert_eq!(read.codec, Some(AvroCodec::Zstd));
+        assert_eq!(read.marker, header.marker);
+        assert_eq!(read.record()?.fields[0].name, "id");
+
+        let (rows, data) = read.read_block(&mut reader)?.unwrap();
+        assert_eq!(rows, 2);
+        assert_eq!(zstd::decode_all(data.as_slice())?, vec![2, 4]);
+        assert!(read.read_block(&mut reader)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_dtype() -> Result<()> {
+        let nullable = AvroSchema::Union(vec![AvroSchema::Null, AvroSchema::Long(None)]);
+        assert_eq!(field_dtype("a", &nullable)?, (DataType::Int64, true));
+        assert_eq!(field_dtype("a", &AvroSchema::String(None))?, (DataType::String, false));
+
+        let null_last = AvroSchema::Union(vec![AvroSchema::Long(None), AvroSchema::Null]);
+        assert!(field_dtype("a", &null_last).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_logical_type_mapping() -> Result<()> {
+        let utc = |unit| DataType::Datetime(unit, Some("UTC".into()));
+        for dtype in [
+            DataType::Date,
+            DataType::Time,
+            DataType::Datetime(TimeUnit::Milliseconds, None),
+            DataType::Datetime(TimeUnit::Microseconds, None),
+            utc(TimeUnit::Milliseconds),
+            utc(TimeUnit::Microseconds),
+            DataType::Decimal(Some(12), Some(2)),
+        ] {
+            assert_eq!(value_dtype("a", &avro_type(&dtype).unwrap())?, dtype);
+        }
+
+        let written_as = |dtype: DataType| value_dtype("a", &avro_type(&dtype).unwrap()).unwrap();
+        assert_eq!(written_as(DataType::UInt16), DataType::Int32);
+        assert_eq!(written_as(DataType::UInt32), DataType::Int64);
+        assert_eq!(written_as(DataType::UInt64), DataType::Decimal(Some(20), Some(0)));
+        assert_eq!(
+            written_as(DataType::Datetime(TimeUnit::Nanoseconds, None)),
+            DataType::Datetime(TimeUnit::Microseconds, None)
+        );
+        assert_eq!(
+            written_as(DataType::Datetime(TimeUnit::Milliseconds, Some("Europe/Paris".into()))),
+            utc(TimeUnit::Milliseconds)
+        );
+        assert_eq!(value_dtype("a", &AvroSchema::String(Some(StringLogical::Uuid)))?, DataType::String);
+        assert_eq!(value_dtype("a", &AvroSchema::Long(Some(LongLogical::Time)))?, DataType::Time);
+        assert!(avro_type(&DataType::List(Box::new(DataType::Int32))).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal_bytes() {
+        assert_eq!(decimal_bytes(0), [0]);
+        assert_eq!(decimal_bytes(127), [0x7f]);
+        assert_eq!(decimal_bytes(128), [0, 0x80]);
+        assert_eq!(decimal_bytes(40000), [0, 0x9c, 0x40]);
+        assert_eq!(decimal_bytes(-1), [0xff]);
+        assert_eq!(decimal_bytes(-129), [0xff, 0x7f]);
+        assert_eq!(decimal_bytes(u64::MAX as i128), [0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_is_uuid_column() -> Result<()> {
+        let ids = Column::new("id".into(), [Some("6f1c2a4e-3b1d-4c7e-9a52-0d3e8f7b1a21"), None]);
+        assert!(is_uuid_column(&ids)?);
+        let names = Column::new("name".into(), ["6f1c2a4e-3b1d-4c7e-9a52-0d3e8f7b1a21", "Alice"]);
+        assert!(!is_uuid_column(&names)?);
+        assert!(!is_uuid_column(&Column::full_null("id".into(), 2, &DataType::String))?);
+        Ok(())
+    }
+}
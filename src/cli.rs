@@ -1,5 +1,15 @@
+use crate::avro::AvroCodec;
+use crate::compression::FileCompression;
 use crate::format::Format;
-use clap::{ArgAction, Parser};
+use crate::from::json::JsonReadFormat;
+use crate::from::SchemaPolicy;
+use crate::to::csv::CsvQuoteStyle;
+use crate::transform::cast::CastMode;
+use crate::to::json::{JsonTemporalFormat, JsonWriteFormat};
+use crate::to::ipc::IpcCodec;
+use crate::to::parquet::ParquetCodec;
+use crate::to::excel::EXCEL_MAX_DATA_ROWS;
+use clap::{ArgAction, Parser, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -8,25 +18,63 @@ use clap::{ArgAction, Parser};
     about = "Convert between CSV/Parquet/Avro/IPC/JSON with optional column and row filtering."
 )]
 pub struct Cli {
-    /// Source format (csv, parquet, avro, ipc, json)
-    #[arg(value_enum)]
-    pub from_format: Format,
+    /// [FROM] [TO] INPUT: the source and target formats (csv, parquet, avro,
+    /// ipc, ipc-stream, json, excel), then the input file ("-" reads stdin).
+    /// Left-out formats are detected from the input's
+    /// first bytes or extension and the output's extension
+    #[arg(value_name = "ARGS", num_args = 1..=3, required = true)]
+    pub positionals: Vec<String>,
 
-    /// Target format (csv, parquet, avro, ipc, json)
-    #[arg(value_enum)]
-    pub to_format: Format,
-
-    /// Path to the input file
-    pub input_file: String,
-
-    /// Path to the output file
+    /// Path to the output file ("-" writes to stdout)
     #[arg(long, short = 'o', action = ArgAction::Set)]
     pub output: Option<String>,
 
-    /// Append to existing output (CSV only)
+    /// TOML file with writer settings (e.g. a [parquet] table); flags override it
+    #[arg(long, required = false)]
+    pub config: Option<String>,
+
+    /// Append to the existing output instead of replacing it; the columns must
+    /// match. Parquet and IPC files are rewritten, or get a new part file when
+    /// the output is a directory
     #[arg(long, action = ArgAction::SetTrue)]
     pub append: bool,
 
+    /// Also use the streaming engine for output that has to be collected in
+    /// memory first, such as Avro, Excel and JSON arrays; other formats are
+    /// always streamed into the output in batches
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub streaming: bool,
+
+    /// Write a Hive-style dataset under the --output directory, one
+    /// subdirectory per value of these comma-separated columns
+    /// (e.g. year=2024/region=EU/part-00000.parquet)
+    #[arg(long = "partition-by", value_delimiter = ',', required = false)]
+    pub partition_by: Vec<String>,
+
+    /// Hash this column's values into --buckets files per partition
+    #[arg(long = "bucket-by", requires = "buckets", required = false)]
+    pub bucket_by: Option<String>,
+
+    /// Number of bucket files per partition for --bucket-by
+    #[arg(long, requires = "bucket_by", required = false)]
+    pub buckets: Option<usize>,
+
+    /// Collect the rows of at most this many partition or bucket files at a
+    /// time, running the query once to find the files and again for each
+    /// batch of them
+    #[arg(long = "max-files-per-pass", required = false)]
+    pub max_files_per_pass: Option<usize>,
+
+    /// Split the output into numbered part files (out-00000.csv, ...) of at
+    /// most this many rows
+    #[arg(long = "max-rows-per-file", required = false)]
+    pub max_rows_per_file: Option<usize>,
+
+    /// Split the output into numbered part files of at most this size, in
+    /// bytes or with a K, M or G suffix (powers of 1024), e.g. "100M"
+    #[arg(long = "max-bytes-per-file", value_parser = parse_size, required = false)]
+    pub max_bytes_per_file: Option<u64>,
+
     /// Comma-separated list of columns to keep
     #[arg(long = "include-columns", value_delimiter = ',', required = false)]
     pub include_columns: Option<Vec<String>>,
@@ -46,4 +94,307 @@ pub struct Cli {
     /// The value to compare against (e.g. "25" or "LA")
     #[arg(long = "row-filter-val", required = false)]
     pub row_filter_val: Option<String>,
+
+    /// Columns to convert to another type, as column:type pairs, e.g.
+    /// "age:int32,ts:datetime[ms,UTC],price:decimal(12,2)"; may be repeated
+    #[arg(long = "cast", required = false)]
+    pub cast: Vec<String>,
+
+    /// What to do with a value that does not fit its cast: strict fails the
+    /// run, lenient leaves a null and reports how many there were
+    #[arg(long = "cast-mode", value_enum, default_value_t = CastMode::Strict)]
+    pub cast_mode: CastMode,
+
+    /// How to combine the columns of several input files read from a glob
+    /// or directory: strict (all alike), union-by-name or intersect
+    #[arg(long = "schema-policy", value_enum, default_value_t = SchemaPolicy::Strict)]
+    pub schema_policy: SchemaPolicy,
+
+    /// Add a column of this name holding the file each row was read from
+    #[arg(long = "source-column", required = false)]
+    pub source_column: Option<String>,
+
+    /// Read key=value directories under a directory or glob input as plain
+    /// directories rather than Hive partition columns
+    #[arg(long = "no-hive-partitioning", action = ArgAction::SetTrue)]
+    pub no_hive_partitioning: bool,
+
+    /// Column types for CSV or JSON input, declared in a TOML or JSON file
+    /// (a [[columns]] list of name, type, nullable, format) or an Avro .avsc
+    /// schema; declared columns are not inferred, and values that do not fit
+    /// fail the run
+    #[arg(long, required = false)]
+    pub schema: Option<String>,
+
+    /// Compression of the CSV, JSON or Avro input file (default: detected
+    /// from the extension or the first bytes; "none" turns detection off);
+    /// the file is decompressed into TMPDIR before it is read
+    #[arg(long = "input-compression", value_enum, required = false)]
+    pub input_compression: Option<FileCompression>,
+
+    /// CSV field delimiter to read (e.g. ";", "|", or "tab")
+    #[arg(long, default_value = ",", value_parser = parse_byte)]
+    pub delimiter: u8,
+
+    /// The CSV input has no header row; columns are named column_1, column_2, ...
+    #[arg(long = "no-header", action = ArgAction::SetTrue)]
+    pub no_header: bool,
+
+    /// CSV quote character (default: ")
+    #[arg(long = "quote-char", value_parser = parse_byte, required = false)]
+    pub quote_char: Option<u8>,
+
+    /// Read CSV fields literally, without treating any character as a quote
+    #[arg(long = "no-quoting", action = ArgAction::SetTrue)]
+    pub no_quoting: bool,
+
+    /// CSV escape character used inside quoted fields (e.g. "\")
+    #[arg(long = "escape-char", value_parser = parse_byte, required = false)]
+    pub escape_char: Option<u8>,
+
+    /// Skip CSV lines starting with this prefix (e.g. "#")
+    #[arg(long = "comment-prefix", required = false)]
+    pub comment_prefix: Option<String>,
+
+    /// Number of CSV lines to skip before the header
+    #[arg(long = "skip-rows", default_value_t = 0)]
+    pub skip_rows: usize,
+
+    /// Comma-separated CSV values to read as null (e.g. "NA,\N")
+    #[arg(long = "null-values", value_delimiter = ',', required = false)]
+    pub null_values: Option<Vec<String>>,
+
+    /// Number of CSV rows used to infer column types
+    #[arg(long = "infer-schema-rows", default_value_t = 10000)]
+    pub infer_schema_rows: usize,
+
+    /// Infer CSV column types from the whole file
+    #[arg(long = "infer-full-file", action = ArgAction::SetTrue)]
+    pub infer_full_file: bool,
+
+    /// Read every CSV column as a string
+    #[arg(long = "all-strings", action = ArgAction::SetTrue)]
+    pub all_strings: bool,
+
+    /// Delimiter of the CSV output (e.g. ";" or "tab")
+    #[arg(long = "output-delimiter", default_value = ",", value_parser = parse_byte)]
+    pub output_delimiter: u8,
+
+    /// Compress the CSV or JSON output file: gzip, zstd, bzip2, xz or none
+    /// (default: from the output extension, e.g. ".csv.gz")
+    #[arg(long = "output-compression", value_enum, required = false)]
+    pub output_compression: Option<FileCompression>,
+
+    /// When to quote CSV output fields
+    #[arg(long = "output-quote-style", value_enum, default_value_t = CsvQuoteStyle::Necessary)]
+    pub output_quote_style: CsvQuoteStyle,
+
+    /// Text written for nulls in the CSV output (default: empty)
+    #[arg(long = "output-null-value", default_value = "")]
+    pub output_null_value: String,
+
+    /// Write the CSV output without a header row
+    #[arg(long = "output-no-header", action = ArgAction::SetTrue)]
+    pub output_no_header: bool,
+
+    /// End CSV output lines with CRLF instead of LF
+    #[arg(long = "output-crlf", action = ArgAction::SetTrue)]
+    pub output_crlf: bool,
+
+    /// chrono format for dates in the CSV output (e.g. "%d/%m/%Y")
+    #[arg(long = "output-date-format", required = false)]
+    pub output_date_format: Option<String>,
+
+    /// chrono format for datetimes in the CSV output (e.g. "%Y-%m-%d %H:%M")
+    #[arg(long = "output-datetime-format", required = false)]
+    pub output_datetime_format: Option<String>,
+
+    /// chrono format for times in the CSV output (e.g. "%H:%M")
+    #[arg(long = "output-time-format", required = false)]
+    pub output_time_format: Option<String>,
+
+    /// Number of decimals written for floats in the CSV output
+    #[arg(long = "output-float-precision", required = false)]
+    pub output_float_precision: Option<usize>,
+
+    /// Prefix CSV text cells starting with =, +, -, @, tab or CR with ' so
+    /// spreadsheets do not evaluate them as formulas
+    #[arg(long = "escape-formulas", action = ArgAction::SetTrue)]
+    pub escape_formulas: bool,
+
+    /// Layout of the JSON input: one object per line, or a single document
+    #[arg(long = "json-format", value_enum, default_value_t = JsonReadFormat::Lines)]
+    pub json_format: JsonReadFormat,
+
+    /// Dotted path to the array of records in a JSON document (e.g. "data.items")
+    #[arg(long = "record-path", required = false)]
+    pub record_path: Option<String>,
+
+    /// Flatten nested JSON objects into columns named "parent.child"
+    #[arg(long, action = ArgAction::SetTrue)]
+    pub flatten: bool,
+
+    /// Avro reader schema (.avsc) to resolve the input against, so files
+    /// written with other versions of the schema read into its fields
+    #[arg(long = "reader-schema", required = false)]
+    pub reader_schema: Option<String>,
+
+    /// Layout of the JSON output: one object per line, or a single array
+    #[arg(long = "output-json-format", value_enum, default_value_t = JsonWriteFormat::Lines)]
+    pub output_json_format: JsonWriteFormat,
+
+    /// Indent the JSON array output
+    #[arg(long = "output-pretty", action = ArgAction::SetTrue)]
+    pub output_pretty: bool,
+
+    /// Leave null fields out of the JSON output instead of writing null
+    #[arg(long = "output-omit-nulls", action = ArgAction::SetTrue)]
+    pub output_omit_nulls: bool,
+
+    /// How dates and datetimes are written to JSON
+    #[arg(long = "output-temporal-format", value_enum, default_value_t = JsonTemporalFormat::Iso)]
+    pub output_temporal_format: JsonTemporalFormat,
+
+    /// Nest the JSON array under this key, as in {"data": [...]}
+    #[arg(long = "output-wrapper-key", required = false)]
+    pub output_wrapper_key: Option<String>,
+
+    /// Parquet compression codec (default: zstd)
+    #[arg(long = "parquet-compression", value_enum, required = false)]
+    pub parquet_compression: Option<ParquetCodec>,
+
+    /// Parquet compression level: zstd 1-22, gzip 0-9, brotli 0-11
+    #[arg(long = "parquet-compression-level", required = false)]
+    pub parquet_compression_level: Option<u32>,
+
+    /// Rows per Parquet row group (default: 262144)
+    #[arg(long = "parquet-row-group-size", required = false)]
+    pub parquet_row_group_size: Option<usize>,
+
+    /// Target Parquet data page size in bytes (default: 1048576)
+    #[arg(long = "parquet-data-page-size", required = false)]
+    pub parquet_data_page_size: Option<usize>,
+
+    /// Write Parquet column statistics, true or false (default: true)
+    #[arg(long = "parquet-statistics", required = false)]
+    pub parquet_statistics: Option<bool>,
+
+    /// Allow Parquet dictionary encoding, true or false (default: true)
+    #[arg(long = "parquet-dictionary", required = false)]
+    pub parquet_dictionary: Option<bool>,
+
+    /// Read local Arrow IPC files into memory in full instead of scanning
+    /// them through a memory map
+    #[arg(long = "no-memory-map", action = ArgAction::SetTrue)]
+    pub no_memory_map: bool,
+
+    /// Compress Arrow IPC output buffers (default: uncompressed)
+    #[arg(long = "ipc-compression", value_enum, required = false)]
+    pub ipc_compression: Option<IpcCodec>,
+
+    /// Avro block compression codec (default: uncompressed)
+    #[arg(long = "avro-compression", value_enum, required = false)]
+    pub avro_compression: Option<AvroCodec>,
+
+    /// Name of the Avro record (e.g. "Customer"; default: "Record")
+    #[arg(long = "avro-record-name", required = false)]
+    pub avro_record_name: Option<String>,
+
+    /// Namespace of the Avro record (e.g. "com.example.sales")
+    #[arg(long = "avro-namespace", required = false)]
+    pub avro_namespace: Option<String>,
+
+    /// Doc for an Avro field, as "column=text"; repeat for more fields
+    #[arg(long = "avro-field-doc", value_parser = parse_key_value, action = ArgAction::Append)]
+    pub avro_field_docs: Vec<(String, String)>,
+
+    /// Avro schema (.avsc) that the output is coerced to and written with
+    #[arg(long = "avro-schema", required = false)]
+    pub avro_schema: Option<String>,
+
+    /// Excel sheet to read, by name or 1-based position (default: the first sheet)
+    #[arg(long, required = false)]
+    pub sheet: Option<String>,
+
+    /// Excel row holding the column names, counted from the top of the sheet or
+    /// --cell-range; rows above it are skipped, 0 means no header
+    #[arg(long = "header-row", default_value_t = 1)]
+    pub header_row: usize,
+
+    /// Excel cell range to read (e.g. "B2:F100")
+    #[arg(long = "cell-range", required = false)]
+    pub cell_range: Option<String>,
+
+    /// Read every Excel sheet into one frame, matching columns by name
+    #[arg(long = "all-sheets", action = ArgAction::SetTrue)]
+    pub all_sheets: bool,
+
+    /// Column recording the source sheet when using --all-sheets
+    #[arg(long = "sheet-column", default_value = "sheet")]
+    pub sheet_column: String,
+
+    /// Read Excel number columns without fractions as integers rather than
+    /// floats, the type Excel stores every number as
+    #[arg(long = "whole-numbers-as-integers", action = ArgAction::SetTrue)]
+    pub whole_numbers_as_integers: bool,
+
+    /// Name of the Excel sheet to write
+    #[arg(long = "sheet-name", default_value = "Sheet1")]
+    pub sheet_name: String,
+
+    /// Write one Excel sheet per distinct value of this column
+    #[arg(long = "split-sheets-by", required = false)]
+    pub split_sheets_by: Option<String>,
+
+    /// Start a new Excel sheet after this many rows (at most 1048575)
+    #[arg(long = "max-rows-per-sheet", default_value_t = EXCEL_MAX_DATA_ROWS)]
+    pub max_rows_per_sheet: usize,
+}
+
+impl Cli {
+    /// The formats named on the command line, and the input file.
+    pub fn formats_and_input(&self) -> Result<(Option<Format>, Option<Format>, String), String> {
+        let parse = |s: &String| Format::from_str(s, true).map_err(|_| format!("unknown format '{s}'"));
+        match self.positionals.as_slice() {
+            [input] => Ok((None, None, input.clone())),
+            [from, input] => Ok((Some(parse(from)?), None, input.clone())),
+            [from, to, input] => Ok((Some(parse(from)?), Some(parse(to)?), input.clone())),
+            _ => Err("expected [FROM] [TO] INPUT".to_string()),
+        }
+    }
+}
+
+/// Parse a single-byte character argument, accepting "tab" and "\t" for a tab.
+fn parse_byte(s: &str) -> Result<u8, String> {
+    match s {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err(format!("expected a single ASCII character, got '{s}'")),
+    }
+}
+
+/// Parse a size in bytes, with an optional K, M or G suffix (powers of 1024).
+fn parse_size(s: &str) -> Result<u64, String> {
+    let upper = s.trim().to_ascii_uppercase();
+    let digits = upper.trim_end_matches(['B', 'I']);
+    let (number, scale) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 1 << 10),
+        Some('M') => (&digits[..digits.len() - 1], 1 << 20),
+        Some('G') => (&digits[..digits.len() - 1], 1 << 30),
+        _ => (digits, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .ok_or_else(|| format!("expected a size such as 1048576, 512K, 100M or 2G, got '{s}'"))
+}
+
+/// Parse a "key=value" argument.
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{s}'")),
+    }
 }
@@ -0,0 +1,201 @@
+//! Whole-file compression of text files such as `.csv.gz` or `.jsonl.zst`, as
+//! opposed to the codecs formats apply inside the file (Parquet pages, IPC
+//! buffers, Avro blocks).
+
+use crate::storage::retain_spool;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// Compression of a whole file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FileCompression {
+    /// Not compressed, whatever the extension or contents suggest
+    #[value(name = "none")]
+    Uncompressed,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl FileCompression {
+    /// The compression named by a path's extension, e.g. `.gz` in `feed.csv.gz`.
+    pub fn from_extension(path: &str) -> Option<Self> {
+        let ext = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "gz" | "gzip" => Some(FileCompression::Gzip),
+            "zst" | "zstd" => Some(FileCompression::Zstd),
+            "bz2" => Some(FileCompression::Bzip2),
+            "xz" => Some(FileCompression::Xz),
+            _ => None,
+        }
+    }
+
+    /// The extension files compressed this way are given, e.g. `gz`.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            FileCompression::Uncompressed => None,
+            FileCompression::Gzip => Some("gz"),
+            FileCompression::Zstd => Some("zst"),
+            FileCompression::Bzip2 => Some("bz2"),
+            FileCompression::Xz => Some("xz"),
+        }
+    }
+
+    /// The compression whose magic number starts `bytes`.
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(FileCompression::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(FileCompression::Zstd)
+        } else if bytes.starts_with(b"BZh") {
+            Some(FileCompression::Bzip2)
+        } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(FileCompression::Xz)
+        } else {
+            None
+        }
+    }
+
+    /// A reader of the decompressed contents. Concatenated gzip, bzip2 and
+    /// xz members are read as one stream, as their command line tools do.
+    pub fn decoder<'a, R: Read + 'a>(self, reader: R) -> Result<Box<dyn Read + 'a>> {
+        let reader = BufReader::new(reader);
+        Ok(match self {
+            FileCompression::Uncompressed => Box::new(reader),
+            FileCompression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+            FileCompression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+            FileCompression::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(reader)),
+            FileCompression::Xz => Box::new(liblzma::bufread::XzDecoder::new_multi_decoder(reader)),
+        })
+    }
+}
+
+/// Compress everything `reader` yields into `writer`, returning `writer`
+/// for the caller to flush.
+pub fn compress<R: Read, W: Write>(compression: FileCompression, reader: &mut R, mut writer: W) -> Result<W> {
+    Ok(match compression {
+        FileCompression::Uncompressed => {
+            io::copy(reader, &mut writer)?;
+            writer
+        }
+        FileCompression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+            io::copy(reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        FileCompression::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, 0)?;
+            io::copy(reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        FileCompression::Bzip2 => {
+            let mut encoder = bzip2::write::BzEncoder::new(writer, bzip2::Compression::default());
+            io::copy(reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        FileCompression::Xz => {
+            let mut encoder = liblzma::write::XzEncoder::new(writer, 6);
+            io::copy(reader, &mut encoder)?;
+            encoder.finish()?
+        }
+    })
+}
+
+/// The compression to write `path` with: `compression` if given, otherwise
+/// the one named by its extension. `None` means plain output.
+pub fn output_compression(path: &str, compression: Option<FileCompression>) -> Option<FileCompression> {
+    compression
+        .or_else(|| FileCompression::from_extension(path))
+        .filter(|compression| *compression != FileCompression::Uncompressed)
+}
+
+/// The compression of a source: `compression` if given, otherwise the one
+/// named by the extension of `path`, otherwise the one whose magic number
+/// starts the local file `source`.
+pub fn detect(path: &str, source: &Path, compression: Option<FileCompression>) -> Result<FileCompression> {
+    if let Some(compression) = compression.or_else(|| FileCompression::from_extension(path)) {
+        return Ok(compression);
+    }
+    let mut magic = Vec::with_capacity(6);
+    File::open(source)?.take(6).read_to_end(&mut magic)?;
+    Ok(FileCompression::from_magic(&magic).unwrap_or(FileCompression::Uncompressed))
+}
+
+/// A local path to the decompressed contents of `source`, the local copy of
+/// the input `path`: `source` itself if it is not compressed, otherwise a
+/// spool retained until `release_spools`, so lazy scans can read it.
+///
+/// The lazy CSV and JSON scans only read files, so the decoder cannot feed
+/// them directly; the spool holds the whole decompressed input and needs that
+/// much room in the temporary directory.
+pub fn decompressed_source(path: &str, source: PathBuf, compression: Option<FileCompression>) -> Result<PathBuf> {
+    let compression = detect(path, &source, compression)?;
+    if compression == FileCompression::Uncompressed {
+        return Ok(source);
+    }
+
+    let spool = NamedTempFile::new()?;
+    let mut decoder = compression.decoder(File::open(&source)?)?;
+    let mut writer = BufWriter::new(spool.as_file());
+    io::copy(&mut decoder, &mut writer)
+        .with_context(|| format!("Cannot decompress {path} as {compression:?} into {}", spool.path().display()))?;
+    writer.flush()?;
+    drop(writer);
+    retain_spool(spool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "name,age\nAlice,30\nBob,25\n";
+
+    fn compressed(compression: FileCompression, data: &[u8]) -> Result<Vec<u8>> {
+        compress(compression, &mut &data[..], Vec::new())
+    }
+
+    #[test]
+    fn test_detect_from_extension_and_magic() -> Result<()> {
+        assert_eq!(FileCompression::from_extension("feed.csv.gz"), Some(FileCompression::Gzip));
+        assert_eq!(FileCompression::from_extension("s3://bucket/feed.JSONL.ZST"), Some(FileCompression::Zstd));
+        assert_eq!(FileCompression::from_extension("feed.csv"), None);
+
+        for compression in [FileCompression::Gzip, FileCompression::Zstd, FileCompression::Bzip2, FileCompression::Xz] {
+            assert_eq!(FileCompression::from_magic(&compressed(compression, CSV.as_bytes())?), Some(compression));
+        }
+        assert_eq!(FileCompression::from_magic(CSV.as_bytes()), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompressed_source() -> Result<()> {
+        for compression in [FileCompression::Gzip, FileCompression::Zstd, FileCompression::Bzip2, FileCompression::Xz] {
+            // no telling extension, so the magic number decides
+            let file = NamedTempFile::new()?;
+            std::fs::write(file.path(), compressed(compression, CSV.as_bytes())?)?;
+            let path = file.path().to_str().unwrap();
+            let source = decompressed_source(path, file.path().to_path_buf(), None)?;
+            assert_ne!(source, file.path());
+            assert_eq!(std::fs::read_to_string(source)?, CSV);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_override_wins() -> Result<()> {
+        let file = NamedTempFile::new()?;
+        std::fs::write(file.path(), CSV)?;
+        let source = decompressed_source("feed.csv.gz", file.path().to_path_buf(), Some(FileCompression::Uncompressed))?;
+        assert_eq!(source, file.path());
+
+        // a wrong override fails rather than passing garbage on
+        let result = decompressed_source("feed.csv", file.path().to_path_buf(), Some(FileCompression::Gzip));
+        assert!(result.is_err());
+        Ok(())
+    }
+}
@@ -1,5 +1,19 @@
+use crate::avro::AvroCodec;
+use crate::cli::Cli;
+use crate::compression::FileCompression;
 use crate::format::Format;
-use anyhow::Error;
+use crate::from::json::JsonReadFormat;
+use crate::from::SchemaPolicy;
+use crate::storage;
+use crate::to::csv::CsvQuoteStyle;
+use crate::to::json::{JsonTemporalFormat, JsonWriteFormat};
+use crate::to::ipc::IpcCodec;
+use crate::to::parquet::ParquetCodec;
+use crate::transform::cast::CastMode;
+use anyhow::{Context, Error};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
 
 #[derive(Debug)]
 pub struct Config {
@@ -7,46 +21,426 @@ pub struct Config {
     pub to_format: Format,
     pub input_file: String,
     pub output_file: Option<String>,
+    pub append: bool,
+    pub streaming: bool,
     pub include_columns: Option<Vec<String>>,
     pub exclude_columns: Option<Vec<String>>,
+    pub input_compression: Option<FileCompression>,
+    pub output_compression: Option<FileCompression>,
 
     // new row-filter fields
     pub row_filter_col: Option<String>,
     pub row_filter_op: Option<String>,
     pub row_filter_val: Option<String>,
+
+    // Cast fields
+    pub cast: Vec<String>,
+    pub cast_mode: CastMode,
+
+    // Multi-file input fields
+    pub schema_policy: SchemaPolicy,
+    pub source_column: Option<String>,
+    pub hive_partitioning: bool,
+
+    // Declared input schema
+    pub schema: Option<String>,
+
+    // Partitioned output fields
+    pub partition_by: Vec<String>,
+    pub bucket_by: Option<String>,
+    pub buckets: usize,
+    pub max_files_per_pass: Option<usize>,
+
+    // Split output fields
+    pub max_rows_per_file: Option<usize>,
+    pub max_bytes_per_file: Option<u64>,
+
+    // CSV reader fields
+    pub delimiter: u8,
+    pub has_header: bool,
+    pub quote_char: Option<u8>,
+    pub escape_char: Option<u8>,
+    pub comment_prefix: Option<String>,
+    pub skip_rows: usize,
+    pub null_values: Vec<String>,
+    pub infer_schema_rows: Option<usize>,
+    pub all_strings: bool,
+
+    // CSV writer fields
+    pub output_delimiter: u8,
+    pub output_quote_style: CsvQuoteStyle,
+    pub output_null_value: String,
+    pub output_has_header: bool,
+    pub output_crlf: bool,
+    pub output_date_format: Option<String>,
+    pub output_datetime_format: Option<String>,
+    pub output_time_format: Option<String>,
+    pub output_float_precision: Option<usize>,
+    pub escape_formulas: bool,
+
+    // JSON reader fields
+    pub json_format: JsonReadFormat,
+    pub record_path: Option<String>,
+    pub flatten: bool,
+
+    // Avro reader fields
+    pub reader_schema: Option<String>,
+
+    // JSON writer fields
+    pub output_json_format: JsonWriteFormat,
+    pub output_pretty: bool,
+    pub output_omit_nulls: bool,
+    pub output_temporal_format: JsonTemporalFormat,
+    pub output_wrapper_key: Option<String>,
+
+    // Parquet writer fields
+    pub parquet_compression: ParquetCodec,
+    pub parquet_compression_level: Option<u32>,
+    pub parquet_row_group_size: Option<usize>,
+    pub parquet_data_page_size: Option<usize>,
+    pub parquet_statistics: bool,
+    pub parquet_dictionary: bool,
+
+    // IPC reader fields
+    pub ipc_memory_map: bool,
+
+    // IPC writer fields
+    pub ipc_compression: Option<IpcCodec>,
+
+    // Avro writer fields
+    pub avro_compression: Option<AvroCodec>,
+    pub avro_record_name: Option<String>,
+    pub avro_namespace: Option<String>,
+    pub avro_field_docs: Vec<(String, String)>,
+    pub avro_schema: Option<String>,
+
+    // Excel reader fields
+    pub sheet: Option<String>,
+    pub header_row: usize,
+    pub cell_range: Option<String>,
+    pub all_sheets: bool,
+    pub sheet_column: String,
+    pub whole_numbers_as_integers: bool,
+
+    // Excel writer fields
+    pub sheet_name: String,
+    pub split_sheets_by: Option<String>,
+    pub max_rows_per_sheet: usize,
 }
 
-impl Config {
-    pub fn new(
-        from_format: Format,
-        to_format: Format,
-        input_file: String,
-        output_file: Option<String>,
-        include_columns: Option<Vec<String>>,
-        exclude_columns: Option<Vec<String>>,
-        row_filter_col: Option<String>,
-        row_filter_op: Option<String>,
-        row_filter_val: Option<String>,
-    ) -> Self {
-        Self {
+/// Writer settings read from the `--config` TOML file. Flags given on the
+/// command line take precedence.
+///
+/// ```toml
+/// [parquet]
+/// compression = "zstd"
+/// compression_level = 3
+/// row_group_size = 122880
+/// statistics = true
+/// dictionary = false
+///
+/// [avro]
+/// compression = "deflate"
+/// record_name = "Customer"
+/// namespace = "com.example.sales"
+/// field_docs = { id = "Customer number" }
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub parquet: ParquetSettings,
+    #[serde(default)]
+    pub avro: AvroSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParquetSettings {
+    pub compression: Option<ParquetCodec>,
+    pub compression_level: Option<u32>,
+    pub row_group_size: Option<usize>,
+    pub data_page_size: Option<usize>,
+    pub statistics: Option<bool>,
+    pub dictionary: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AvroSettings {
+    pub compression: Option<AvroCodec>,
+    pub record_name: Option<String>,
+    pub namespace: Option<String>,
+    #[serde(default)]
+    pub field_docs: BTreeMap<String, String>,
+    pub schema: Option<String>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path).with_context(|| format!("Cannot read config file {path}"))?;
+        toml::from_str(&contents).with_context(|| format!("Invalid config file {path}"))
+    }
+}
+
+impl TryFrom<Cli> for Config {
+    type Error = Error;
+
+    fn try_from(cli: Cli) -> Result<Self, Error> {
+        let (from_format, to_format, input_file) = cli.formats_and_input().map_err(Error::msg)?;
+        let from_format = match from_format {
+            Some(format) => format,
+            // a directory has no extension, so look at the first file in it
+            None => Format::detect_source(&input_file).or_else(|err| match storage::expand_input(&input_file, None) {
+                Ok(files) if files[0] != input_file => Format::detect_source(&files[0]),
+                _ => Err(err),
+            })?,
+        };
+        let to_format = match (to_format, &cli.output) {
+            (Some(format), _) => format,
+            (None, Some(output)) => Format::detect_target(output)?,
+            (None, None) => anyhow::bail!("Output file must be specified via --output"),
+        };
+
+        let file = match &cli.config {
+            Some(path) => ConfigFile::load(path)?,
+            None => ConfigFile::default(),
+        };
+        let parquet = file.parquet;
+        let avro = file.avro;
+        // docs from flags are applied last, so they win over the file's
+        let mut avro_field_docs: Vec<_> = avro.field_docs.into_iter().collect();
+        avro_field_docs.extend(cli.avro_field_docs);
+
+        Ok(Self {
             from_format,
             to_format,
             input_file,
-            output_file,
-            include_columns,
-            exclude_columns,
+            output_file: cli.output,
+            append: cli.append,
+            streaming: cli.streaming,
+            include_columns: cli.include_columns,
+            exclude_columns: cli.exclude_columns,
+            input_compression: cli.input_compression,
+            output_compression: cli.output_compression,
 
-            row_filter_col,
-            row_filter_op,
-            row_filter_val,
-        }
+            row_filter_col: cli.row_filter_col,
+            row_filter_op: cli.row_filter_op,
+            row_filter_val: cli.row_filter_val,
+
+            cast: cli.cast,
+            cast_mode: cli.cast_mode,
+
+            schema_policy: cli.schema_policy,
+            source_column: cli.source_column,
+            hive_partitioning: !cli.no_hive_partitioning,
+
+            schema: cli.schema,
+
+            partition_by: cli.partition_by,
+            bucket_by: cli.bucket_by,
+            buckets: cli.buckets.unwrap_or(1),
+            max_files_per_pass: cli.max_files_per_pass,
+
+            max_rows_per_file: cli.max_rows_per_file,
+            max_bytes_per_file: cli.max_bytes_per_file,
+
+            delimiter: cli.delimiter,
+            has_header: !cli.no_header,
+            quote_char: (!cli.no_quoting).then(|| cli.quote_char.unwrap_or(b'"')),
+            escape_char: cli.escape_char,
+            comment_prefix: cli.comment_prefix,
+            skip_rows: cli.skip_rows,
+            null_values: cli.null_values.unwrap_or_default(),
+            infer_schema_rows: (!cli.infer_full_file).then_some(cli.infer_schema_rows),
+            all_strings: cli.all_strings,
+
+            output_delimiter: cli.output_delimiter,
+            output_quote_style: cli.output_quote_style,
+            output_null_value: cli.output_null_value,
+            output_has_header: !cli.output_no_header,
+            output_crlf: cli.output_crlf,
+            output_date_format: cli.output_date_format,
+            output_datetime_format: cli.output_datetime_format,
+            output_time_format: cli.output_time_format,
+            output_float_precision: cli.output_float_precision,
+            escape_formulas: cli.escape_formulas,
+
+            json_format: cli.json_format,
+            record_path: cli.record_path,
+            flatten: cli.flatten,
+
+            reader_schema: cli.reader_schema,
+
+            output_json_format: cli.output_json_format,
+            output_pretty: cli.output_pretty,
+            output_omit_nulls: cli.output_omit_nulls,
+            output_temporal_format: cli.output_temporal_format,
+            output_wrapper_key: cli.output_wrapper_key,
+
+            parquet_compression: cli.parquet_compression.or(parquet.compression).unwrap_or_default(),
+            parquet_compression_level: cli.parquet_compression_level.or(parquet.compression_level),
+            parquet_row_group_size: cli.parquet_row_group_size.or(parquet.row_group_size),
+            parquet_data_page_size: cli.parquet_data_page_size.or(parquet.data_page_size),
+            parquet_statistics: cli.parquet_statistics.or(parquet.statistics).unwrap_or(true),
+            parquet_dictionary: cli.parquet_dictionary.or(parquet.dictionary).unwrap_or(true),
+
+            ipc_memory_map: !cli.no_memory_map,
+
+            ipc_compression: cli.ipc_compression,
+
+            avro_compression: cli.avro_compression.or(avro.compression),
+            avro_record_name: cli.avro_record_name.or(avro.record_name),
+            avro_namespace: cli.avro_namespace.or(avro.namespace),
+            avro_field_docs,
+            avro_schema: cli.avro_schema.or(avro.schema),
+
+            sheet: cli.sheet,
+            header_row: cli.header_row,
+            cell_range: cli.cell_range,
+            all_sheets: cli.all_sheets,
+            sheet_column: cli.sheet_column,
+            whole_numbers_as_integers: cli.whole_numbers_as_integers,
+
+            sheet_name: cli.sheet_name,
+            split_sheets_by: cli.split_sheets_by,
+            max_rows_per_sheet: cli.max_rows_per_sheet,
+        })
     }
+}
 
+impl Config {
     pub fn validate(&self) -> Result<(), Error> {
         if self.output_file.is_none() {
             anyhow::bail!("Output file must be specified via --output");
         }
+        let compressed = self.output_compression.is_some_and(|c| c != FileCompression::Uncompressed);
+        if compressed && !matches!(self.to_format, Format::Csv | Format::Json) {
+            anyhow::bail!("--output-compression applies to CSV and JSON output; {:?} output has its own codec settings", self.to_format);
+        }
+        if self.schema.is_some() && !matches!(self.from_format, Format::Csv | Format::Json) {
+            anyhow::bail!("--schema applies to CSV and JSON input; {:?} files carry their own types", self.from_format);
+        }
+        let partitioned = !self.partition_by.is_empty() || self.bucket_by.is_some();
+        if partitioned && self.output_file.as_deref() == Some(crate::storage::STDIO_PATH) {
+            anyhow::bail!("A partitioned dataset needs an output directory, not stdout");
+        }
+        if self.bucket_by.is_some() && self.buckets == 0 {
+            anyhow::bail!("--buckets must be at least 1");
+        }
+        if self.max_files_per_pass == Some(0) {
+            anyhow::bail!("--max-files-per-pass must be at least 1");
+        }
+        let split = self.max_rows_per_file.is_some() || self.max_bytes_per_file.is_some();
+        if split && partitioned {
+            anyhow::bail!("--max-rows-per-file and --max-bytes-per-file cannot be combined with --partition-by or --bucket-by");
+        }
+        if split && self.append {
+            anyhow::bail!("Split output cannot be appended to; each run writes a fresh set of part files");
+        }
+        if split && self.output_file.as_deref() == Some(crate::storage::STDIO_PATH) {
+            anyhow::bail!("Split output needs an output file name, not stdout");
+        }
+        if self.max_rows_per_file == Some(0) || self.max_bytes_per_file == Some(0) {
+            anyhow::bail!("--max-rows-per-file and --max-bytes-per-file must be at least 1");
+        }
         // no other validations needed if row_filter_col/op/val are optional
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn config_with(file_contents: &str, flags: &[&str]) -> Result<Config, Error> {
+        let mut file = NamedTempFile::new()?;
+        file.write_all(file_contents.as_bytes())?;
+        let path = file.path().to_str().unwrap();
+        let mut args = vec!["frameblaze", "csv", "parquet", "in.csv", "-o", "out.parquet", "--config", path];
+        args.extend_from_slice(flags);
+        Config::try_from(Cli::parse_from(args))
+    }
+
+    #[test]
+    fn test_config_file_parquet_settings() -> Result<(), Error> {
+        let contents = "[parquet]\ncompression = \"gzip\"\ncompression_level = 6\nrow_group_size = 1000\nstatistics = false\n";
+        let config = config_with(contents, &[])?;
+        assert_eq!(config.parquet_compression, ParquetCodec::Gzip);
+        assert_eq!(config.parquet_compression_level, Some(6));
+        assert_eq!(config.parquet_row_group_size, Some(1000));
+        assert!(!config.parquet_statistics);
+        assert!(config.parquet_dictionary);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flags_override_config_file() -> Result<(), Error> {
+        let contents = "[parquet]\ncompression = \"gzip\"\nstatistics = false\n";
+        let flags = ["--parquet-compression", "snappy", "--parquet-statistics", "true"];
+        let config = config_with(contents, &flags)?;
+        assert_eq!(config.parquet_compression, ParquetCodec::Snappy);
+        assert!(config.parquet_statistics);
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_file_avro_settings() -> Result<(), Error> {
+        let contents = "[avro]\ncompression = \"zstd\"\nnamespace = \"com.example\"\nfield_docs = { id = \"Key\", name = \"Full name\" }\n";
+        let flags = ["--avro-record-name", "Customer", "--avro-field-doc", "id=Customer number"];
+        let config = config_with(contents, &flags)?;
+        assert_eq!(config.avro_compression, Some(AvroCodec::Zstd));
+        assert_eq!(config.avro_record_name.as_deref(), Some("Customer"));
+        assert_eq!(config.avro_namespace.as_deref(), Some("com.example"));
+        let docs: Vec<_> = config.avro_field_docs.iter().map(|(c, d)| format!("{c}={d}")).collect();
+        assert_eq!(docs, ["id=Key", "name=Full name", "id=Customer number"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_config_file() {
+        assert!(config_with("[parquet]\ncodec = \"zstd\"\n", &[]).is_err());
+        assert!(config_with("[parquet]\ncompression = \"zip\"\n", &[]).is_err());
+    }
+
+    fn config_from(args: &[&str]) -> Result<Config, Error> {
+        Config::try_from(Cli::try_parse_from(std::iter::once("frameblaze").chain(args.iter().copied()))?)
+    }
+
+    #[test]
+    fn test_formats_detected_when_left_out() -> Result<(), Error> {
+        let config = config_from(&["feed.csv.gz", "-o", "out.parquet"])?;
+        assert_eq!((config.from_format, config.to_format), (Format::Csv, Format::Parquet));
+        assert_eq!(config.input_file, "feed.csv.gz");
+
+        // a named source format, and the target from the extension
+        let config = config_from(&["json", "export.txt", "-o", "out.arrow"])?;
+        assert_eq!((config.from_format, config.to_format), (Format::Json, Format::Ipc));
+
+        // named formats win over the extensions
+        let config = config_from(&["csv", "avro", "data.parquet", "-o", "out.json"])?;
+        assert_eq!((config.from_format, config.to_format), (Format::Csv, Format::Avro));
+
+        assert!(config_from(&["export.txt", "-o", "out.parquet"]).is_err());
+        assert!(config_from(&["data.csv", "-o", "out.bin"]).is_err());
+        assert!(config_from(&["xml", "data.csv", "-o", "out.parquet"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_output_flags() -> Result<(), Error> {
+        let config = config_from(&["in.csv", "-o", "out.csv", "--max-bytes-per-file", "100M", "--max-rows-per-file", "5000"])?;
+        assert_eq!(config.max_bytes_per_file, Some(100 << 20));
+        assert_eq!(config.max_rows_per_file, Some(5000));
+        config.validate()?;
+        assert_eq!(config_from(&["in.csv", "-o", "out.csv", "--max-bytes-per-file", "2GiB"])?.max_bytes_per_file, Some(2 << 30));
+        assert!(config_from(&["in.csv", "-o", "out.csv", "--max-bytes-per-file", "lots"]).is_err());
+
+        let config = config_from(&["in.csv", "-o", "out.csv", "--max-rows-per-file", "10", "--append"])?;
+        assert!(config.validate().is_err());
+        Ok(())
+    }
+}
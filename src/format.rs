@@ -1,4 +1,8 @@
+use crate::compression::FileCompression;
+use crate::storage::{Storage, STDIO_PATH};
+use anyhow::{bail, Result};
 use clap::ValueEnum;
+use std::path::Path;
 
 #[derive(Clone, Debug, Copy, PartialEq, Eq, ValueEnum)]
 pub enum Format {
@@ -6,5 +10,107 @@ pub enum Format {
     Parquet,
     Avro,
     Ipc,
+    /// Arrow IPC stream format, which can be piped through stdin and stdout
+    IpcStream,
     Json,
-}
\ No newline at end of file
+    Excel,
+}
+
+impl Format {
+    /// The format named by a path's extension, looking past a compression
+    /// extension, so `feed.csv.gz` is CSV.
+    pub fn from_extension(path: &str) -> Option<Self> {
+        let mut path = Path::new(path);
+        if FileCompression::from_extension(path.to_str()?).is_some() {
+            path = Path::new(path.file_stem()?);
+        }
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "csv" | "tsv" => Some(Format::Csv),
+            "parquet" | "pq" => Some(Format::Parquet),
+            "avro" => Some(Format::Avro),
+            "arrow" | "ipc" | "feather" => Some(Format::Ipc),
+            "arrows" => Some(Format::IpcStream),
+            "json" | "jsonl" | "ndjson" => Some(Format::Json),
+            "xlsx" | "xlsm" | "xls" | "ods" => Some(Format::Excel),
+            _ => None,
+        }
+    }
+
+    /// The binary format whose magic number starts `bytes`. Text formats
+    /// have none, and compressed files are told apart by their extension.
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"PAR1") {
+            Some(Format::Parquet)
+        } else if bytes.starts_with(b"ARROW1") {
+            Some(Format::Ipc)
+        } else if bytes.starts_with(b"Obj\x01") {
+            Some(Format::Avro)
+        } else {
+            None
+        }
+    }
+
+    /// The format of the input: from the magic number in its first bytes,
+    /// read with a range request for remote objects, otherwise from the
+    /// extension. Paths that cannot be read here, such as globs, go by the
+    /// extension alone.
+    pub fn detect_source(path: &str) -> Result<Self> {
+        if path == STDIO_PATH {
+            bail!("Cannot tell the format of stdin; name it, as in `frameblaze json parquet - ...`");
+        }
+        if let Ok(magic) = Storage::new(path).and_then(|storage| storage.read_head(6)) {
+            if let Some(format) = Format::from_magic(&magic) {
+                return Ok(format);
+            }
+        }
+        match Format::from_extension(path) {
+            Some(format) => Ok(format),
+            None => bail!("Cannot tell the format of '{path}'; name it, as in `frameblaze csv parquet {path} ...`"),
+        }
+    }
+
+    /// The format of the output, from its extension.
+    pub fn detect_target(path: &str) -> Result<Self> {
+        if path == STDIO_PATH {
+            bail!("Cannot tell the format to write to stdout; name it, as in `frameblaze csv json INPUT -o -`");
+        }
+        match Format::from_extension(path) {
+            Some(format) => Ok(format),
+            None => bail!("Cannot tell the output format from '{path}'; name it, as in `frameblaze csv parquet INPUT -o {path}`"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(Format::from_extension("data.parquet"), Some(Format::Parquet));
+        assert_eq!(Format::from_extension("s3://bucket/feed.CSV.gz"), Some(Format::Csv));
+        assert_eq!(Format::from_extension("events.jsonl.zst"), Some(Format::Json));
+        assert_eq!(Format::from_extension("events.arrows"), Some(Format::IpcStream));
+        assert_eq!(Format::from_extension("book.xlsx"), Some(Format::Excel));
+        assert_eq!(Format::from_extension("feed.gz"), None);
+        assert_eq!(Format::from_extension("README"), None);
+    }
+
+    #[test]
+    fn test_detect_source_prefers_magic() -> Result<()> {
+        // an Avro file with a misleading extension
+        let mut file = tempfile::Builder::new().suffix(".csv").tempfile()?;
+        file.write_all(b"Obj\x01rest of the header")?;
+        assert_eq!(Format::detect_source(file.path().to_str().unwrap())?, Format::Avro);
+
+        let mut file = tempfile::Builder::new().suffix(".csv").tempfile()?;
+        file.write_all(b"a,b\n1,2\n")?;
+        assert_eq!(Format::detect_source(file.path().to_str().unwrap())?, Format::Csv);
+
+        assert!(Format::detect_source("no-extension").is_err());
+        assert!(Format::detect_target("-").is_err());
+        Ok(())
+    }
+}
@@ -1,19 +1,166 @@
+use crate::avro::{field_dtype, load_schema, parse_record, AvroCodec, Header};
+use crate::compression::{decompressed_source, FileCompression};
 use crate::storage::Storage;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
+use avro_schema::schema::{Field, Record, Schema as AvroSchema};
 use polars::prelude::*;
 use polars_io::avro::AvroReader;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Seek, SeekFrom};
 
+/// Reads Avro object container files.
+///
+/// - `reader_schema`: an `.avsc` file the data is resolved against, so files
+///   written with older or newer versions of a schema read into one shape
+/// - `compression`: compression of the whole file, such as `.avro.gz`,
+///   detected when `None`; this is separate from the codec of its blocks
 #[derive(Default)]
-pub struct AvroReaderImpl;
+pub struct AvroReaderImpl {
+    pub reader_schema: Option<String>,
+    pub compression: Option<FileCompression>,
+}
 
 impl super::FromFile for AvroReaderImpl {
-    fn read_data(&self, path: &str) -> Result<DataFrame> {
+    fn read_data(&self, path: &str) -> Result<LazyFrame> {
         let mut storage = Storage::new(path)?;
-        let file = storage.get_source_file()?;
-        let df = AvroReader::new(file)
-            .finish()?;
-        Ok(df)
+        let mut file = File::open(decompressed_source(path, storage.get_source_path()?, self.compression)?)?;
+        let header = Header::read(&mut BufReader::new(&mut file))?;
+        file.seek(SeekFrom::Start(0))?;
+
+        // Avro has no lazy scan in Polars, so read eagerly and wrap the frame
+        let df = if readable_by_polars(&header) {
+            AvroReader::new(file).finish()?
+        } else {
+            AvroReader::new(Cursor::new(rewrite_for_polars(file)?)).finish()?
+        };
+        let df = apply_logical_types(df, &header.record()?)?;
+        let df = match &self.reader_schema {
+            Some(path) => resolve(df, &load_schema(path)?).with_context(|| format!("Cannot resolve {path} against the schema of the file"))?,
+            None => df,
+        };
+        Ok(df.lazy())
+    }
+}
+
+/// Give columns the Polars type of their field's Avro type where Polars'
+/// reader loses part of it, such as the UTC zone of timestamps.
+fn apply_logical_types(mut df: DataFrame, record: &Record) -> Result<DataFrame> {
+    for field in &record.fields {
+        // lists and structs keep the type Polars reads them as
+        let Ok((dtype, _)) = field_dtype(&field.name, &field.schema) else {
+            continue;
+        };
+        let column = df.column(&field.name)?;
+        if column.dtype() != &dtype {
+            let column = column.cast(&dtype)?;
+            df.with_column(column)?;
+        }
+    }
+    Ok(df)
+}
+
+/// Avro schema resolution: shape the frame, read with the file's writer
+/// schema, into the reader schema's fields. Fields are matched by name or by
+/// one of the reader field's aliases; fields missing from the file take the
+/// reader's default, fields missing from the reader schema are dropped, and
+/// values are promoted to wider types where Avro allows it.
+fn resolve(df: DataFrame, reader_schema: &Value) -> Result<DataFrame> {
+    let record = parse_record(reader_schema)?;
+    let defaults = reader_schema["fields"].as_array().cloned().unwrap_or_default();
+
+    let columns = record
+        .fields
+        .iter()
+        .zip(defaults.iter().map(|field| field.get("default")))
+        .map(|(field, default)| {
+            let (dtype, nullable) = field_dtype(&field.name, &field.schema)?;
+            let column = match writer_column(&df, field) {
+                Some(column) => promote(column, &dtype)
+                    .with_context(|| format!("Field '{}'", field.name))?,
+                None => {
+                    let default = default.ok_or_else(|| {
+                        anyhow!("Field '{}' is not in the file and has no default", field.name)
+                    })?;
+                    default_column(default, &dtype, df.height()).with_context(|| format!("Default of field '{}'", field.name))?
+                }
+            };
+            if !nullable && column.null_count() > 0 {
+                bail!("Field '{}' is not nullable but the file has {} nulls in it", field.name, column.null_count());
+            }
+            Ok(column.with_name(field.name.as_str().into()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(DataFrame::new(columns)?)
+}
+
+/// The file's column for a reader field, found by name or by alias.
+fn writer_column<'a>(df: &'a DataFrame, field: &Field) -> Option<&'a Column> {
+    std::iter::once(&field.name)
+        .chain(&field.aliases)
+        .find_map(|name| df.column(name).ok())
+}
+
+/// Cast a column to the reader's type, if Avro allows promoting it: int to
+/// long, float or double; long to float or double; float to double; and
+/// between string and bytes.
+fn promote(column: &Column, to: &DataType) -> Result<Column> {
+    use DataType::*;
+    let from = column.dtype();
+    let allowed = from == to
+        || matches!(
+            (from, to),
+            (Int32, Int64 | Float32 | Float64) | (Int64, Float32 | Float64) | (Float32, Float64) | (String, Binary) | (Binary, String)
+        );
+    if !allowed {
+        bail!("{from} in the file cannot be read as {to}");
+    }
+    Ok(column.cast(to)?)
+}
+
+/// A column repeating a reader field's JSON default.
+fn default_column(default: &Value, dtype: &DataType, height: usize) -> Result<Column> {
+    let name = PlSmallStr::EMPTY;
+    let value = match default {
+        Value::Null => return Ok(Column::full_null(name, height, dtype)),
+        Value::Bool(b) => Series::new(name, [*b]),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Series::new(name, [i]),
+            None => Series::new(name, [n.as_f64().unwrap_or_default()]),
+        },
+        Value::String(s) => Series::new(name, [s.as_str()]),
+        other => bail!("Unsupported default {other}"),
+    };
+    Ok(value.strict_cast(dtype)?.new_from_index(0, height).into_column())
+}
+
+/// Polars' Avro reader knows neither the zstandard codec nor field defaults
+/// in the schema.
+fn readable_by_polars(header: &Header) -> bool {
+    header.codec != Some(AvroCodec::Zstd) && serde_json::from_value::<AvroSchema>(header.schema.clone()).is_ok()
+}
+
+/// Rewrite the file in memory with a schema Polars can parse, decompressing
+/// zstandard blocks. Blocks in other codecs are copied as they are.
+fn rewrite_for_polars(file: File) -> Result<Vec<u8>> {
+    let mut reader = BufReader::new(file);
+    let header = Header::read(&mut reader)?;
+    let rewritten = Header {
+        schema: serde_json::to_value(AvroSchema::Record(header.record()?))?,
+        codec: header.codec.filter(|codec| *codec != AvroCodec::Zstd),
+        marker: header.marker,
+    };
+
+    let mut out = Vec::new();
+    rewritten.write(&mut out)?;
+    while let Some((rows, data)) = header.read_block(&mut reader)? {
+        let data = match header.codec {
+            Some(AvroCodec::Zstd) => zstd::decode_all(data.as_slice())?,
+            _ => data,
+        };
+        rewritten.write_block(&mut out, rows, &data)?;
     }
+    Ok(out)
 }
 
 #[cfg(test)]
@@ -22,6 +169,7 @@ mod tests {
     use crate::from::FromFile;
     use crate::test_utils::helpers::*;
     use polars_io::avro::AvroWriter;
+    use std::io::Write;
     use tempfile::NamedTempFile;
 
     fn reader() -> AvroReaderImpl {
@@ -38,6 +186,16 @@ mod tests {
         test_write_then_read(writer_fn, reader).unwrap();
     }
 
+    #[test]
+    fn test_scan_valid_df() {
+        let writer_fn = |temp_file: &mut NamedTempFile, df: &DataFrame| -> Result<()> {
+            AvroWriter::new(temp_file).finish(&mut df.clone())?;
+            Ok(())
+        };
+        let reader = Box::new(reader());
+        test_write_then_scan(writer_fn, reader).unwrap();
+    }
+
     #[test]
     fn test_missing_source_file() {
         let result = reader().read_data("non_existent_file");
@@ -50,4 +208,85 @@ mod tests {
         let result = reader().read_data(temp_file.unwrap().path().to_str().unwrap());
         assert!(result.is_err());
     }
+
+    fn write_avro(df: &DataFrame) -> Result<NamedTempFile> {
+        let mut temp_file = create_temp_file()?;
+        AvroWriter::new(&mut temp_file).finish(&mut df.clone())?;
+        Ok(temp_file)
+    }
+
+    fn reader_with(schema: &Value) -> Result<(AvroReaderImpl, NamedTempFile)> {
+        let mut schema_file = create_temp_file()?;
+        schema_file.write_all(schema.to_string().as_bytes())?;
+        let reader = AvroReaderImpl {
+            reader_schema: Some(schema_file.path().to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        Ok((reader, schema_file))
+    }
+
+    fn customer_v2() -> Value {
+        serde_json::json!({
+            "type": "record",
+            "name": "Customer",
+            "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "full_name", "type": ["null", "string"], "aliases": ["name"]},
+                {"name": "email", "type": ["null", "string"], "default": null},
+                {"name": "score", "type": "double", "default": 1.5},
+                {"name": "country", "type": "string", "default": "NL"}
+            ]
+        })
+    }
+
+    #[test]
+    fn test_reader_schema_resolution() -> Result<()> {
+        let v1 = df! {
+            "id" => &[1i32, 2],
+            "name" => &["Alice", "Bob"],
+            "legacy" => &["x", "y"]
+        }?;
+        let v2 = df! {
+            "id" => &[3i64],
+            "full_name" => &["Carol"],
+            "email" => &["carol@example.com"],
+            "score" => &[9.0f64],
+            "country" => &["BE"]
+        }?;
+        let (reader, _schema_file) = reader_with(&customer_v2())?;
+
+        let old = reader.read_data(write_avro(&v1)?.path().to_str().unwrap())?.collect()?;
+        let new = reader.read_data(write_avro(&v2)?.path().to_str().unwrap())?.collect()?;
+        assert_eq!(old.schema(), new.schema());
+
+        let expected = df! {
+            "id" => &[1i64, 2],
+            "full_name" => &["Alice", "Bob"],
+            "email" => &[None::<&str>, None],
+            "score" => &[1.5f64, 1.5],
+            "country" => &["NL", "NL"]
+        }?;
+        assert!(old.equals_missing(&expected));
+        assert!(new.equals_missing(&v2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reader_schema_mismatches() -> Result<()> {
+        let file = write_avro(&df! { "id" => &["a"], "name" => &[None::<&str>] }?)?;
+        let path = file.path().to_str().unwrap();
+        let read_as = |fields: Value| -> Result<DataFrame> {
+            let schema = serde_json::json!({"type": "record", "name": "R", "fields": fields});
+            let (reader, _schema_file) = reader_with(&schema)?;
+            reader.read_data(path)?.collect().map_err(Into::into)
+        };
+
+        let no_default = read_as(serde_json::json!([{"name": "age", "type": "int"}])).unwrap_err();
+        assert!(format!("{no_default:#}").contains("has no default"));
+        let not_promotable = read_as(serde_json::json!([{"name": "id", "type": "long"}])).unwrap_err();
+        assert!(format!("{not_promotable:#}").contains("cannot be read as i64"));
+        let not_nullable = read_as(serde_json::json!([{"name": "name", "type": "string"}])).unwrap_err();
+        assert!(format!("{not_nullable:#}").contains("not nullable"));
+        Ok(())
+    }
 }
@@ -1,20 +1,126 @@
-use crate::storage::Storage;
+use crate::compression::{decompressed_source, FileCompression};
+use crate::schema::TableSchema;
+use crate::storage::{retain_spool, Storage};
 use anyhow::Result;
 use polars::prelude::*;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
 
-#[derive(Default)]
-pub struct CsvReaderImpl;
+/// CSV dialect and schema inference settings.
+///
+/// - `escape_char`: Polars only understands doubled quotes, so a file using a
+///   backslash style escape is first rewritten to standard CSV
+/// - `infer_schema_rows`: rows used to infer dtypes, `None` for the whole file
+/// - `all_strings`: skip inference and read every column as a string
+/// - `compression`: compression of the file, detected when `None`
+/// - `schema`: declared column types, applied instead of inference
+pub struct CsvReaderImpl {
+    pub delimiter: u8,
+    pub has_header: bool,
+    pub quote_char: Option<u8>,
+    pub escape_char: Option<u8>,
+    pub comment_prefix: Option<String>,
+    pub skip_rows: usize,
+    pub null_values: Vec<String>,
+    pub infer_schema_rows: Option<usize>,
+    pub all_strings: bool,
+    pub compression: Option<FileCompression>,
+    pub schema: Option<TableSchema>,
+}
+
+impl Default for CsvReaderImpl {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_header: true,
+            quote_char: Some(b'"'),
+            escape_char: None,
+            comment_prefix: None,
+            skip_rows: 0,
+            null_values: Vec::new(),
+            infer_schema_rows: Some(10000),
+            all_strings: false,
+            compression: None,
+            schema: None,
+        }
+    }
+}
 
 impl super::FromFile for CsvReaderImpl {
-    fn read_data(&self, path: &str) -> Result<DataFrame> {
+    fn read_data(&self, path: &str) -> Result<LazyFrame> {
         let mut storage = Storage::new(path)?;
-        let file = storage.get_source_file()?;
+        let mut source = decompressed_source(path, storage.get_source_path()?, self.compression)?;
+        let mut quote_char = self.quote_char;
+        if let Some(escape) = self.escape_char {
+            source = self.unescape(&source, escape)?;
+            quote_char = Some(b'"');
+        }
 
-        let df = CsvReadOptions::default()
-            .with_infer_schema_length(Some(10000))
-            .into_reader_with_file_handle(file)
+        let infer_schema_length = if self.all_strings {
+            // zero inference rows makes every column a string
+            Some(0)
+        } else {
+            self.infer_schema_rows
+        };
+        let null_values = (!self.null_values.is_empty()).then(|| {
+            NullValues::AllColumns(self.null_values.iter().map(|v| v.as_str().into()).collect())
+        });
+
+        let mut lf = LazyCsvReader::new(source)
+            .with_separator(self.delimiter)
+            .with_has_header(self.has_header)
+            .with_quote_char(quote_char)
+            .with_comment_prefix(self.comment_prefix.as_deref().map(Into::into))
+            .with_skip_rows(self.skip_rows)
+            .with_null_values(null_values)
+            .with_infer_schema_length(infer_schema_length)
+            .with_dtype_overwrite(self.schema.as_ref().map(|s| Arc::new(s.text_overrides(!self.has_header))))
             .finish()?;
-        Ok(df)
+        if let Some(schema) = &self.schema {
+            lf = schema.apply(lf, !self.has_header)?;
+        }
+        // resolve the schema up front so unreadable input fails here
+        lf.collect_schema()?;
+        Ok(lf)
+    }
+}
+
+impl CsvReaderImpl {
+    /// Rewrite a file that escapes quotes with `escape` into standard CSV
+    /// (doubled `"` quotes) in a spool, returning the spool path.
+    fn unescape(&self, source: &Path, escape: u8) -> Result<PathBuf> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote_char.unwrap_or(b'"'))
+            .quoting(self.quote_char.is_some())
+            .escape(Some(escape))
+            .double_quote(false)
+            .comment(self.comment_prefix.as_deref().and_then(single_byte))
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(File::open(source)?);
+
+        let spool = NamedTempFile::new()?;
+        {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(self.delimiter)
+                .flexible(true)
+                .from_writer(BufWriter::new(spool.as_file()));
+            for record in reader.byte_records() {
+                writer.write_byte_record(&record?)?;
+            }
+            writer.flush()?;
+        }
+        retain_spool(spool)
+    }
+}
+
+fn single_byte(s: &str) -> Option<u8> {
+    match s.as_bytes() {
+        [b] => Some(*b),
+        _ => None,
     }
 }
 
@@ -30,6 +136,12 @@ mod tests {
         CsvReaderImpl::default()
     }
 
+    fn read_str(reader: CsvReaderImpl, contents: &str) -> Result<DataFrame> {
+        let mut temp_file = NamedTempFile::new()?;
+        write!(temp_file, "{contents}")?;
+        Ok(reader.read_data(temp_file.path().to_str().unwrap())?.collect()?)
+    }
+
     #[test]
     fn test_read_valid_df() {
         let writer_fn = |temp_file: &mut NamedTempFile, df: &DataFrame| -> Result<()> {
@@ -40,6 +152,16 @@ mod tests {
         test_write_then_read(writer_fn, reader).unwrap();
     }
 
+    #[test]
+    fn test_scan_valid_df() {
+        let writer_fn = |temp_file: &mut NamedTempFile, df: &DataFrame| -> Result<()> {
+            CsvWriter::new(temp_file).finish(&mut df.clone())?;
+            Ok(())
+        };
+        let reader = Box::new(reader());
+        test_write_then_scan(writer_fn, reader).unwrap();
+    }
+
     #[test]
     fn test_missing_source_file() {
         let result = reader().read_data("non_existent_file");
@@ -47,6 +169,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::nonminimal_bool, reason = "reads as the opposite of test_missing_source_file")]
     fn test_read_malformed_file() {
         let temp_file = create_malformed_file();
         let result = reader().read_data(temp_file.unwrap().path().to_str().unwrap());
@@ -57,8 +180,138 @@ mod tests {
     fn test_read_empty_csv() {
         let mut temp_file = NamedTempFile::new().unwrap();
         writeln!(temp_file, "name,age,city").unwrap();
-        let df = reader().read_data(temp_file.path().to_str().unwrap()).unwrap();
+        let df = reader().read_data(temp_file.path().to_str().unwrap()).unwrap().collect().unwrap();
         assert_eq!(df.shape(), (0, 3));
         assert_eq!(df.get_column_names(), &["name", "age", "city"]);
     }
+
+    #[test]
+    fn test_read_headerless_tsv() -> Result<()> {
+        let reader = CsvReaderImpl {
+            delimiter: b'\t',
+            has_header: false,
+            ..reader()
+        };
+        let df = read_str(reader, "Alice\t30\nBob\t25\n")?;
+        assert_eq!(df.get_column_names(), vec!["column_1", "column_2"]);
+        assert_eq!(df.column("column_2")?.i64()?.get(1), Some(25));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_custom_quote_and_escape() -> Result<()> {
+        let reader = CsvReaderImpl {
+            delimiter: b'|',
+            quote_char: Some(b'\''),
+            escape_char: Some(b'\\'),
+            ..reader()
+        };
+        let df = read_str(reader, "name|quote\nAlice|'it\\'s|here'\n")?;
+        assert_eq!(df.column("quote")?.str()?.get(0), Some("it's|here"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_comments_skip_rows_and_nulls() -> Result<()> {
+        let reader = CsvReaderImpl {
+            comment_prefix: Some("#".to_string()),
+            skip_rows: 1,
+            null_values: vec!["NA".to_string(), "\\N".to_string()],
+            ..reader()
+        };
+        let df = read_str(reader, "exported 2024-01-01\nname,age\n# a comment\nAlice,NA\nBob,\\N\nCarol,41\n")?;
+        assert_eq!(df.shape(), (3, 2));
+        assert_eq!(df.column("age")?.dtype(), &DataType::Int64);
+        assert_eq!(df.column("age")?.null_count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_all_strings_and_full_inference() -> Result<()> {
+        // the float only shows up after the first inference row
+        let contents = "zip,amount\n01234,1\n02345,2.5\n";
+
+        let strings = read_str(CsvReaderImpl { all_strings: true, ..reader() }, contents)?;
+        assert_eq!(strings.column("zip")?.str()?.get(0), Some("01234"));
+        assert_eq!(strings.column("amount")?.dtype(), &DataType::String);
+
+        let full = read_str(CsvReaderImpl { infer_schema_rows: None, ..reader() }, contents)?;
+        assert_eq!(full.column("amount")?.dtype(), &DataType::Float64);
+
+        let short = CsvReaderImpl { infer_schema_rows: Some(1), ..reader() };
+        assert!(read_str(short, contents).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_gzip_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("feed.csv.gz");
+        let mut encoder = flate2::write::GzEncoder::new(File::create(&path)?, flate2::Compression::default());
+        encoder.write_all(b"name,age\nAlice,30\nBob,25\n")?;
+        encoder.finish()?;
+
+        let df = reader().read_data(path.to_str().unwrap())?.collect()?;
+        assert_eq!(df.shape(), (2, 2));
+        assert_eq!(df.column("age")?.i64()?.get(1), Some(25));
+        Ok(())
+    }
+
+    fn declared(columns: &[(&str, DataType, Option<&str>)]) -> TableSchema {
+        TableSchema {
+            columns: columns
+                .iter()
+                .map(|(name, dtype, format)| crate::schema::ColumnSchema {
+                    name: name.to_string(),
+                    dtype: dtype.clone(),
+                    nullable: true,
+                    format: format.map(str::to_string),
+                })
+                .collect(),
+            source: "schema.toml".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_read_with_schema() -> Result<()> {
+        let schema = declared(&[
+            ("zip", DataType::String, None),
+            ("id", DataType::Int64, None),
+            ("ordered", DataType::Date, Some("%d/%m/%Y")),
+            ("price", DataType::Decimal(Some(10), Some(2)), None),
+        ]);
+        let contents = "zip,id,ordered,price,note
+01234,1,31/12/2024,9.99,a
+02345,2,01/01/2025,10.5,b
+";
+        let df = read_str(CsvReaderImpl { schema: Some(schema.clone()), ..reader() }, contents)?;
+        assert_eq!(df.column("zip")?.str()?.get(0), Some("01234"));
+        assert_eq!(df.column("id")?.dtype(), &DataType::Int64);
+        assert_eq!(df.column("ordered")?.dtype(), &DataType::Date);
+        assert_eq!(df.column("price")?.dtype(), &DataType::Decimal(Some(10), Some(2)));
+        // undeclared columns are inferred as before
+        assert_eq!(df.column("note")?.dtype(), &DataType::String);
+
+        let bad = "zip,id,ordered,price
+01234,1.5,31/12/2024,9.99
+";
+        let err = read_str(CsvReaderImpl { schema: Some(schema), ..reader() }, bad).unwrap_err();
+        assert!(err.to_string().contains("Column 'id' does not fit i64"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_names_headerless_columns() -> Result<()> {
+        let schema = declared(&[("zip", DataType::String, None), ("count", DataType::Int32, None)]);
+        let reader = CsvReaderImpl {
+            has_header: false,
+            schema: Some(schema),
+            ..reader()
+        };
+        let df = read_str(reader, "01234,7,x
+")?;
+        assert_eq!(df.get_column_names(), ["zip", "count", "column_3"]);
+        assert_eq!(df.column("count")?.i32()?.get(0), Some(7));
+        Ok(())
+    }
 }
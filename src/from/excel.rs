@@ -0,0 +1,476 @@
+use crate::storage::Storage;
+use anyhow::{anyhow, bail, Result};
+use calamine::{open_workbook_auto_from_rs, Data, Range, Reader};
+use polars::prelude::*;
+use std::io::{Cursor, Read};
+
+/// Reads `.xlsx`, `.xlsm`, `.xls` and `.ods` workbooks through calamine.
+///
+/// - `sheet`: sheet name, or 1-based position when no sheet has that name
+/// - `header_row`: 1-based row (within `cell_range`, if set) holding the
+///   column names; rows above it are skipped and `0` means there is no header
+/// - `cell_range`: an `A1:D100` style block to read instead of the used area
+/// - `all_sheets`: read every sheet and stack them by column name, recording
+///   the sheet in `sheet_column`
+/// - `whole_numbers_as_integers`: read number columns without fractions as
+///   integers; Excel stores every number as a float, so by default only
+///   cells the workbook types as integers are read as integers
+pub struct ExcelReaderImpl {
+    pub sheet: Option<String>,
+    pub header_row: usize,
+    pub cell_range: Option<String>,
+    pub all_sheets: bool,
+    pub sheet_column: String,
+    pub whole_numbers_as_integers: bool,
+}
+
+impl Default for ExcelReaderImpl {
+    fn default() -> Self {
+        Self {
+            sheet: None,
+            header_row: 1,
+            cell_range: None,
+            all_sheets: false,
+            sheet_column: "sheet".to_string(),
+            whole_numbers_as_integers: false,
+        }
+    }
+}
+
+impl super::FromFile for ExcelReaderImpl {
+    fn read_data(&self, path: &str) -> Result<LazyFrame> {
+        let mut storage = Storage::new(path)?;
+        let mut bytes = Vec::new();
+        storage.get_source_file()?.read_to_end(&mut bytes)?;
+        // sniff the workbook type from its content, spools have no extension
+        let mut workbook = open_workbook_auto_from_rs(Cursor::new(bytes))?;
+        let sheet_names = workbook.sheet_names();
+
+        if self.all_sheets {
+            let mut frames = Vec::with_capacity(sheet_names.len());
+            for name in &sheet_names {
+                let range = workbook.worksheet_range(name)?;
+                let mut df = self.range_to_df(&range)?;
+                let sheet = Column::new(
+                    self.sheet_column.as_str().into(),
+                    vec![name.as_str(); df.height()],
+                );
+                df.insert_column(0, sheet)?;
+                frames.push(df);
+            }
+            if frames.is_empty() {
+                bail!("Workbook {path} has no sheets");
+            }
+            return Ok(polars::functions::concat_df_diagonal(&frames)?.lazy());
+        }
+
+        let name = self.resolve_sheet(&sheet_names)?;
+        let range = workbook.worksheet_range(&name)?;
+        Ok(self.range_to_df(&range)?.lazy())
+    }
+}
+
+/// Every sheet of a workbook, named by its first row, for writers that
+/// rewrite a workbook with rows added.
+pub(crate) fn read_workbook_sheets(bytes: Vec<u8>) -> Result<Vec<(String, DataFrame)>> {
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(bytes))?;
+    let reader = ExcelReaderImpl {
+        whole_numbers_as_integers: true,
+        ..ExcelReaderImpl::default()
+    };
+    let mut sheets = Vec::new();
+    for name in workbook.sheet_names() {
+        let range = workbook.worksheet_range(&name)?;
+        let df = reader.range_to_df(&range)?;
+        sheets.push((name, df));
+    }
+    Ok(sheets)
+}
+
+impl ExcelReaderImpl {
+    /// Pick the sheet to read: an exact name match wins, then a 1-based index.
+    fn resolve_sheet(&self, sheet_names: &[String]) -> Result<String> {
+        let Some(wanted) = &self.sheet else {
+            return sheet_names
+                .first()
+                .cloned()
+                .ok_or_else(|| anyhow!("Workbook has no sheets"));
+        };
+        if sheet_names.iter().any(|n| n == wanted) {
+            return Ok(wanted.clone());
+        }
+        match wanted.parse::<usize>() {
+            Ok(i) if i >= 1 && i <= sheet_names.len() => Ok(sheet_names[i - 1].clone()),
+            _ => bail!(
+                "Sheet '{wanted}' not found; available sheets: {}",
+                sheet_names.join(", ")
+            ),
+        }
+    }
+
+    fn range_to_df(&self, range: &Range<Data>) -> Result<DataFrame> {
+        let range = match &self.cell_range {
+            Some(spec) => {
+                let (start, end) = parse_cell_range(spec)?;
+                range.range(start, end)
+            }
+            None => range.clone(),
+        };
+
+        let rows: Vec<&[Data]> = range.rows().collect();
+        let width = rows.first().map_or(0, |r| r.len());
+        let (names, data_rows) = if self.header_row == 0 {
+            let names = (1..=width).map(|i| format!("column_{i}")).collect();
+            (names, &rows[..])
+        } else if self.header_row <= rows.len() {
+            let header = rows[self.header_row - 1];
+            (header_names(header), &rows[self.header_row..])
+        } else {
+            // the header is past the end of the sheet, so there is nothing to read
+            (Vec::new(), &rows[..0])
+        };
+
+        let columns = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let cells: Vec<&Data> = data_rows.iter().map(|r| &r[i]).collect();
+                cells_to_column(name, &cells, self.whole_numbers_as_integers)
+            })
+            .collect::<Vec<_>>();
+        Ok(DataFrame::new(columns)?)
+    }
+}
+
+/// Column names from a header row. Blank cells get positional names and
+/// repeats get a suffix, mirroring what the CSV reader does.
+fn header_names(header: &[Data]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::with_capacity(header.len());
+    for (i, cell) in header.iter().enumerate() {
+        let mut name = match cell {
+            Data::Empty => format!("column_{}", i + 1),
+            other => other.to_string(),
+        };
+        let base = name.clone();
+        let mut n = 1;
+        while names.contains(&name) {
+            name = format!("{base}_duplicated_{n}");
+            n += 1;
+        }
+        names.push(name);
+    }
+    names
+}
+
+/// Build a typed column: booleans, integers, floats, dates and datetimes are
+/// kept when every non-empty cell agrees, anything mixed becomes a string.
+/// Float cells only count as integers with `whole_numbers_as_integers`.
+/// Empty and error cells are null.
+fn cells_to_column(name: &str, cells: &[&Data], whole_numbers_as_integers: bool) -> Column {
+    let name: PlSmallStr = name.into();
+    let present: Vec<&Data> = cells
+        .iter()
+        .copied()
+        .filter(|c| !matches!(c, Data::Empty | Data::Error(_)))
+        .collect();
+
+    let all_bool = present.iter().all(|c| matches!(c, Data::Bool(_)));
+    let all_numeric = present
+        .iter()
+        .all(|c| matches!(c, Data::Int(_) | Data::Float(_)));
+    let all_datetime = present.iter().all(|c| matches!(c, Data::DateTime(_)));
+
+    if present.is_empty() {
+        return Column::full_null(name, cells.len(), &DataType::String);
+    }
+
+    if all_bool {
+        let values: Vec<Option<bool>> = cells
+            .iter()
+            .map(|c| match c {
+                Data::Bool(b) => Some(*b),
+                _ => None,
+            })
+            .collect();
+        return Column::new(name, values);
+    }
+
+    if all_numeric {
+        let integral = present.iter().all(|c| match c {
+            Data::Int(_) => true,
+            Data::Float(f) => whole_numbers_as_integers && f.fract() == 0.0 && f.abs() < i64::MAX as f64,
+            _ => false,
+        });
+        if integral {
+            let values: Vec<Option<i64>> = cells
+                .iter()
+                .map(|c| match c {
+                    Data::Int(i) => Some(*i),
+                    Data::Float(f) => Some(*f as i64),
+                    _ => None,
+                })
+                .collect();
+            return Column::new(name, values);
+        }
+        let values: Vec<Option<f64>> = cells
+            .iter()
+            .map(|c| match c {
+                Data::Int(i) => Some(*i as f64),
+                Data::Float(f) => Some(*f),
+                _ => None,
+            })
+            .collect();
+        return Column::new(name, values);
+    }
+
+    if all_datetime {
+        let values: Vec<Option<chrono::NaiveDateTime>> = cells
+            .iter()
+            .map(|c| match c {
+                Data::DateTime(dt) => dt.as_datetime(),
+                _ => None,
+            })
+            .collect();
+        let date_only = values
+            .iter()
+            .flatten()
+            .all(|dt| dt.time() == chrono::NaiveTime::MIN);
+        if date_only {
+            let epoch = chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+            let days: Vec<Option<i32>> = values
+                .iter()
+                .map(|v| v.map(|dt| (dt.date() - epoch).num_days() as i32))
+                .collect();
+            return Int32Chunked::new(name, days).into_date().into_column();
+        }
+        let millis: Vec<Option<i64>> = values
+            .iter()
+            .map(|v| v.map(|dt| dt.and_utc().timestamp_millis()))
+            .collect();
+        return Int64Chunked::new(name, millis)
+            .into_datetime(TimeUnit::Milliseconds, None)
+            .into_column();
+    }
+
+    let values: Vec<Option<String>> = cells
+        .iter()
+        .map(|c| match c {
+            Data::Empty | Data::Error(_) => None,
+            Data::DateTime(dt) => dt.as_datetime().map(|d| d.to_string()),
+            other => Some(other.to_string()),
+        })
+        .collect();
+    Column::new(name, values)
+}
+
+/// Parse an `A1:D100` range into 0-based `(row, column)` start and end cells.
+fn parse_cell_range(spec: &str) -> Result<((u32, u32), (u32, u32))> {
+    let (start, end) = spec
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid cell range '{spec}', expected e.g. A1:D100"))?;
+    let start = parse_cell_ref(start)?;
+    let end = parse_cell_ref(end)?;
+    if start.0 > end.0 || start.1 > end.1 {
+        bail!("Invalid cell range '{spec}': start is after end");
+    }
+    Ok((start, end))
+}
+
+/// The number of columns in a worksheet, up to column `XFD`.
+const MAX_COLUMNS: u32 = 16_384;
+
+fn parse_cell_ref(cell: &str) -> Result<(u32, u32)> {
+    let cell = cell.trim().to_ascii_uppercase();
+    let split = cell
+        .find(|c: char| c.is_ascii_digit())
+        .ok_or_else(|| anyhow!("Invalid cell reference '{cell}'"))?;
+    let (letters, digits) = cell.split_at(split);
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_uppercase()) {
+        bail!("Invalid cell reference '{cell}'");
+    }
+    let col = letters
+        .chars()
+        .try_fold(0u32, |acc, c| acc.checked_mul(26)?.checked_add(c as u32 - 'A' as u32 + 1))
+        .filter(|&col| col <= MAX_COLUMNS)
+        .ok_or_else(|| anyhow!("Invalid cell reference '{cell}': columns end at XFD"))?
+        - 1;
+    let row: u32 = digits
+        .parse()
+        .map_err(|_| anyhow!("Invalid cell reference '{cell}'"))?;
+    if row == 0 {
+        bail!("Invalid cell reference '{cell}': rows start at 1");
+    }
+    Ok((row - 1, col))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from::FromFile;
+    use crate::test_utils::helpers::*;
+    use rust_xlsxwriter::Workbook;
+    use tempfile::NamedTempFile;
+
+    fn reader() -> ExcelReaderImpl {
+        ExcelReaderImpl::default()
+    }
+
+    /// Two sheets: "people" holds the sample frame below a title row,
+    /// "more" holds one extra person.
+    fn create_sample_workbook() -> Result<NamedTempFile> {
+        let temp_file = NamedTempFile::new()?;
+        let mut workbook = Workbook::new();
+
+        let people = workbook.add_worksheet().set_name("people")?;
+        people.write(0, 0, "Staff list")?;
+        for (col, header) in ["name", "age", "city"].iter().enumerate() {
+            people.write(1, col as u16, *header)?;
+        }
+        for (row, (name, age, city)) in [("Alice", 30, "New York"), ("Bob", 25, "Los Angeles")]
+            .iter()
+            .enumerate()
+        {
+            let row = row as u32 + 2;
+            people.write(row, 0, *name)?;
+            people.write(row, 1, *age)?;
+            people.write(row, 2, *city)?;
+        }
+
+        let more = workbook.add_worksheet().set_name("more")?;
+        more.write(0, 0, "name")?;
+        more.write(0, 1, "age")?;
+        more.write(1, 0, "Carol")?;
+        more.write(1, 1, 41)?;
+
+        workbook.save(temp_file.path())?;
+        Ok(temp_file)
+    }
+
+    #[test]
+    fn test_read_sheet_with_header_row() -> Result<()> {
+        let temp_file = create_sample_workbook()?;
+        let reader = ExcelReaderImpl {
+            header_row: 2,
+            ..reader()
+        };
+        let df = reader.read_data(temp_file.path().to_str().unwrap())?.collect()?;
+        // Excel stores every number as a float
+        let expected = create_sample_df()?.lazy().with_column(col("age").cast(DataType::Float64)).collect()?;
+        assert_dataframes_equal(&expected, &df);
+        assert_eq!(df.column("age")?.dtype(), &DataType::Float64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_whole_numbers_as_integers() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet();
+        sheet.write(0, 0, "qty")?;
+        sheet.write(0, 1, "price")?;
+        for (row, (qty, price)) in [(1.0, 10.0), (2.0, 20.5)].iter().enumerate() {
+            sheet.write(row as u32 + 1, 0, *qty)?;
+            sheet.write(row as u32 + 1, 1, *price)?;
+        }
+        workbook.save(temp_file.path())?;
+        let path = temp_file.path().to_str().unwrap();
+
+        let df = reader().read_data(path)?.collect()?;
+        assert_eq!(df.column("qty")?.dtype(), &DataType::Float64);
+
+        let reader = ExcelReaderImpl {
+            whole_numbers_as_integers: true,
+            ..reader()
+        };
+        let df = reader.read_data(path)?.collect()?;
+        assert_eq!(df.column("qty")?.i64()?.into_no_null_iter().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(df.column("price")?.dtype(), &DataType::Float64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_sheet_by_name_and_index() -> Result<()> {
+        let temp_file = create_sample_workbook()?;
+        let path = temp_file.path().to_str().unwrap();
+        for sheet in ["more", "2"] {
+            let reader = ExcelReaderImpl {
+                sheet: Some(sheet.to_string()),
+                ..reader()
+            };
+            let df = reader.read_data(path)?.collect()?;
+            assert_eq!(df.shape(), (1, 2));
+            assert_eq!(df.column("name")?.str()?.get(0), Some("Carol"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_cell_range_without_header() -> Result<()> {
+        let temp_file = create_sample_workbook()?;
+        let reader = ExcelReaderImpl {
+            cell_range: Some("A3:B4".to_string()),
+            header_row: 0,
+            ..reader()
+        };
+        let df = reader.read_data(temp_file.path().to_str().unwrap())?.collect()?;
+        assert_eq!(df.get_column_names(), vec!["column_1", "column_2"]);
+        assert_eq!(df.column("column_2")?.f64()?.get(1), Some(25.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_all_sheets() -> Result<()> {
+        let temp_file = create_sample_workbook()?;
+        let reader = ExcelReaderImpl {
+            all_sheets: true,
+            header_row: 0,
+            ..reader()
+        };
+        let df = reader.read_data(temp_file.path().to_str().unwrap())?.collect()?;
+        // "more" is narrower, so its missing column is null
+        assert_eq!(
+            df.get_column_names(),
+            vec!["sheet", "column_1", "column_2", "column_3"]
+        );
+        // 4 used rows from "people" and 2 from "more"
+        assert_eq!(df.height(), 6);
+        assert_eq!(df.column("sheet")?.str()?.get(5), Some("more"));
+        assert_eq!(df.column("column_3")?.null_count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_sheet() -> Result<()> {
+        let temp_file = create_sample_workbook()?;
+        let reader = ExcelReaderImpl {
+            sheet: Some("nope".to_string()),
+            ..reader()
+        };
+        assert!(reader.read_data(temp_file.path().to_str().unwrap()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_source_file() {
+        let result = reader().read_data("non_existent_file");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_read_malformed_file() {
+        let temp_file = create_malformed_file();
+        let result = reader().read_data(temp_file.unwrap().path().to_str().unwrap());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_cell_range() {
+        assert_eq!(parse_cell_range("B2:AA10").unwrap(), ((1, 1), (9, 26)));
+        assert!(parse_cell_range("B2").is_err());
+        assert!(parse_cell_range("C3:A1").is_err());
+        assert!(parse_cell_range("A0:B2").is_err());
+        assert_eq!(parse_cell_range("A1:XFD2").unwrap(), ((0, 0), (1, 16_383)));
+        assert!(parse_cell_range("A1:XFE2").is_err());
+        assert!(parse_cell_range("A1:AAAAAAAA1").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Hive-style partitioned input: files under `key=value` directories, as in
+//! `sales/year=2024/region=EU/part-00000.parquet`, read with the partition
+//! keys as typed columns.
+
+use crate::to::partition::HIVE_DEFAULT_PARTITION;
+use crate::transform::row_filter::RowFilter;
+use crate::transform::Transform;
+use anyhow::{bail, Result};
+use chrono::NaiveDate;
+use polars::prelude::*;
+
+const FILE_INDEX_COLUMN: &str = "__file";
+
+/// The directory an input path lists from: the path itself for a directory,
+/// or the directory before the first wildcard of a glob.
+pub fn dataset_root(input: &str) -> &str {
+    match input.find(['*', '?', '[']) {
+        Some(wildcard) => &input[..input[..wildcard].rfind('/').map_or(0, |i| i + 1)],
+        None => input,
+    }
+}
+
+/// The partition keys of every file below `root`, one row per file, typed as
+/// integers, floats, booleans or dates where every value parses as one, and
+/// strings otherwise. `None` if the files are not under `key=value`
+/// directories.
+pub fn partitions(root: &str, files: &[String]) -> Result<Option<DataFrame>> {
+    let parsed: Vec<Vec<(String, Option<String>)>> = files.iter().map(|file| partition_values(root, file)).collect();
+    let Some(first) = parsed.first().filter(|keys| !keys.is_empty()) else {
+        return Ok(None);
+    };
+    let names: Vec<&String> = first.iter().map(|(name, _)| name).collect();
+    for (file, keys) in files.iter().zip(&parsed) {
+        if keys.iter().map(|(name, _)| name).ne(names.iter().copied()) {
+            bail!("{file} is not partitioned like {}: expected the directories {}", files[0], names.iter().map(|n| format!("{n}=...")).collect::<Vec<_>>().join("/"));
+        }
+    }
+
+    let mut columns = Vec::with_capacity(names.len());
+    for (i, name) in names.iter().enumerate() {
+        let values: Vec<Option<&str>> = parsed.iter().map(|keys| keys[i].1.as_deref()).collect();
+        columns.push(typed_column(name, &values)?);
+    }
+    Ok(Some(DataFrame::new(columns)?))
+}
+
+/// Which files can hold rows that pass `filters`, judging by the filters on
+/// partition columns alone. Filters on other columns keep every file.
+pub fn prune(partitions: &DataFrame, filters: &[RowFilter]) -> Result<Vec<bool>> {
+    let mut lf = partitions.clone().lazy().with_row_index(FILE_INDEX_COLUMN, None);
+    for filter in filters.iter().filter(|f| partitions.get_column_index(&f.column).is_some()) {
+        lf = filter.transform(lf)?;
+    }
+    let kept = lf.select([col(FILE_INDEX_COLUMN)]).collect()?;
+    let mut keep = vec![false; partitions.height()];
+    for index in kept.column(FILE_INDEX_COLUMN)?.idx()?.into_no_null_iter() {
+        keep[index as usize] = true;
+    }
+    Ok(keep)
+}
+
+/// The partition values of row `row` as literal columns to add to a file.
+pub fn partition_columns(partitions: &DataFrame, row: usize) -> Result<Vec<Expr>> {
+    partitions
+        .get_columns()
+        .iter()
+        .map(|column| {
+            let value = Scalar::new(column.dtype().clone(), column.get(row)?.into_static());
+            Ok(lit(value).alias(column.name().clone()))
+        })
+        .collect()
+}
+
+/// The `key=value` directories of `file` below `root`, unescaped. Null and
+/// empty values are Hive's default partition.
+fn partition_values(root: &str, file: &str) -> Vec<(String, Option<String>)> {
+    let relative = file.strip_prefix(root).unwrap_or(file).replace('\\', "/");
+    let mut dirs: Vec<&str> = relative.split('/').filter(|s| !s.is_empty()).collect();
+    dirs.pop();
+    dirs.into_iter()
+        .filter_map(|dir| dir.split_once('='))
+        .map(|(name, value)| {
+            let value = (value != HIVE_DEFAULT_PARTITION && !value.is_empty()).then(|| unescape_path_name(value));
+            (unescape_path_name(name), value)
+        })
+        .collect()
+}
+
+/// Integers are only inferred when they print back as the same text, and
+/// floats when they have no leading zero, so that `month=01` stays text.
+fn typed_column(name: &str, values: &[Option<&str>]) -> Result<Column> {
+    let present = || values.iter().flatten();
+    let column = if present().all(|v| v.parse::<i64>().is_ok_and(|n| n.to_string() == *v)) {
+        Column::new(name.into(), values.iter().map(|v| v.map(|v| v.parse::<i64>().unwrap())).collect::<Vec<_>>())
+    } else if present().all(|v| v.parse::<f64>().is_ok() && !has_leading_zero(v)) {
+        Column::new(name.into(), values.iter().map(|v| v.map(|v| v.parse::<f64>().unwrap())).collect::<Vec<_>>())
+    } else if present().all(|v| v.parse::<bool>().is_ok()) {
+        Column::new(name.into(), values.iter().map(|v| v.map(|v| v.parse::<bool>().unwrap())).collect::<Vec<_>>())
+    } else if present().all(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").is_ok()) {
+        Column::new(name.into(), values.to_vec()).cast(&DataType::Date)?
+    } else {
+        Column::new(name.into(), values.to_vec())
+    };
+    Ok(column)
+}
+
+/// Whether a number is written with a zero before another digit, as in `01`.
+fn has_leading_zero(value: &str) -> bool {
+    let digits = value.trim_start_matches(['-', '+']).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit()
+}
+
+/// Undo the percent-encoding Hive applies to partition directory names.
+fn unescape_path_name(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                unescaped.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                unescaped.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::row_filter::{RowFilterOp, RowFilterValue};
+
+    fn files(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| format!("s3://lake/sales/{p}")).collect()
+    }
+
+    #[test]
+    fn test_typed_partitions() -> Result<()> {
+        let files = files(&[
+            "year=2023/day=2023-12-31/region=EU/part-00000.parquet",
+            "year=2024/day=2024-01-01/region=__HIVE_DEFAULT_PARTITION__/part-00000.parquet",
+            "year=2024/day=2024-01-02/region=a%2Fb/part-00000.parquet",
+        ]);
+        let df = partitions("s3://lake/sales/", &files)?.unwrap();
+        assert_eq!(df.get_column_names(), ["year", "day", "region"]);
+        assert_eq!(df.column("year")?.dtype(), &DataType::Int64);
+        assert_eq!(df.column("day")?.dtype(), &DataType::Date);
+        assert_eq!(df.column("region")?.dtype(), &DataType::String);
+        assert_eq!(df.column("region")?.null_count(), 1);
+        assert_eq!(df.column("region")?.str()?.get(2), Some("a/b"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_zero_padded_partitions_stay_text() -> Result<()> {
+        let files = files(&[
+            "month=01/code=007.5/rate=0.5/part-00000.parquet",
+            "month=12/code=1.5/rate=2.0/part-00000.parquet",
+        ]);
+        let df = partitions("s3://lake/sales/", &files)?.unwrap();
+        assert_eq!(df.column("month")?.dtype(), &DataType::String);
+        assert_eq!(df.column("month")?.str()?.get(0), Some("01"));
+        assert_eq!(df.column("code")?.dtype(), &DataType::String);
+        assert_eq!(df.column("rate")?.dtype(), &DataType::Float64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpartitioned_and_inconsistent_layouts() -> Result<()> {
+        assert!(partitions("data/", &["data/a.csv".to_string(), "data/b.csv".to_string()])?.is_none());
+        let mixed = files(&["year=2024/a.csv", "region=EU/b.csv"]);
+        assert!(partitions("s3://lake/sales/", &mixed).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune() -> Result<()> {
+        let files = files(&["year=2023/a.csv", "year=2024/b.csv", "year=2025/c.csv"]);
+        let df = partitions("s3://lake/sales/", &files)?.unwrap();
+        let filters = [RowFilter::new("year", RowFilterOp::Gt, RowFilterValue::Int(2023))];
+        assert_eq!(prune(&df, &filters)?, [false, true, true]);
+        // filters on data columns cannot prune
+        let filters = [RowFilter::new("amount", RowFilterOp::Gt, RowFilterValue::Int(0))];
+        assert_eq!(prune(&df, &filters)?, [true, true, true]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dataset_root() {
+        assert_eq!(dataset_root("s3://lake/sales/"), "s3://lake/sales/");
+        assert_eq!(dataset_root("s3://lake/sales/year=*/*.parquet"), "s3://lake/sales/");
+        assert_eq!(dataset_root("*.csv"), "");
+    }
+}
@@ -1,26 +1,55 @@
 use crate::storage::Storage;
 use anyhow::Result;
+use polars::io::HiveOptions;
 use polars::prelude::*;
+use std::fs::File;
 
-#[derive(Default)]
-pub struct IpcReaderImpl;
+/// Reads the Arrow IPC file format with a lazy scan, so only the columns and
+/// record batches the query needs are decoded. The Polars scan memory-maps
+/// local files, and remote ones once they are spooled. With `memory_map`
+/// off, local files are read into memory in full instead, for filesystems
+/// where mapping a file is unsafe or slow.
+pub struct IpcReaderImpl {
+    pub memory_map: bool,
+}
+
+impl Default for IpcReaderImpl {
+    fn default() -> Self {
+        Self { memory_map: true }
+    }
+}
 
 impl super::FromFile for IpcReaderImpl {
-    fn read_data(&self, path: &str) -> Result<DataFrame> {
+    fn read_data(&self, path: &str) -> Result<LazyFrame> {
         let mut storage = Storage::new(path)?;
-        let file = storage.get_source_file()?;
-        let df = IpcReader::new(file)
-            .finish()?;
-        Ok(df)
+        let source = storage.get_source_path()?;
+        if !self.memory_map && storage.is_local() {
+            let df = IpcReader::new(File::open(&source)?).memory_mapped(None).finish()?;
+            return Ok(df.lazy());
+        }
+
+        let args = ScanArgsIpc {
+            // partition directories are read by `read_inputs`
+            hive_options: HiveOptions {
+                enabled: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut lf = LazyFrame::scan_ipc(source, args)?;
+        lf.collect_schema()?;
+        Ok(lf)
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::from::FromFile;
     use crate::test_utils::helpers::*;
+    use crate::transform::column_filter::ColumnFilter;
+    use crate::transform::row_filter::{RowFilter, RowFilterOp, RowFilterValue};
+    use crate::transform::Transform;
     use tempfile::NamedTempFile;
 
     fn reader() -> IpcReaderImpl {
@@ -37,6 +66,16 @@ mod tests {
         test_write_then_read(writer_fn, reader).unwrap();
     }
 
+    #[test]
+    fn test_scan_valid_df() {
+        let writer_fn = |temp_file: &mut NamedTempFile, df: &DataFrame| -> Result<()> {
+            IpcWriter::new(temp_file).finish(&mut df.clone())?;
+            Ok(())
+        };
+        let reader = Box::new(reader());
+        test_write_then_scan(writer_fn, reader).unwrap();
+    }
+
     #[test]
     fn test_missing_source_file() {
         let result = reader().read_data("non_existent_file");
@@ -49,4 +88,70 @@ mod tests {
         let result = reader().read_data(temp_file.unwrap().path().to_str().unwrap());
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_filters_push_down_into_scan() -> Result<()> {
+        let mut temp_file = create_temp_file()?;
+        let mut df = create_sample_df()?;
+        IpcWriter::new(&mut temp_file).finish(&mut df)?;
+
+        let lf = reader().read_data(temp_file.path().to_str().unwrap())?;
+        let lf = RowFilter::new("age", RowFilterOp::Gt, RowFilterValue::Int(26)).transform(lf)?;
+        let lf = ColumnFilter::new(Some(vec!["name".to_string()]), None).transform(lf)?;
+
+        // the scan itself only decodes `name` and `age` and applies the predicate
+        let plan = lf.clone().describe_optimized_plan()?;
+        assert!(plan.contains("PROJECT 2/3 COLUMNS"));
+        assert!(plan.contains("SELECTION"));
+
+        let out = lf.collect()?;
+        assert_eq!(out.get_column_names(), vec!["name"]);
+        assert_eq!(out.height(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_read_with_filters() -> Result<()> {
+        let mut temp_file = create_temp_file()?;
+        let mut df = create_sample_df()?;
+        IpcWriter::new(&mut temp_file).finish(&mut df)?;
+
+        let lf = IpcReaderImpl { memory_map: false }.read_data(temp_file.path().to_str().unwrap())?;
+        let lf = RowFilter::new("age", RowFilterOp::Gt, RowFilterValue::Int(26)).transform(lf)?;
+        let lf = ColumnFilter::new(Some(vec!["name".to_string()]), None).transform(lf)?;
+
+        let out = lf.collect()?;
+        assert_eq!(out.get_column_names(), vec!["name"]);
+        assert_eq!(out.height(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_mapped_matches_regular_read() -> Result<()> {
+        let mut temp_file = create_temp_file()?;
+        let mut df = create_sample_df()?;
+        IpcWriter::new(&mut temp_file).finish(&mut df)?;
+        let path = temp_file.path().to_str().unwrap();
+
+        let mapped = reader().read_data(path)?.collect()?;
+        let regular = IpcReaderImpl { memory_map: false }.read_data(path)?.collect()?;
+        assert!(mapped.equals_missing(&regular));
+        assert!(mapped.equals_missing(&df));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_compressed_file() -> Result<()> {
+        let mut temp_file = create_temp_file()?;
+        let mut df = create_sample_df()?;
+        IpcWriter::new(&mut temp_file)
+            .with_compression(Some(IpcCompression::ZSTD))
+            .finish(&mut df)?;
+
+        for reader in [reader(), IpcReaderImpl { memory_map: false }] {
+            let out = reader.read_data(temp_file.path().to_str().unwrap())?.collect()?;
+            assert!(out.equals_missing(&df));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use crate::storage::Storage;
+use anyhow::Result;
+use polars::prelude::*;
+
+/// Reads the Arrow IPC stream format, from a file or from stdin with `-`.
+/// A stream cannot be scanned lazily, so it is read in full.
+#[derive(Default)]
+pub struct IpcStreamReaderImpl;
+
+impl super::FromFile for IpcStreamReaderImpl {
+    fn read_data(&self, path: &str) -> Result<LazyFrame> {
+        let mut storage = Storage::new(path)?;
+        let file = storage.get_source_file()?;
+        Ok(IpcStreamReader::new(file).finish()?.lazy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from::FromFile;
+    use crate::test_utils::helpers::*;
+    use tempfile::NamedTempFile;
+
+    fn reader() -> IpcStreamReaderImpl {
+        IpcStreamReaderImpl
+    }
+
+    #[test]
+    fn test_read_valid_df() {
+        let writer_fn = |temp_file: &mut NamedTempFile, df: &DataFrame| -> Result<()> {
+            IpcStreamWriter::new(temp_file).finish(&mut df.clone())?;
+            Ok(())
+        };
+        let reader = Box::new(reader());
+        test_write_then_read(writer_fn, reader).unwrap();
+    }
+
+    #[test]
+    fn test_read_compressed() {
+        let writer_fn = |temp_file: &mut NamedTempFile, df: &DataFrame| -> Result<()> {
+            IpcStreamWriter::new(temp_file)
+                .with_compression(Some(IpcCompression::ZSTD))
+                .finish(&mut df.clone())?;
+            Ok(())
+        };
+        let reader = Box::new(reader());
+        test_write_then_read(writer_fn, reader).unwrap();
+    }
+
+    #[test]
+    fn test_missing_source_file() {
+        let result = reader().read_data("non_existent_file");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_read_malformed_file() {
+        let temp_file = create_malformed_file();
+        let result = reader().read_data(temp_file.unwrap().path().to_str().unwrap());
+        assert!(result.is_err());
+    }
+}
@@ -1,33 +1,158 @@
+use crate::compression::{decompressed_source, FileCompression};
+use crate::schema::TableSchema;
 use crate::storage::Storage;
-use anyhow::Result;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
 use polars::prelude::*;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
 
+/// Layout of the JSON input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum JsonReadFormat {
+    /// One JSON object per line (NDJSON)
+    #[default]
+    Lines,
+    /// A single JSON document holding an array of records
+    Json,
+}
+
+/// Reads JSON Lines, or standard JSON documents such as API exports.
+///
+/// - `record_path`: dotted path to the array of records inside a standard
+///   JSON document, e.g. `data.items`; array elements are addressed by index
+/// - `flatten`: replace nested objects with one column per field, named
+///   `parent.child`
+/// - `compression`: compression of the file, detected when `None`
+/// - `schema`: declared column types, converted to from the inferred ones
 #[derive(Default)]
-pub struct JsonReaderImpl;
+pub struct JsonReaderImpl {
+    pub format: JsonReadFormat,
+    pub record_path: Option<String>,
+    pub flatten: bool,
+    pub compression: Option<FileCompression>,
+    pub schema: Option<TableSchema>,
+}
 
 impl super::FromFile for JsonReaderImpl {
-    fn read_data(&self, path: &str) -> Result<DataFrame> {
+    fn read_data(&self, path: &str) -> Result<LazyFrame> {
         let mut storage = Storage::new(path)?;
-        let file = storage.get_source_file()?;
-        // By default, this interprets the file as NDJSON (one JSON object per line)
-        let df = JsonReader::new(file)
-            .with_json_format(JsonFormat::JsonLines)
-            .finish()?;
+        let source = decompressed_source(path, storage.get_source_path()?, self.compression)?;
+        let mut lf = match self.format {
+            JsonReadFormat::Lines => {
+                if self.record_path.is_some() {
+                    bail!("A record path needs standard JSON input (--json-format json)");
+                }
+                LazyJsonLineReader::new(source).finish()?
+            }
+            JsonReadFormat::Json => self.read_document(&source)?.lazy(),
+        };
+        if self.flatten {
+            lf = flatten_structs(lf)?;
+        }
+        // JSON values are typed already, and the readers null out numbers
+        // forced to text, so declared columns convert from the inferred types
+        if let Some(schema) = &self.schema {
+            lf = schema.apply(lf, false)?;
+        }
+        lf.collect_schema()?;
+        Ok(lf)
+    }
+}
+
+impl JsonReaderImpl {
+    /// Standard JSON has no streaming reader, so the document is read eagerly.
+    fn read_document(&self, source: &Path) -> Result<DataFrame> {
+        let file = File::open(source)?;
+        let df = match &self.record_path {
+            None => JsonReader::new(file).with_json_format(JsonFormat::Json).finish()?,
+            Some(record_path) => {
+                let document: Value = serde_json::from_reader(BufReader::new(file))?;
+                let records = select_records(&document, record_path)?;
+                JsonReader::new(Cursor::new(serde_json::to_vec(records)?))
+                    .with_json_format(JsonFormat::Json)
+                    .finish()?
+            }
+        };
         Ok(df)
     }
 }
 
+/// Follow a dotted record path, e.g. `data.items` or `results.0.rows`, to the
+/// records inside a JSON document.
+fn select_records<'a>(document: &'a Value, record_path: &str) -> Result<&'a Value> {
+    let mut current = document;
+    let mut walked = Vec::new();
+    for key in record_path.split('.') {
+        let next = match current {
+            Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => current.get(key),
+        };
+        current = match next {
+            Some(value) => value,
+            None if walked.is_empty() => bail!("Record path '{record_path}': no '{key}' at the top of the document"),
+            None => bail!("Record path '{record_path}': no '{key}' under '{}'", walked.join(".")),
+        };
+        walked.push(key);
+    }
+    match current {
+        Value::Array(_) | Value::Object(_) => Ok(current),
+        other => bail!("Record path '{record_path}' points to {other}, not to an array of records"),
+    }
+}
+
+/// Replace struct columns with one column per field, named `parent.child`,
+/// recursing into nested structs. Lists are left as they are.
+fn flatten_structs(mut lf: LazyFrame) -> Result<LazyFrame> {
+    let schema = lf.collect_schema()?;
+    let mut columns = Vec::new();
+    for (name, dtype) in schema.iter() {
+        flatten_column(col(name.clone()), name, dtype, &mut columns);
+    }
+    Ok(lf.select(columns))
+}
+
+fn flatten_column(expr: Expr, name: &str, dtype: &DataType, columns: &mut Vec<Expr>) {
+    match dtype {
+        DataType::Struct(fields) if !fields.is_empty() => {
+            for field in fields {
+                let field_expr = expr.clone().struct_().field_by_name(field.name());
+                let field_name = format!("{name}.{}", field.name());
+                flatten_column(field_expr, &field_name, field.dtype(), columns);
+            }
+        }
+        _ => columns.push(expr.alias(name)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::from::FromFile;
     use crate::test_utils::helpers::*;
+    use std::io::Write;
     use tempfile::NamedTempFile;
 
     fn reader() -> JsonReaderImpl {
         JsonReaderImpl::default()
     }
 
+    fn read_str(reader: JsonReaderImpl, contents: &str) -> Result<DataFrame> {
+        let mut temp_file = create_temp_file()?;
+        temp_file.write_all(contents.as_bytes())?;
+        reader.read_data(temp_file.path().to_str().unwrap())?.collect().map_err(Into::into)
+    }
+
+    fn document_reader(record_path: Option<&str>) -> JsonReaderImpl {
+        JsonReaderImpl {
+            format: JsonReadFormat::Json,
+            record_path: record_path.map(str::to_string),
+            ..reader()
+        }
+    }
+
     #[test]
     fn test_read_valid_df() {
         let writer_fn = |temp_file: &mut NamedTempFile, df: &DataFrame| -> Result<()> {
@@ -38,6 +163,16 @@ mod tests {
         test_write_then_read(writer_fn, reader).unwrap();
     }
 
+    #[test]
+    fn test_scan_valid_df() {
+        let writer_fn = |temp_file: &mut NamedTempFile, df: &DataFrame| -> Result<()> {
+            JsonWriter::new(temp_file).with_json_format(JsonFormat::JsonLines).finish(&mut df.clone())?;
+            Ok(())
+        };
+        let reader = Box::new(reader());
+        test_write_then_scan(writer_fn, reader).unwrap();
+    }
+
     #[test]
     fn test_missing_source_file() {
         let result = reader().read_data("non_existent_file");
@@ -50,4 +185,81 @@ mod tests {
         let result = reader().read_data(temp_file.unwrap().path().to_str().unwrap());
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_read_array_document() -> Result<()> {
+        let df = read_str(document_reader(None), r#"[{"name": "Alice", "age": 30}, {"name": "Bob", "age": 25}]"#)?;
+        assert_eq!(df.get_column_names(), vec!["name", "age"]);
+        assert_eq!(df.height(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_record_path() -> Result<()> {
+        let contents = r#"{"meta": {"page": 1}, "data": {"items": [{"id": 1}, {"id": 2}, {"id": 3}]}}"#;
+        let df = read_str(document_reader(Some("data.items")), contents)?;
+        assert_eq!(df.column("id")?.i64()?.into_no_null_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let nested = r#"{"results": [{"rows": [{"id": 7}]}]}"#;
+        let df = read_str(document_reader(Some("results.0.rows")), nested)?;
+        assert_eq!(df.height(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_path_errors() -> Result<()> {
+        let contents = r#"{"data": {"count": 3}}"#;
+        let err = read_str(document_reader(Some("data.items")), contents).unwrap_err();
+        assert!(err.to_string().contains("no 'items' under 'data'"));
+        let err = read_str(document_reader(Some("data.count")), contents).unwrap_err();
+        assert!(err.to_string().contains("not to an array of records"));
+        // a record path only makes sense for a standard JSON document
+        let lines = JsonReaderImpl {
+            record_path: Some("data".to_string()),
+            ..reader()
+        };
+        assert!(read_str(lines, "{\"data\": 1}\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten_nested_objects() -> Result<()> {
+        let contents = "{\"id\": 1, \"user\": {\"name\": \"Alice\", \"address\": {\"city\": \"NYC\"}}, \"tags\": [\"a\"]}\n";
+        let reader = JsonReaderImpl {
+            flatten: true,
+            ..reader()
+        };
+        let df = read_str(reader, contents)?;
+        assert_eq!(df.get_column_names(), vec!["id", "user.name", "user.address.city", "tags"]);
+        assert_eq!(df.column("user.address.city")?.str()?.get(0), Some("NYC"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_zstd_lines_without_extension() -> Result<()> {
+        // no .zst extension, so the zstd magic number gives it away
+        let mut temp_file = NamedTempFile::new()?;
+        temp_file.write_all(&zstd::encode_all(&b"{\"id\": 1}\n{\"id\": 2}\n"[..], 0)?)?;
+        let df = reader().read_data(temp_file.path().to_str().unwrap())?.collect()?;
+        assert_eq!(df.column("id")?.i64()?.get(1), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_with_schema() -> Result<()> {
+        let mut schema_file = tempfile::Builder::new().suffix(".toml").tempfile()?;
+        schema_file.write_all(
+            b"[[columns]]\nname = \"id\"\ntype = \"string\"\n\n[[columns]]\nname = \"amount\"\ntype = \"decimal(8,2)\"\nnullable = false\n",
+        )?;
+        let schema = TableSchema::load(schema_file.path().to_str().unwrap())?;
+        let contents = "{\"id\": 1001, \"amount\": 12.5}\n{\"id\": 1002, \"amount\": 3}\n";
+        let df = read_str(JsonReaderImpl { schema: Some(schema.clone()), ..reader() }, contents)?;
+        assert_eq!(df.column("id")?.str()?.get(1), Some("1002"));
+        assert_eq!(df.column("amount")?.dtype(), &DataType::Decimal(Some(8), Some(2)));
+
+        let missing = "{\"id\": 1, \"amount\": null}\n";
+        let err = read_str(JsonReaderImpl { schema: Some(schema), ..reader() }, missing).unwrap_err();
+        assert!(err.to_string().contains("declared not nullable"), "{err}");
+        Ok(())
+    }
+}
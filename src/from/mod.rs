@@ -1,8 +1,13 @@
-use anyhow::Result;
+use crate::transform::row_filter::RowFilter;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
 use polars::prelude::*;
 
+/// Readers hand back a lazy query over the source, so column selections and
+/// row filters are pushed down into the scan. Formats without a native scan
+/// read eagerly and wrap the frame.
 pub trait FromFile {
-    fn read_data(&self, path: &str) -> Result<DataFrame>;
+    fn read_data(&self, path: &str) -> Result<LazyFrame>;
 }
 
 // The `DataReader` enum acts as a dispatcher for multiple reader implementations
@@ -11,23 +16,200 @@ pub enum DataReader {
     Parquet(parquet::ParquetReaderImpl),
     Avro(avro::AvroReaderImpl),
     Ipc(ipc::IpcReaderImpl),
+    IpcStream(ipc_stream::IpcStreamReaderImpl),
     Json(json::JsonReaderImpl),
+    Excel(excel::ExcelReaderImpl),
 }
 
 impl DataReader {
-    pub fn read_data(&self, path: &str) -> Result<DataFrame> {
+    pub fn read_data(&self, path: &str) -> Result<LazyFrame> {
         match self {
             DataReader::Csv(r) => r.read_data(path),
             DataReader::Parquet(r) => r.read_data(path),
             DataReader::Avro(r) => r.read_data(path),
             DataReader::Ipc(r) => r.read_data(path),
+            DataReader::IpcStream(r) => r.read_data(path),
             DataReader::Json(r) => r.read_data(path),
+            DataReader::Excel(r) => r.read_data(path),
         }
     }
 }
 
+/// How the columns of several input files are combined.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SchemaPolicy {
+    /// Every file must have the same columns, in the same order and types
+    #[default]
+    Strict,
+    /// Keep every column of every file, filling nulls where a file lacks one
+    UnionByName,
+    /// Keep only the columns every file has
+    Intersect,
+}
+
+/// Read every file with `reader` and concatenate them under `policy`,
+/// optionally adding a `source_column` with the file each row came from.
+/// Types that differ between files are widened to a common type, except
+/// under the strict policy.
+///
+/// With a `hive_root`, `key=value` directories below it become typed
+/// columns, and files whose partition values fail one of `filters` are
+/// never opened. The filters still have to be applied to the result.
+pub fn read_inputs(
+    reader: &DataReader,
+    paths: &[String],
+    policy: SchemaPolicy,
+    source_column: Option<&str>,
+    hive_root: Option<&str>,
+    filters: &[RowFilter],
+) -> Result<LazyFrame> {
+    let partitions = match hive_root {
+        Some(root) => hive::partitions(root, paths)?,
+        None => None,
+    };
+    let mut keep = match &partitions {
+        Some(partitions) => hive::prune(partitions, filters)?,
+        None => vec![true; paths.len()],
+    };
+    if !keep.contains(&true) {
+        // the filters reject every row anyway, but the query needs a schema
+        keep[0] = true;
+    }
+
+    let mut frames = Vec::with_capacity(paths.len());
+    let mut schemas = Vec::with_capacity(paths.len());
+    let mut kept_paths = Vec::with_capacity(paths.len());
+    for (i, path) in paths.iter().enumerate().filter(|(i, _)| keep[*i]) {
+        let mut lf = reader.read_data(path)?;
+        if let Some(partitions) = &partitions {
+            lf = lf.with_columns(hive::partition_columns(partitions, i)?);
+        }
+        let schema = lf.collect_schema()?;
+        if let Some(name) = source_column {
+            if schema.contains(name) {
+                bail!("Cannot add source column '{name}': {path} already has a column of that name");
+            }
+            lf = lf.with_column(lit(path.as_str()).alias(name));
+        }
+        schemas.push(schema);
+        frames.push(lf);
+        kept_paths.push(path);
+    }
+    if frames.len() == 1 {
+        return Ok(frames.remove(0));
+    }
+
+    let args = UnionArgs {
+        to_supertypes: policy != SchemaPolicy::Strict,
+        ..Default::default()
+    };
+    match policy {
+        SchemaPolicy::Strict => {
+            for (path, schema) in kept_paths.iter().zip(&schemas).skip(1) {
+                if **schema != *schemas[0] {
+                    bail!(
+                        "{path} has columns [{}] but {} has [{}]; use --schema-policy union-by-name or intersect to combine them",
+                        describe(schema),
+                        kept_paths[0],
+                        describe(&schemas[0])
+                    );
+                }
+            }
+            Ok(concat(frames, args)?)
+        }
+        SchemaPolicy::UnionByName => Ok(concat_lf_diagonal(frames, args)?),
+        SchemaPolicy::Intersect => {
+            let mut common: Vec<Expr> = schemas[0]
+                .iter_names()
+                .filter(|name| schemas.iter().all(|schema| schema.contains(name)))
+                .map(|name| col(name.clone()))
+                .collect();
+            if common.is_empty() {
+                bail!("The input files have no columns in common");
+            }
+            if let Some(name) = source_column {
+                common.push(col(name));
+            }
+            let frames = frames.into_iter().map(|lf| lf.select(common.clone())).collect::<Vec<_>>();
+            Ok(concat(frames, args)?)
+        }
+    }
+}
+
+fn describe(schema: &Schema) -> String {
+    schema
+        .iter()
+        .map(|(name, dtype)| format!("{name}: {dtype}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub mod hive;
 pub mod csv;
 pub mod parquet;
 pub mod avro;
 pub mod ipc;
-pub mod json;
\ No newline at end of file
+pub mod ipc_stream;
+pub mod json;
+pub mod excel;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from::csv::CsvReaderImpl;
+    use tempfile::TempDir;
+
+    fn write_inputs(dir: &TempDir, files: &[(&str, &str)]) -> Result<Vec<String>> {
+        let mut paths = Vec::new();
+        for (name, contents) in files {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents)?;
+            paths.push(path.to_str().unwrap().to_string());
+        }
+        Ok(paths)
+    }
+
+    #[test]
+    fn test_strict_policy_rejects_differing_schemas() -> Result<()> {
+        let dir = TempDir::new()?;
+        let reader = DataReader::Csv(CsvReaderImpl::default());
+        let paths = write_inputs(&dir, &[("a.csv", "name,age\nAlice,30\n"), ("b.csv", "name,age\nBob,25\n")])?;
+        let df = read_inputs(&reader, &paths, SchemaPolicy::Strict, None, None, &[])?.collect()?;
+        assert_eq!(df.shape(), (2, 2));
+
+        let paths = write_inputs(&dir, &[("a.csv", "name,age\nAlice,30\n"), ("c.csv", "name,city\nCarol,Paris\n")])?;
+        let err = read_inputs(&reader, &paths, SchemaPolicy::Strict, None, None, &[]).err().unwrap();
+        assert!(err.to_string().contains("--schema-policy"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_union_by_name_and_intersect() -> Result<()> {
+        let dir = TempDir::new()?;
+        let reader = DataReader::Csv(CsvReaderImpl::default());
+        let paths = write_inputs(
+            &dir,
+            &[("a.csv", "name,age\nAlice,30\n"), ("b.csv", "age,city,name\n25.5,LA,Bob\n")],
+        )?;
+
+        let df = read_inputs(&reader, &paths, SchemaPolicy::UnionByName, Some("file"), None, &[])?.collect()?;
+        assert_eq!(df.get_column_names(), ["name", "age", "file", "city"]);
+        // the integer and float ages are widened to a common type
+        assert_eq!(df.column("age")?.dtype(), &DataType::Float64);
+        assert_eq!(df.column("city")?.null_count(), 1);
+        assert_eq!(df.column("file")?.str()?.get(1), Some(paths[1].as_str()));
+
+        let df = read_inputs(&reader, &paths, SchemaPolicy::Intersect, None, None, &[])?.collect()?;
+        assert_eq!(df.get_column_names(), ["name", "age"]);
+        assert_eq!(df.height(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_column_must_be_new() -> Result<()> {
+        let dir = TempDir::new()?;
+        let reader = DataReader::Csv(CsvReaderImpl::default());
+        let paths = write_inputs(&dir, &[("a.csv", "name,age\nAlice,30\n")])?;
+        assert!(read_inputs(&reader, &paths, SchemaPolicy::Strict, Some("age"), None, &[]).is_err());
+        Ok(())
+    }
+}
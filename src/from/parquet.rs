@@ -1,17 +1,29 @@
 use super::FromFile;
 use crate::storage::Storage;
 use anyhow::Result;
+use polars::io::HiveOptions;
 use polars::prelude::*;
 
 #[derive(Default)]
 pub struct ParquetReaderImpl;
 
 impl FromFile for ParquetReaderImpl {
-    fn read_data(&self, path: &str) -> Result<DataFrame> {
+    fn read_data(&self, path: &str) -> Result<LazyFrame> {
         let mut storage = Storage::new(path)?;
-        let file = storage.get_source_file()?;
-        let df = ParquetReader::new(file).finish()?;
-        Ok(df)
+        let source = storage.get_source_path()?;
+        // row group statistics let predicates skip whole row groups
+        let args = ScanArgsParquet {
+            use_statistics: true,
+            // partition directories are read by `read_inputs`, for every format alike
+            hive_options: HiveOptions {
+                enabled: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut lf = LazyFrame::scan_parquet(source, args)?;
+        lf.collect_schema()?;
+        Ok(lf)
     }
 }
 
@@ -19,10 +31,13 @@ impl FromFile for ParquetReaderImpl {
 mod tests {
     use super::*;
     use crate::test_utils::helpers::*;
+    use crate::transform::column_filter::ColumnFilter;
+    use crate::transform::row_filter::{RowFilter, RowFilterOp, RowFilterValue};
+    use crate::transform::Transform;
     use tempfile::NamedTempFile;
 
     fn reader() -> ParquetReaderImpl {
-        ParquetReaderImpl::default()
+        ParquetReaderImpl
     }
 
     #[test]
@@ -35,6 +50,16 @@ mod tests {
         test_write_then_read(writer_fn, reader).unwrap();
     }
 
+    #[test]
+    fn test_scan_valid_df() {
+        let writer_fn = |temp_file: &mut NamedTempFile, df: &DataFrame| -> Result<()> {
+            ParquetWriter::new(temp_file).finish(&mut df.clone())?;
+            Ok(())
+        };
+        let reader = Box::new(reader());
+        test_write_then_scan(writer_fn, reader).unwrap();
+    }
+
     #[test]
     fn test_missing_source_file() {
         let result = reader().read_data("non_existent_file");
@@ -47,4 +72,25 @@ mod tests {
         let result = reader().read_data(temp_file.unwrap().path().to_str().unwrap());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_filters_push_down_into_scan() -> Result<()> {
+        let mut temp_file = create_temp_file()?;
+        let mut df = create_sample_df()?;
+        ParquetWriter::new(&mut temp_file).finish(&mut df)?;
+
+        let lf = reader().read_data(temp_file.path().to_str().unwrap())?;
+        let lf = RowFilter::new("age", RowFilterOp::Gt, RowFilterValue::Int(26)).transform(lf)?;
+        let lf = ColumnFilter::new(Some(vec!["name".to_string()]), None).transform(lf)?;
+
+        // the scan itself only decodes `name` and `age` and applies the predicate
+        let plan = lf.clone().describe_optimized_plan()?;
+        assert!(plan.contains("PROJECT 2/3 COLUMNS"));
+        assert!(plan.contains("SELECTION"));
+
+        let out = lf.collect()?;
+        assert_eq!(out.get_column_names(), vec!["name"]);
+        assert_eq!(out.height(), 1);
+        Ok(())
+    }
 }
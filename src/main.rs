@@ -1,11 +1,14 @@
 use anyhow::Result;
 use clap::Parser;
 
+mod avro;
 mod cli;
+mod compression;
 mod config;
 mod format;  // Where the Format enum is
 mod r#from;
 mod r#to;
+mod schema;
 mod transform;
 mod test_utils;
 mod storage;
@@ -13,97 +16,235 @@ mod storage;
 use crate::cli::Cli;
 use crate::config::Config;
 use crate::format::Format;
-use crate::from::{avro::AvroReaderImpl, ipc::IpcReaderImpl, json::JsonReaderImpl};
-use crate::to::{avro::AvroWriterImpl, ipc::IpcWriterImpl, json::JsonWriterImpl};
+use crate::schema::TableSchema;
+use crate::from::{avro::AvroReaderImpl, excel::ExcelReaderImpl, ipc::IpcReaderImpl, ipc_stream::IpcStreamReaderImpl, json::JsonReaderImpl};
+use crate::to::{avro::AvroWriterImpl, excel::ExcelWriterImpl, ipc::IpcWriterImpl, ipc_stream::IpcStreamWriterImpl, json::JsonWriterImpl};
+use crate::to::partition::{write_partitioned, PartitionSpec};
+use crate::to::split::{write_split, SplitSpec};
 use r#from::{csv::CsvReaderImpl, parquet::ParquetReaderImpl, DataReader};
 use r#to::{csv::CsvWriterImpl, parquet::ParquetWriterImpl, DataWriter};
-use transform::{column_filter::ColumnFilter, row_filter::{RowFilter, RowFilterValue, RowFilterOp}, Transform};
+use transform::{cast::Cast, column_filter::ColumnFilter, row_filter::{RowFilter, RowFilterValue, RowFilterOp}, Transform};
 
 fn main() -> Result<()> {
+    let result = run();
+    // remote sources spooled for lazy scans are only needed while the query runs
+    storage::release_spools();
+    result
+}
+
+fn run() -> Result<()> {
     // 1. Parse CLI
     let cli = Cli::parse();
 
-    // 2. Build config from CLI
-    let config = Config::new(
-        cli.from_format,
-        cli.to_format,
-        cli.input_file,
-        cli.output,
-        cli.include_columns,
-        cli.exclude_columns,
-        cli.row_filter_col,
-        cli.row_filter_op,
-        cli.row_filter_val,
-    );
+    // 2. Build config from CLI and the optional config file
+    let config = Config::try_from(cli)?;
     config.validate()?;
 
-    // 3. Create reader based on enum
+    // 3. Create reader based on enum, with the declared schema if any
+    let schema = config.schema.as_deref().map(TableSchema::load).transpose()?;
     let reader = match config.from_format {
-        Format::Csv => DataReader::Csv(CsvReaderImpl::default()),
-        Format::Parquet => DataReader::Parquet(ParquetReaderImpl::default()),
-        Format::Avro => DataReader::Avro(AvroReaderImpl::default()),
-        Format::Ipc => DataReader::Ipc(IpcReaderImpl::default()),
-        Format::Json => DataReader::Json(JsonReaderImpl::default()),
+        Format::Csv => DataReader::Csv(CsvReaderImpl {
+            delimiter: config.delimiter,
+            has_header: config.has_header,
+            quote_char: config.quote_char,
+            escape_char: config.escape_char,
+            comment_prefix: config.comment_prefix.clone(),
+            skip_rows: config.skip_rows,
+            null_values: config.null_values.clone(),
+            infer_schema_rows: config.infer_schema_rows,
+            all_strings: config.all_strings,
+            compression: config.input_compression,
+            schema: schema.clone(),
+        }),
+        Format::Parquet => DataReader::Parquet(ParquetReaderImpl),
+        Format::Avro => DataReader::Avro(AvroReaderImpl {
+            reader_schema: config.reader_schema.clone(),
+            compression: config.input_compression,
+        }),
+        Format::Ipc => DataReader::Ipc(IpcReaderImpl {
+            memory_map: config.ipc_memory_map,
+        }),
+        Format::IpcStream => DataReader::IpcStream(IpcStreamReaderImpl),
+        Format::Json => DataReader::Json(JsonReaderImpl {
+            format: config.json_format,
+            record_path: config.record_path.clone(),
+            flatten: config.flatten,
+            compression: config.input_compression,
+            schema: schema.clone(),
+        }),
+        Format::Excel => DataReader::Excel(ExcelReaderImpl {
+            sheet: config.sheet.clone(),
+            header_row: config.header_row,
+            cell_range: config.cell_range.clone(),
+            all_sheets: config.all_sheets,
+            sheet_column: config.sheet_column.clone(),
+            whole_numbers_as_integers: config.whole_numbers_as_integers,
+        }),
     };
 
     // 4. Create writer based on enum
     let writer = match config.to_format {
-        Format::Csv => DataWriter::Csv(CsvWriterImpl::default()),
-        Format::Parquet => DataWriter::Parquet(ParquetWriterImpl::default()),
-        Format::Avro => DataWriter::Avro(AvroWriterImpl::default()),
-        Format::Ipc => DataWriter::Ipc(IpcWriterImpl::default()),
-        Format::Json => DataWriter::Json(JsonWriterImpl::default()),
+        Format::Csv => DataWriter::Csv(CsvWriterImpl {
+            delimiter: config.output_delimiter,
+            quote_style: config.output_quote_style,
+            null_value: config.output_null_value.clone(),
+            include_header: config.output_has_header,
+            crlf: config.output_crlf,
+            date_format: config.output_date_format.clone(),
+            datetime_format: config.output_datetime_format.clone(),
+            time_format: config.output_time_format.clone(),
+            float_precision: config.output_float_precision,
+            escape_formulas: config.escape_formulas,
+            compression: config.output_compression,
+        }),
+        Format::Parquet => DataWriter::Parquet(ParquetWriterImpl {
+            codec: config.parquet_compression,
+            compression_level: config.parquet_compression_level,
+            row_group_size: config.parquet_row_group_size,
+            data_page_size: config.parquet_data_page_size,
+            statistics: config.parquet_statistics,
+            dictionary: config.parquet_dictionary,
+        }),
+        Format::Avro => DataWriter::Avro(AvroWriterImpl {
+            codec: config.avro_compression,
+            record_name: config.avro_record_name.clone(),
+            namespace: config.avro_namespace.clone(),
+            field_docs: config.avro_field_docs.clone(),
+            schema: config.avro_schema.clone(),
+        }),
+        Format::Ipc => DataWriter::Ipc(IpcWriterImpl {
+            compression: config.ipc_compression,
+        }),
+        Format::IpcStream => DataWriter::IpcStream(IpcStreamWriterImpl {
+            compression: config.ipc_compression,
+        }),
+        Format::Json => DataWriter::Json(JsonWriterImpl {
+            format: config.output_json_format,
+            pretty: config.output_pretty,
+            omit_nulls: config.output_omit_nulls,
+            temporal_format: config.output_temporal_format,
+            wrapper_key: config.output_wrapper_key.clone(),
+            compression: config.output_compression,
+        }),
+        Format::Excel => DataWriter::Excel(ExcelWriterImpl {
+            sheet_name: config.sheet_name.clone(),
+            split_by: config.split_sheets_by.clone(),
+            max_rows_per_sheet: config.max_rows_per_sheet,
+        }),
+    };
+
+    // 5. Build the transform chain
+    let cast = (!config.cast.is_empty()).then(|| Cast::parse(&config.cast, config.cast_mode)).transpose()?;
+    let row_filter = build_row_filter(&config);
+    let transforms = build_transforms(&config, cast.as_ref(), row_filter.as_ref());
+
+    let output = config
+        .output_file
+        .as_ref()
+        .expect("Output file must be provided via --output");
+
+    // 6. Build the lazy query: scan every input file, skipping partitions the
+    //    row filter rules out, then each transform on the plan
+    let inputs = storage::expand_input(&config.input_file, Some(config.from_format))?;
+    let hive_root = config.hive_partitioning.then(|| r#from::hive::dataset_root(&config.input_file));
+    let row_filters: Vec<RowFilter> = row_filter.into_iter().collect();
+    let mut lf = r#from::read_inputs(
+        &reader,
+        &inputs,
+        config.schema_policy,
+        config.source_column.as_deref(),
+        hive_root,
+        &row_filters,
+    )?;
+    for t in &transforms {
+        lf = t.transform(lf)?;
+    }
+
+    // 7. Write a partitioned dataset or numbered part files, or sink the
+    //    query batch by batch. Queries that are collected use the streaming
+    //    engine with --streaming
+    let partitions = PartitionSpec {
+        columns: config.partition_by.clone(),
+        bucket_by: config.bucket_by.clone(),
+        buckets: config.buckets,
+        max_files_per_pass: config.max_files_per_pass,
+    };
+    let split = SplitSpec {
+        max_rows: config.max_rows_per_file,
+        max_bytes: config.max_bytes_per_file,
     };
+    let lf = lf.with_streaming(config.streaming);
+    if partitions.is_partitioned() {
+        write_partitioned(&writer, output, lf, &partitions, config.append)?;
+    } else if split.is_split() {
+        write_split(&writer, output, lf, split)?;
+    } else {
+        writer.sink_data(output, lf, config.append)?;
+    }
+
+    // 8. Report the values a lenient cast left null
+    if let Some(cast) = &cast {
+        for (column, dtype, failures) in cast.failures() {
+            eprintln!(
+                "Cast of '{column}' to {dtype}: {} value(s) did not fit and were set to null, such as '{}'",
+                failures.count,
+                failures.example.unwrap_or_default()
+            );
+        }
+    }
 
-    // 5. Read DataFrame
-    let df = reader.read_data(&config.input_file)?;
+    Ok(())
+}
 
-    // 6. Column Filtering
-    let column_filter = ColumnFilter::new(
+fn build_transforms(config: &Config, cast: Option<&Cast>, row_filter: Option<&RowFilter>) -> Vec<Box<dyn Transform>> {
+    let mut transforms: Vec<Box<dyn Transform>> = Vec::new();
+
+    // Casting comes first so the row filter compares the new types
+    if let Some(cast) = cast {
+        transforms.push(Box::new(cast.clone()));
+    }
+
+    // Row filtering comes first so it can use columns the column filter drops
+    if let Some(row_filter) = row_filter {
+        transforms.push(Box::new(row_filter.clone()));
+    }
+
+    // Column Filtering
+    transforms.push(Box::new(ColumnFilter::new(
         config.include_columns.clone(),
         config.exclude_columns.clone(),
-    );
-    let mut df_transformed = column_filter.transform(df)?;
+    )));
+
+    transforms
+}
 
-    // 7. Check if we have row-filter arguments
-    if let (Some(col), Some(op_str), Some(val)) =
+fn build_row_filter(config: &Config) -> Option<RowFilter> {
+    let (Some(col), Some(op_str), Some(val)) =
         (&config.row_filter_col, &config.row_filter_op, &config.row_filter_val)
-    {
-        // parse operator into an Option<RowFilterOp>
-        let op = match op_str.as_str() {
-            "eq" => Some(RowFilterOp::Eq),
-            "gt" => Some(RowFilterOp::Gt),
-            "lt" => Some(RowFilterOp::Lt),
-            _ => {
-                eprintln!("Invalid row filter operator: {}. Ignoring row filter.", op_str);
-                None
-            }
-        };
+    else {
+        return None;
+    };
 
-        if let Some(parsed_op) = op {
-            // parse val as i64 or treat as string
-            let val_as_int = val.parse::<i64>();
-            let row_value = if let Ok(i) = val_as_int {
-                RowFilterValue::Int(i)
-            } else {
-                RowFilterValue::Str(val.to_string())
-            };
-
-            let row_filter = RowFilter::new(col, parsed_op, row_value);
-            df_transformed = row_filter.transform(df_transformed)?;
+    // parse operator into an Option<RowFilterOp>
+    let op = match op_str.as_str() {
+        "eq" => Some(RowFilterOp::Eq),
+        "gt" => Some(RowFilterOp::Gt),
+        "lt" => Some(RowFilterOp::Lt),
+        _ => {
+            eprintln!("Invalid row filter operator: {}. Ignoring row filter.", op_str);
+            None
         }
-    }
-
+    };
 
-    // 8. Write DataFrame
-    writer.write_data(
-        config
-            .output_file
-            .as_ref()
-            .expect("Output file must be provided via --output"),
-        &df_transformed,
-        cli.append,
-    )?;
+    op.map(|parsed_op| {
+        // parse val as i64 or treat as string
+        let val_as_int = val.parse::<i64>();
+        let row_value = if let Ok(i) = val_as_int {
+            RowFilterValue::Int(i)
+        } else {
+            RowFilterValue::Str(val.to_string())
+        };
 
-    Ok(())
+        RowFilter::new(col, parsed_op, row_value)
+    })
 }
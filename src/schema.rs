@@ -0,0 +1,398 @@
+//! Declared schemas for untyped inputs. CSV and JSON readers keep the
+//! declared columns as text and convert them here, so zip codes keep their
+//! leading zeros and a value that does not fit its type fails the run
+//! instead of turning into a null.
+//!
+//! ```toml
+//! [[columns]]
+//! name = "zip"
+//! type = "string"
+//! nullable = false
+//!
+//! [[columns]]
+//! name = "ordered"
+//! type = "date"
+//! format = "%d/%m/%Y"
+//!
+//! [[columns]]
+//! name = "price"
+//! type = "decimal(12,2)"
+//! ```
+
+use crate::avro::{field_dtype, load_schema, parse_record};
+use anyhow::{bail, Context, Result};
+use polars::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// A declared column.
+/// - `format`: chrono format of date, datetime and time text, e.g. `%d/%m/%Y`;
+///   ISO 8601 when `None`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub dtype: DataType,
+    pub nullable: bool,
+    pub format: Option<String>,
+}
+
+/// The columns declared by a `--schema` file, in order. Columns the file
+/// leaves out keep their inferred types.
+#[derive(Debug, Clone, Default)]
+pub struct TableSchema {
+    pub columns: Vec<ColumnSchema>,
+    /// The file the schema came from, for error messages
+    pub source: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SchemaFile {
+    columns: Vec<ColumnEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColumnEntry {
+    name: String,
+    #[serde(rename = "type")]
+    dtype: String,
+    #[serde(default = "nullable_by_default")]
+    nullable: bool,
+    format: Option<String>,
+    precision: Option<usize>,
+    scale: Option<usize>,
+}
+
+fn nullable_by_default() -> bool {
+    true
+}
+
+impl TableSchema {
+    /// Load a schema from a TOML file, a JSON file of the same shape, or an
+    /// Avro record schema (`.avsc`, or JSON with `"type": "record"`).
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Cannot read schema file {path}"))?;
+        let ext = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+        let entries = match ext.as_str() {
+            "json" | "avsc" => {
+                let value: Value = serde_json::from_str(&contents).with_context(|| format!("Invalid schema file {path}"))?;
+                if value.get("type").and_then(Value::as_str) == Some("record") {
+                    return Self::from_avro(path);
+                }
+                serde_json::from_value::<SchemaFile>(value).with_context(|| format!("Invalid schema file {path}"))?
+            }
+            _ => toml::from_str::<SchemaFile>(&contents).with_context(|| format!("Invalid schema file {path}"))?,
+        };
+
+        let mut columns = Vec::with_capacity(entries.columns.len());
+        for entry in entries.columns {
+            let dtype = match (entry.dtype.trim().to_ascii_lowercase().as_str(), entry.precision) {
+                ("decimal", Some(precision)) => decimal(precision, entry.scale.unwrap_or(0)),
+                (_, _) => parse_dtype(&entry.dtype),
+            }
+            .with_context(|| format!("Column '{}' in schema file {path}", entry.name))?;
+            columns.push(ColumnSchema {
+                name: entry.name,
+                dtype,
+                nullable: entry.nullable,
+                format: entry.format,
+            });
+        }
+        Self::new(columns, path)
+    }
+
+    fn from_avro(path: &str) -> Result<Self> {
+        let record = parse_record(&load_schema(path)?)?;
+        let mut columns = Vec::with_capacity(record.fields.len());
+        for field in &record.fields {
+            let (dtype, nullable) = field_dtype(&field.name, &field.schema)?;
+            columns.push(ColumnSchema {
+                name: field.name.clone(),
+                dtype,
+                nullable,
+                format: None,
+            });
+        }
+        Self::new(columns, path)
+    }
+
+    fn new(columns: Vec<ColumnSchema>, source: &str) -> Result<Self> {
+        for (i, column) in columns.iter().enumerate() {
+            if columns[..i].iter().any(|c| c.name == column.name) {
+                bail!("Column '{}' is declared twice in schema file {source}", column.name);
+            }
+        }
+        Ok(Self {
+            columns,
+            source: source.to_string(),
+        })
+    }
+
+    /// Dtype overrides that make a reader keep the declared columns as text.
+    /// A headerless CSV has columns named `column_1`, `column_2`, ..., which
+    /// the declared columns name in order.
+    pub fn text_overrides(&self, headerless: bool) -> Schema {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| match headerless {
+                true => Field::new(format!("column_{}", i + 1).into(), DataType::String),
+                false => Field::new(column.name.as_str().into(), DataType::String),
+            })
+            .collect()
+    }
+
+    /// Convert the declared columns of `lf`, as read by a reader given
+    /// `text_overrides`, to their types. Values that do not fit, and nulls in
+    /// columns that are not nullable, fail the query when it runs.
+    pub fn apply(&self, mut lf: LazyFrame, headerless: bool) -> Result<LazyFrame> {
+        if headerless {
+            let width = lf.collect_schema()?.len();
+            if self.columns.len() > width {
+                bail!("Schema file {} declares {} columns but the input has {width}", self.source, self.columns.len());
+            }
+            let positional = (1..=self.columns.len()).map(|i| format!("column_{i}"));
+            lf = lf.rename(positional, self.columns.iter().map(|c| c.name.as_str()), true);
+        }
+        let input = lf.collect_schema()?;
+        let mut conversions = Vec::with_capacity(self.columns.len());
+        for column in &self.columns {
+            if !input.contains(&column.name) {
+                bail!("Column '{}' is declared in schema file {} but the input has no such column", column.name, self.source);
+            }
+            let declared = column.clone();
+            let source = self.source.clone();
+            let convert_column = move |c: Column| {
+                let out = convert(&c, &declared.dtype, declared.format.as_deref())?;
+                let (failed, example) = conversion_failures(&c, &out)?;
+                if failed > 0 {
+                    return Err(PolarsError::ComputeError(
+                        format!(
+                            "Column '{}' does not fit {} as declared in schema file {source}: {failed} value(s) fail, such as '{}'",
+                            declared.name,
+                            describe_type(&declared),
+                            example.unwrap_or_default()
+                        )
+                        .into(),
+                    ));
+                }
+                if !declared.nullable && out.null_count() > 0 {
+                    return Err(PolarsError::ComputeError(
+                        format!(
+                            "Column '{}' is declared not nullable in schema file {source} but has {} null(s)",
+                            declared.name,
+                            out.null_count()
+                        )
+                        .into(),
+                    ));
+                }
+                Ok(Some(out))
+            };
+            conversions.push(col(column.name.as_str()).map(convert_column, GetOutput::from_type(column.dtype.clone())));
+        }
+        Ok(lf.with_columns(conversions))
+    }
+}
+
+fn describe_type(column: &ColumnSchema) -> String {
+    match &column.format {
+        Some(format) => format!("{} (format {format})", column.dtype),
+        None => column.dtype.to_string(),
+    }
+}
+
+/// Parse a type name: `bool`, `int8` to `int64`, `uint8` to `uint64`,
+/// `float32`, `float64`, `string`, `date`, `time`, `datetime[unit, zone]`
+/// with both parts optional, or `decimal(precision, scale)`.
+pub fn parse_dtype(name: &str) -> Result<DataType> {
+    let lower = name.trim().to_ascii_lowercase();
+    let dtype = match lower.as_str() {
+        "bool" | "boolean" => DataType::Boolean,
+        "int8" => DataType::Int8,
+        "int16" => DataType::Int16,
+        "int32" => DataType::Int32,
+        "int64" | "int" => DataType::Int64,
+        "uint8" => DataType::UInt8,
+        "uint16" => DataType::UInt16,
+        "uint32" => DataType::UInt32,
+        "uint64" => DataType::UInt64,
+        "float32" => DataType::Float32,
+        "float64" | "float" | "double" => DataType::Float64,
+        "string" | "str" | "utf8" => DataType::String,
+        "date" => DataType::Date,
+        "time" => DataType::Time,
+        "datetime" => DataType::Datetime(TimeUnit::Microseconds, None),
+        _ => {
+            if let Some(args) = lower.strip_prefix("datetime[").and_then(|s| s.strip_suffix(']')) {
+                // keep the zone's case, as in Europe/Paris
+                let original = &name.trim()["datetime[".len()..name.trim().len() - 1];
+                let (unit, zone) = match original.split_once(',') {
+                    Some((unit, zone)) => (unit.trim(), Some(zone.trim())),
+                    None => (args.trim(), None),
+                };
+                let unit = match unit.to_ascii_lowercase().as_str() {
+                    "ms" => TimeUnit::Milliseconds,
+                    "us" | "" => TimeUnit::Microseconds,
+                    "ns" => TimeUnit::Nanoseconds,
+                    other => bail!("Unknown time unit '{other}' in '{name}'; use ms, us or ns"),
+                };
+                DataType::Datetime(unit, zone.filter(|z| !z.is_empty()).map(Into::into))
+            } else if let Some(args) = lower.strip_prefix("decimal(").and_then(|s| s.strip_suffix(')')) {
+                let parse = |s: &str| s.trim().parse::<usize>().with_context(|| format!("Invalid decimal type '{name}'"));
+                match args.split_once(',') {
+                    Some((precision, scale)) => decimal(parse(precision)?, parse(scale)?)?,
+                    None => decimal(parse(args)?, 0)?,
+                }
+            } else if lower == "decimal" {
+                bail!("A decimal needs a precision and scale, as in decimal(12,2)");
+            } else {
+                bail!("Unknown type '{name}'");
+            }
+        }
+    };
+    Ok(dtype)
+}
+
+fn decimal(precision: usize, scale: usize) -> Result<DataType> {
+    if !(1..=38).contains(&precision) || scale > precision {
+        bail!("A decimal needs a precision of 1 to 38 and a scale no larger than it, not ({precision}, {scale})");
+    }
+    Ok(DataType::Decimal(Some(precision), Some(scale)))
+}
+
+/// Convert a column to `dtype`, leaving a null wherever a value does not
+/// fit. Text is parsed, with `format` for dates, datetimes and times;
+/// floats only become integers when they have no fractional part.
+pub fn convert(column: &Column, dtype: &DataType, format: Option<&str>) -> PolarsResult<Column> {
+    if column.dtype() == dtype {
+        return Ok(column.clone());
+    }
+    let name = column.name().clone();
+    let out = match (column.dtype(), dtype) {
+        (DataType::String, DataType::Date) => column.str()?.as_date(format, false)?.into_column(),
+        (DataType::String, DataType::Time) => column.str()?.as_time(format, false)?.into_column(),
+        (DataType::String, DataType::Datetime(unit, zone)) => {
+            let tz_aware = format.is_some_and(|f| f.contains("%z") || f.contains("%:z"));
+            let ambiguous = StringChunked::from_slice("".into(), &["raise"]);
+            column
+                .str()?
+                .as_datetime(format, *unit, false, tz_aware, zone.as_ref(), &ambiguous)?
+                .into_column()
+        }
+        (DataType::String, DataType::Boolean) => column
+            .str()?
+            .iter()
+            .map(|value| match value.map(|v| v.trim().to_ascii_lowercase()) {
+                Some(v) if v == "true" => Some(true),
+                Some(v) if v == "false" => Some(false),
+                _ => None,
+            })
+            .collect::<BooleanChunked>()
+            .into_column(),
+        (from, to) if from.is_float() && to.is_integer() => {
+            let out = column.cast(dtype)?;
+            // truncated values do not survive the round trip
+            let exact = out.cast(from)?.equal_missing(column)?;
+            out.as_materialized_series().zip_with(&exact, &Series::full_null("".into(), out.len(), dtype))?.into_column()
+        }
+        _ => column.cast(dtype)?,
+    };
+    Ok(out.with_name(name))
+}
+
+/// How many values of `input` became null in `output`, and the first of them.
+pub fn conversion_failures(input: &Column, output: &Column) -> PolarsResult<(usize, Option<String>)> {
+    let failed = input.is_not_null() & output.is_null();
+    let count = failed.num_trues();
+    let example = match failed.into_iter().position(|f| f == Some(true)) {
+        Some(i) => Some(match input.get(i)? {
+            AnyValue::String(s) => s.to_string(),
+            other => other.to_string(),
+        }),
+        None => None,
+    };
+    Ok((count, example))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn schema_file(suffix: &str, contents: &str) -> Result<NamedTempFile> {
+        let mut file = tempfile::Builder::new().suffix(suffix).tempfile()?;
+        file.write_all(contents.as_bytes())?;
+        Ok(file)
+    }
+
+    #[test]
+    fn test_parse_dtype() -> Result<()> {
+        assert_eq!(parse_dtype("Int32")?, DataType::Int32);
+        assert_eq!(parse_dtype("string")?, DataType::String);
+        assert_eq!(parse_dtype("datetime[ms, UTC]")?, DataType::Datetime(TimeUnit::Milliseconds, Some("UTC".into())));
+        assert_eq!(parse_dtype("datetime[ns,Europe/Paris]")?, DataType::Datetime(TimeUnit::Nanoseconds, Some("Europe/Paris".into())));
+        assert_eq!(parse_dtype("datetime")?, DataType::Datetime(TimeUnit::Microseconds, None));
+        assert_eq!(parse_dtype("decimal(12, 2)")?, DataType::Decimal(Some(12), Some(2)));
+        assert!(parse_dtype("decimal").is_err());
+        assert!(parse_dtype("decimal(2,4)").is_err());
+        assert!(parse_dtype("datetime[s]").is_err());
+        assert!(parse_dtype("varchar").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_toml_json_and_avro() -> Result<()> {
+        let toml = schema_file(
+            ".toml",
+            "[[columns]]\nname = \"zip\"\ntype = \"string\"\nnullable = false\n\n[[columns]]\nname = \"price\"\ntype = \"decimal\"\nprecision = 10\nscale = 2\n",
+        )?;
+        let schema = TableSchema::load(toml.path().to_str().unwrap())?;
+        assert_eq!(schema.columns.len(), 2);
+        assert!(!schema.columns[0].nullable);
+        assert_eq!(schema.columns[1].dtype, DataType::Decimal(Some(10), Some(2)));
+
+        let json = schema_file(".json", r#"{"columns": [{"name": "day", "type": "date", "format": "%d/%m/%Y"}]}"#)?;
+        let schema = TableSchema::load(json.path().to_str().unwrap())?;
+        assert_eq!(schema.columns[0].format.as_deref(), Some("%d/%m/%Y"));
+        assert!(schema.columns[0].nullable);
+
+        let avro = schema_file(
+            ".avsc",
+            r#"{"type": "record", "name": "Row", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "day", "type": ["null", {"type": "int", "logicalType": "date"}]}
+            ]}"#,
+        )?;
+        let schema = TableSchema::load(avro.path().to_str().unwrap())?;
+        assert_eq!((schema.columns[0].dtype.clone(), schema.columns[0].nullable), (DataType::Int64, false));
+        assert_eq!((schema.columns[1].dtype.clone(), schema.columns[1].nullable), (DataType::Date, true));
+
+        let twice = schema_file(".toml", "[[columns]]\nname = \"a\"\ntype = \"int64\"\n[[columns]]\nname = \"a\"\ntype = \"int64\"\n")?;
+        assert!(TableSchema::load(twice.path().to_str().unwrap()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_and_count_failures() -> Result<()> {
+        let text = Column::new("v".into(), [Some("1"), Some("x"), None, Some("4")]);
+        let ints = convert(&text, &DataType::Int64, None)?;
+        assert_eq!(ints.dtype(), &DataType::Int64);
+        assert_eq!(conversion_failures(&text, &ints)?, (1, Some("x".to_string())));
+
+        let days = Column::new("d".into(), ["31/12/2024", "31/02/2024"]);
+        let dates = convert(&days, &DataType::Date, Some("%d/%m/%Y"))?;
+        assert_eq!(conversion_failures(&days, &dates)?, (1, Some("31/02/2024".to_string())));
+
+        let floats = Column::new("f".into(), [1.0, 2.5]);
+        let ints = convert(&floats, &DataType::Int32, None)?;
+        assert_eq!(conversion_failures(&floats, &ints)?.0, 1);
+
+        let flags = Column::new("b".into(), ["TRUE", "false", "maybe"]);
+        let bools = convert(&flags, &DataType::Boolean, None)?;
+        assert_eq!(conversion_failures(&flags, &bools)?, (1, Some("maybe".to_string())));
+        Ok(())
+    }
+}
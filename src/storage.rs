@@ -1,3 +1,4 @@
+use crate::format::Format;
 use anyhow::{anyhow, bail, Result};
 use futures::StreamExt;
 use object_store::{
@@ -7,18 +8,178 @@ use object_store::{
     local::LocalFileSystem,
     path::Path
     ,
-    ObjectStore,
+    GetOptions, GetRange, ObjectStore,
 };
-use std::fs::{read, File};
-use std::io::{Error as IoError, ErrorKind, Write};
+use std::fs::{read, File, OpenOptions};
+use std::io::{self, Error as IoError, ErrorKind, Read, Write};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tempfile::NamedTempFile;
 use bytes::Bytes;
+use once_cell::sync::Lazy;
 use url::Url;
 
+/// Path standing for stdin when reading and stdout when writing. Stdin is
+/// spooled to a temporary file like a remote source, and output is written
+/// to a spool that `finish_write` copies to stdout, so every reader and
+/// writer supports it.
+pub const STDIO_PATH: &str = "-";
+
+/// Spools backing lazy scans. A scan only opens its file when the query runs,
+/// which is after the `Storage` that downloaded it has been dropped, so the
+/// spool is parked here until `release_spools` is called.
+static RETAINED_SPOOLS: Lazy<Mutex<Vec<NamedTempFile>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Park a spool until `release_spools` and return its path, for readers that
+/// rewrite their input into a temporary file before scanning it.
+pub fn retain_spool(spool: NamedTempFile) -> Result<PathBuf> {
+    let path = spool.path().to_path_buf();
+    RETAINED_SPOOLS
+        .lock()
+        .map_err(|_| anyhow!("Spool registry poisoned"))?
+        .push(spool);
+    Ok(path)
+}
+
+/// The files an input path stands for, in name order: the matches of a glob
+/// such as `data/2024-*.csv` or `s3://bucket/prefix/*.parquet`, the files of
+/// `format` under a directory or under an object store prefix ending in `/`,
+/// or else the path itself. Listings skip hidden files and files starting
+/// with `_`, such as `_SUCCESS` markers. A directory is searched for files of
+/// any known format when `format` is `None`.
+///
+/// A local file that exists is never a glob, even with `[` or `?` in its
+/// name, and the query string of a URL, such as a SAS token, is ignored.
+pub fn expand_input(path: &str, format: Option<Format>) -> Result<Vec<String>> {
+    let url = Url::parse(path).ok().filter(|url| url.scheme() != "file");
+    let (is_glob, is_dir) = match &url {
+        Some(url) => (url.path().contains(WILDCARDS), url.path().ends_with('/')),
+        None => {
+            let local = std::path::Path::new(path);
+            (path.contains(WILDCARDS) && !local.is_file(), path.ends_with('/') || local.is_dir())
+        }
+    };
+    if path == STDIO_PATH || !(is_glob || is_dir) {
+        return Ok(vec![path.to_string()]);
+    }
+
+    let mut files = match url {
+        Some(url) => list_remote(path, &url, is_glob)?,
+        None if is_glob => {
+            let mut files = Vec::new();
+            for entry in glob::glob(path)? {
+                let entry = entry?;
+                if entry.is_file() && is_listed(&entry.to_string_lossy()) {
+                    files.push(entry.to_string_lossy().into_owned());
+                }
+            }
+            files
+        }
+        None => {
+            let mut files = Vec::new();
+            list_local_dir(std::path::Path::new(path), &mut files)?;
+            files
+        }
+    };
+    if !is_glob {
+        // a glob names the files it wants; a directory may hold others
+        files.retain(|file| Format::from_extension(file).is_some_and(|found| format.is_none_or(|f| f == found)));
+    }
+    if files.is_empty() {
+        bail!("No input files found at {path}");
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The characters that make a path a glob.
+const WILDCARDS: [char; 3] = ['*', '?', '['];
+
+/// Whether a listed file is data rather than hidden or a marker like `_SUCCESS`.
+fn is_listed(path: &str) -> bool {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    !name.starts_with('.') && !name.starts_with('_')
+}
+
+fn list_local_dir(dir: &std::path::Path, files: &mut Vec<String>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.to_string_lossy().into_owned();
+        if !is_listed(&name) {
+            continue;
+        }
+        if path.is_dir() {
+            list_local_dir(&path, files)?;
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// List the objects under the prefix of a glob or directory URL, as URLs.
+fn list_remote(path: &str, url: &Url, is_glob: bool) -> Result<Vec<String>> {
+    let (store, _, _) = Storage::detect_object_store(path)?;
+    let key = url.path().trim_start_matches('/');
+    // list from the last directory before the first wildcard
+    let literal = &key[..key.find(WILDCARDS).unwrap_or(key.len())];
+    let prefix = &literal[..literal.rfind('/').map_or(0, |i| i + 1)];
+    let pattern = glob::Pattern::new(key)?;
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let keys = rt.block_on(async {
+        let prefix = (!prefix.is_empty()).then(|| Path::from(prefix.trim_end_matches('/')));
+        let mut listing = store.list(prefix.as_ref());
+        let mut keys = Vec::new();
+        while let Some(meta) = listing.next().await {
+            let meta = meta.map_err(|e| anyhow!("Error listing {path}: {e}"))?;
+            keys.push(meta.location.to_string());
+        }
+        Ok::<_, anyhow::Error>(keys)
+    })?;
+
+    let host = url.host_str().unwrap_or_default();
+    // each object is read with the same query string, such as a SAS token
+    let query = url.query().map(|query| format!("?{query}")).unwrap_or_default();
+    Ok(keys
+        .into_iter()
+        .filter(|key| key.get(prefix.len()..).unwrap_or_default().split('/').all(is_listed))
+        .filter(|key| !is_glob || pattern.matches_with(key, options))
+        .map(|key| format!("{}://{host}/{key}{query}", url.scheme()))
+        .collect())
+}
+
+/// Up to the first `len` bytes of a remote object, by a range request.
+fn remote_head(store: &dyn ObjectStore, location: &Path, len: usize) -> Result<Bytes> {
+    let options = GetOptions {
+        range: Some(GetRange::Bounded(0..len)),
+        ..Default::default()
+    };
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    rt.block_on(async {
+        let head = store.get_opts(location, options).await?.bytes().await?;
+        Ok(head)
+    })
+}
+
+/// Delete every spool retained for lazy scans. Call once the query has run.
+pub fn release_spools() {
+    if let Ok(mut spools) = RETAINED_SPOOLS.lock() {
+        spools.clear();
+    }
+}
+
 pub struct Storage {
     is_local: bool,
+    is_stdio: bool,
     store: Option<Arc<dyn ObjectStore>>,
     location: Option<Path>,
     local_path: PathBuf,
@@ -26,14 +187,30 @@ pub struct Storage {
     downloaded: bool,
     wrote_to_spool: bool,
     wrote_spool_path: Option<PathBuf>,
+    retained_path: Option<PathBuf>,
 }
 
 impl Storage {
     pub fn new(path: &str) -> Result<Self> {
+        if path == STDIO_PATH {
+            return Ok(Self {
+                is_local: false,
+                is_stdio: true,
+                store: None,
+                location: None,
+                local_path: PathBuf::new(),
+                spool: None,
+                downloaded: false,
+                wrote_to_spool: false,
+                wrote_spool_path: None,
+                retained_path: None,
+            });
+        }
         let (store, is_local, loc) = Self::detect_object_store(path)?;
         if is_local {
             Ok(Self {
                 is_local: true,
+                is_stdio: false,
                 store: None,
                 location: None,
                 local_path: PathBuf::from(path),
@@ -41,10 +218,12 @@ impl Storage {
                 downloaded: false,
                 wrote_to_spool: false,
                 wrote_spool_path: None,
+                retained_path: None,
             })
         } else {
             Ok(Self {
                 is_local: false,
+                is_stdio: false,
                 store: Some(store),
                 location: Some(loc),
                 local_path: PathBuf::new(),
@@ -52,6 +231,7 @@ impl Storage {
                 downloaded: false,
                 wrote_to_spool: false,
                 wrote_spool_path: None,
+                retained_path: None,
             })
         }
     }
@@ -71,8 +251,66 @@ impl Storage {
         }
     }
 
+    /// Return the target opened for reading and appending. Writes always land
+    /// after the existing contents, and an empty file means nothing to extend.
+    /// - If local, the file is created if missing.
+    /// - If remote, the current object (if any) is downloaded into a spool
+    ///   that `finish_write` uploads.
+    pub fn get_append_file(&mut self) -> Result<File> {
+        if self.is_stdio {
+            bail!("Cannot append to stdout");
+        }
+        let path = if self.is_local {
+            self.local_path.clone()
+        } else {
+            if self.remote_exists()? {
+                self.download_remote()?;
+            } else {
+                self.spool = Some(NamedTempFile::new()?);
+            }
+            let spool_path = self.spool.as_ref().unwrap().path().to_path_buf();
+            self.wrote_to_spool = true;
+            self.wrote_spool_path = Some(spool_path.clone());
+            spool_path
+        };
+        let f = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        Ok(f)
+    }
+
+    /// The target is an existing local directory, appended to as a dataset of
+    /// part files rather than as a single file.
+    pub fn is_local_dir(&self) -> bool {
+        self.is_local && self.local_path.is_dir()
+    }
+
+    /// The path points at the local filesystem rather than an object store.
+    pub fn is_local(&self) -> bool {
+        self.is_local
+    }
+
+    /// The local path of the target. Only meaningful if `is_local`.
+    pub fn local_path(&self) -> &std::path::Path {
+        &self.local_path
+    }
+
+    /// Return a local path for Polars sinks, which open the file themselves.
+    /// - If local, this is the target path.
+    /// - If remote, this is a fresh spool that `finish_write` uploads.
+    pub fn get_target_path(&mut self) -> Result<PathBuf> {
+        if self.is_local {
+            return Ok(self.local_path.clone());
+        }
+        let spool = NamedTempFile::new()?;
+        let spool_path = spool.path().to_path_buf();
+        self.spool = Some(spool);
+        self.wrote_to_spool = true;
+        self.wrote_spool_path = Some(spool_path.clone());
+        Ok(spool_path)
+    }
+
     /// Call this once Polars has finished writing. If remote, we do a single
-    /// `store.put(...)` of the spool file contents.
+    /// `store.put(...)` of the spool file contents; for stdout, the spool is
+    /// copied to it.
     pub fn finish_write(&mut self) -> Result<()> {
         if self.is_local {
             return Ok(());
@@ -86,6 +324,13 @@ impl Storage {
             .as_ref()
             .ok_or_else(|| anyhow!("No spool path found"))?;
 
+        if self.is_stdio {
+            let mut stdout = io::stdout().lock();
+            io::copy(&mut File::open(spool_path)?, &mut stdout)?;
+            stdout.flush()?;
+            return Ok(());
+        }
+
         let store = self
             .store
             .as_ref()
@@ -108,7 +353,7 @@ impl Storage {
             store
                 .put(location, payload)
                 .await
-                .map_err(|e| IoError::new(ErrorKind::Other, format!("Error uploading spool: {e}")))
+                .map_err(|e| IoError::other(format!("Error uploading spool: {e}")))
         })?;
 
         Ok(())
@@ -117,7 +362,7 @@ impl Storage {
 
     /// Return a local File handle for reading.
     /// - If local, we open local_path.
-    /// - If remote, spool once, then open spool file.
+    /// - If remote or stdin, spool once, then open spool file.
     pub fn get_source_file(&mut self) -> Result<File> {
         if self.is_local {
             // just open local_path
@@ -127,7 +372,7 @@ impl Storage {
 
         // If remote => spool if not already
         if !self.downloaded {
-            self.download_remote()?;
+            self.download_source()?;
             self.downloaded = true;
         }
 
@@ -143,6 +388,80 @@ impl Storage {
         Ok(f)
     }
 
+    /// Return a local path for lazy scans.
+    /// - If local, we check local_path exists and hand it back.
+    /// - If remote or stdin, spool once and retain the spool until `release_spools`.
+    pub fn get_source_path(&mut self) -> Result<PathBuf> {
+        if self.is_local {
+            if !self.local_path.exists() {
+                bail!("Input file not found: {}", self.local_path.display());
+            }
+            return Ok(self.local_path.clone());
+        }
+
+        if let Some(path) = &self.retained_path {
+            return Ok(path.clone());
+        }
+        if !self.downloaded {
+            self.download_source()?;
+            self.downloaded = true;
+        }
+        let spool = self
+            .spool
+            .take()
+            .ok_or_else(|| anyhow!("No spool file found"))?;
+        let spool_path = retain_spool(spool)?;
+        self.retained_path = Some(spool_path.clone());
+        Ok(spool_path)
+    }
+
+    /// Up to the first `len` bytes of the source, such as a magic number.
+    /// Remote objects are read with a range request rather than downloaded.
+    pub fn read_head(&self, len: usize) -> Result<Vec<u8>> {
+        let mut head = Vec::with_capacity(len);
+        match (&self.store, &self.location) {
+            _ if self.is_stdio => bail!("Cannot read ahead in stdin"),
+            (Some(store), Some(location)) => head.extend_from_slice(&remote_head(store.as_ref(), location, len)?),
+            _ => {
+                File::open(&self.local_path)?.take(len as u64).read_to_end(&mut head)?;
+            }
+        }
+        Ok(head)
+    }
+
+    fn remote_exists(&self) -> Result<bool> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or_else(|| anyhow!("No store found (remote)"))?;
+        let location = self
+            .location
+            .as_ref()
+            .ok_or_else(|| anyhow!("No location found (remote)"))?;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        rt.block_on(async {
+            match store.head(location).await {
+                Ok(_) => Ok(true),
+                Err(object_store::Error::NotFound { .. }) => Ok(false),
+                Err(e) => Err(anyhow!("Error checking remote object: {e}")),
+            }
+        })
+    }
+
+    /// Spool the source: stdin, or the remote object.
+    fn download_source(&mut self) -> Result<()> {
+        if !self.is_stdio {
+            return self.download_remote();
+        }
+        let spool = NamedTempFile::new()?;
+        io::copy(&mut io::stdin().lock(), &mut spool.as_file())?;
+        self.spool = Some(spool);
+        Ok(())
+    }
+
     fn download_remote(&mut self) -> Result<()> {
         // Must have store+location
         let store = self
@@ -168,7 +487,7 @@ impl Storage {
                     let mut stream = getres.into_stream();
                     while let Some(chunk_res) = stream.next().await {
                         let chunk = chunk_res.map_err(|e| {
-                            std::io::Error::new(ErrorKind::Other, format!("Download error: {e}"))
+                            IoError::other(format!("Download error: {e}"))
                         })?;
                         sink.write_all(&chunk)?;
                     }
@@ -237,3 +556,63 @@ impl Drop for Storage {
         // spool is auto-removed by NamedTempFile
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_expand_input() -> Result<()> {
+        let dir = TempDir::new()?;
+        std::fs::create_dir(dir.path().join("2024"))?;
+        for name in ["b.csv", "a.csv", "notes.txt", "_SUCCESS", ".hidden.csv", "2024/c.csv", "2024/d.json.gz"] {
+            std::fs::write(dir.path().join(name), "x\n1\n")?;
+        }
+        let root = dir.path().to_str().unwrap();
+        let names = |files: Vec<String>| -> Vec<String> {
+            files.into_iter().map(|f| f[root.len() + 1..].replace('\\', "/")).collect()
+        };
+
+        assert_eq!(names(expand_input(&format!("{root}/*.csv"), Some(Format::Csv))?), ["a.csv", "b.csv"]);
+        assert_eq!(names(expand_input(&format!("{root}/*.txt"), Some(Format::Csv))?), ["notes.txt"]);
+        assert_eq!(names(expand_input(root, Some(Format::Csv))?), ["2024/c.csv", "a.csv", "b.csv"]);
+        assert_eq!(names(expand_input(root, Some(Format::Json))?), ["2024/d.json.gz"]);
+        assert_eq!(names(expand_input(root, None)?), ["2024/c.csv", "2024/d.json.gz", "a.csv", "b.csv"]);
+        assert!(expand_input(root, Some(Format::Parquet)).is_err());
+
+        let single = format!("{root}/a.csv");
+        assert_eq!(expand_input(&single, Some(Format::Csv))?, [single.as_str()]);
+        assert_eq!(expand_input(STDIO_PATH, Some(Format::Csv))?, [STDIO_PATH]);
+        assert!(expand_input(&format!("{root}/*.parquet"), Some(Format::Parquet)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_head_reads_a_range() -> Result<()> {
+        let store = object_store::memory::InMemory::new();
+        let rt = tokio::runtime::Builder::new_current_thread().build()?;
+        rt.block_on(async {
+            store.put(&Path::from("data/feed"), Bytes::from_static(b"PAR1 and the rest").into()).await?;
+            store.put(&Path::from("data/tiny"), Bytes::from_static(b"a,b").into()).await
+        })?;
+        assert_eq!(remote_head(&store, &Path::from("data/feed"), 6)?, &b"PAR1 a"[..]);
+        assert_eq!(remote_head(&store, &Path::from("data/tiny"), 6)?, &b"a,b"[..]);
+        assert!(remote_head(&store, &Path::from("data/missing"), 6).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_input_names_that_look_like_globs() -> Result<()> {
+        let dir = TempDir::new()?;
+        let bracketed = dir.path().join("a[1].csv");
+        std::fs::write(&bracketed, "x\n1\n")?;
+        let bracketed = bracketed.to_str().unwrap();
+        assert_eq!(expand_input(bracketed, Some(Format::Csv))?, [bracketed]);
+
+        // only the path of a URL can hold wildcards, not a SAS token
+        let signed = "az://container/data/file.csv?sv=2024-01-01&sig=abc%3D";
+        assert_eq!(expand_input(signed, Some(Format::Csv))?, [signed]);
+        Ok(())
+    }
+}
@@ -56,7 +56,35 @@ pub mod helpers {
         let mut temp_file = create_temp_file()?;
         let df = create_sample_df()?;
         writer_fn(&mut temp_file, &df)?;
-        let read_df = reader.read_data(temp_file.path().to_str().unwrap())?;
+        let read_df = reader.read_data(temp_file.path().to_str().unwrap())?.collect()?;
+        assert_dataframes_equal(&df, &read_df);
+        Ok(())
+    }
+
+
+    pub fn test_write_then_scan<WriterFn>(writer_fn: WriterFn, reader: Box<dyn FromFile>) -> Result<()>
+    where
+        WriterFn: FnOnce(&mut NamedTempFile, &DataFrame) -> Result<()>,
+    {
+        let mut temp_file = create_temp_file()?;
+        let df = create_sample_df()?;
+        writer_fn(&mut temp_file, &df)?;
+        let read_df = reader.read_data(temp_file.path().to_str().unwrap())?.with_streaming(true).collect()?;
+        assert_dataframes_equal(&df, &read_df);
+        Ok(())
+    }
+
+
+    pub fn test_sink_read_compare<W, R>(writer: &W, read_fn: R) -> Result<()>
+    where
+        W: ToFile,
+        R: Fn(&str) -> Result<DataFrame, Box<dyn Error>>,
+    {
+        let temp_file = NamedTempFile::new()?;
+        let file_path = temp_file.path().to_str().unwrap();
+        let df = create_sample_df()?;
+        writer.sink_data(file_path, df.clone().lazy(), false)?;
+        let read_df = read_fn(file_path).unwrap();
         assert_dataframes_equal(&df, &read_df);
         Ok(())
     }
@@ -108,4 +136,54 @@ pub mod helpers {
 
         Ok(())
     }
+
+    pub fn test_write_append<W, R>(writer: &W, reader: R) -> Result<()>
+    where
+        W: ToFile,
+        R: Fn(&str) -> Result<DataFrame, Box<dyn Error>>,
+    {
+        let temp_file = NamedTempFile::new()?;
+        let file_path = temp_file.path().to_str().unwrap();
+        let df = create_sample_df()?;
+        // the first append lands in an empty file and must write it whole
+        writer.write_data(file_path, &df, true)?;
+        writer.write_data(file_path, &df, true)?;
+        let df_read = reader(file_path).unwrap();
+        assert_dataframes_equal(&df.vstack(&df)?, &df_read);
+        Ok(())
+    }
+
+    pub fn test_sink_append<W, R>(writer: &W, reader: R) -> Result<()>
+    where
+        W: ToFile,
+        R: Fn(&str) -> Result<DataFrame, Box<dyn Error>>,
+    {
+        let temp_file = NamedTempFile::new()?;
+        let file_path = temp_file.path().to_str().unwrap();
+        let df = create_sample_df()?;
+        writer.write_data(file_path, &df, false)?;
+        writer.sink_data(file_path, df.clone().lazy(), true)?;
+        let df_read = reader(file_path).unwrap();
+        assert_dataframes_equal(&df.vstack(&df)?, &df_read);
+        Ok(())
+    }
+
+    pub fn test_append_schema_mismatch<W, R>(writer: &W, reader: R) -> Result<()>
+    where
+        W: ToFile,
+        R: Fn(&str) -> Result<DataFrame, Box<dyn Error>>,
+    {
+        let temp_file = NamedTempFile::new()?;
+        let file_path = temp_file.path().to_str().unwrap();
+        let df = create_sample_df()?;
+        writer.write_data(file_path, &df, false)?;
+        let other = df! {
+            "name" => &["Carol"],
+            "score" => &[1.5]
+        }?;
+        assert!(writer.write_data(file_path, &other, true).is_err());
+        // the rejected append leaves the target untouched
+        assert_dataframes_equal(&df, &reader(file_path).unwrap());
+        Ok(())
+    }
 }
@@ -1,29 +1,225 @@
+use super::check_append_columns;
+use crate::avro::{avro_type, field_dtype, is_uuid_column, load_schema, parse_record, to_physical, value_dtype, AvroCodec, Header};
 use crate::storage::Storage;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
+use avro_schema::file::Block;
+use avro_schema::schema::{Field as AvroField, Record, Schema as AvroSchema, StringLogical};
 use polars::prelude::*;
-use polars_io::avro::AvroWriter;
-use std::fs::File;
+use polars_arrow::io::avro::write::{new_serializer, serialize, to_record};
+use serde_json::Value;
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
 
+/// The name of a derived record; Avro names cannot be empty.
+const DEFAULT_RECORD_NAME: &str = "Record";
+
+/// Avro output settings.
+///
+/// - `record_name`, `namespace`: name the top-level record, e.g. for a
+///   schema registry; a derived schema's record is named `Record` otherwise
+/// - `field_docs`: `doc` attributes for fields, by column name
+/// - `schema`: an `.avsc` file; the frame is coerced to its fields, and the
+///   schema is written as given
 #[derive(Default)]
-pub struct AvroWriterImpl;
+pub struct AvroWriterImpl {
+    pub codec: Option<AvroCodec>,
+    pub record_name: Option<String>,
+    pub namespace: Option<String>,
+    pub field_docs: Vec<(String, String)>,
+    pub schema: Option<String>,
+}
 
 impl super::ToFile for AvroWriterImpl {
-    fn write_data(&self, path: &str, df: &DataFrame, _append: bool) -> Result<()> {
+    fn write_data(&self, path: &str, df: &DataFrame, append: bool) -> Result<()> {
+        let (df, schema) = self.prepare(df)?;
+        let record = parse_record(&schema)?;
         let mut storage = Storage::new(path)?;
-        let file: File = storage.get_target_file()?;
-        let mut df_to_write = df.clone();
-        AvroWriter::new(file).finish(&mut df_to_write)?;
+        let mut file = if append { storage.get_append_file()? } else { storage.get_target_file()? };
+
+        if append && file.metadata()?.len() > 0 {
+            // new blocks use the file's own codec and sync marker, so the
+            // header stays valid
+            file.seek(SeekFrom::Start(0))?;
+            let existing = Header::read(&mut BufReader::new(&mut file)).context("Cannot append to invalid Avro file")?;
+            check_append_fields(&existing.record()?, &record)?;
+            write_records(&mut file, &existing, &record, &df)?;
+        } else {
+            let header = Header::new(schema, self.codec);
+            let mut writer = BufWriter::new(&mut file);
+            header.write(&mut writer)?;
+            write_records(&mut writer, &header, &record, &df)?;
+        }
         storage.finish_write()?;
         Ok(())
     }
 }
 
+impl AvroWriterImpl {
+    /// The frame to write and the schema to write it with: the `.avsc` file
+    /// if given, otherwise one derived from the frame, then named and
+    /// documented as configured.
+    fn prepare(&self, df: &DataFrame) -> Result<(DataFrame, Value)> {
+        let (df, mut schema) = match &self.schema {
+            Some(path) => {
+                let schema = load_schema(path)?;
+                (coerce(df, &parse_record(&schema)?)?, schema)
+            }
+            None => {
+                let (df, record) = derive_record(df)?;
+                (df, serde_json::to_value(AvroSchema::Record(record))?)
+            }
+        };
+
+        let record = schema.as_object_mut().ok_or_else(|| anyhow!("The Avro schema must be a record"))?;
+        if let Some(name) = &self.record_name {
+            check_name(name, "record name")?;
+            record.insert("name".to_string(), name.clone().into());
+        }
+        if let Some(namespace) = &self.namespace {
+            namespace.split('.').try_for_each(|part| check_name(part, "namespace"))?;
+            record.insert("namespace".to_string(), namespace.clone().into());
+        }
+        for (column, doc) in &self.field_docs {
+            let field = record
+                .get_mut("fields")
+                .and_then(Value::as_array_mut)
+                .and_then(|fields| fields.iter_mut().find(|f| f["name"] == column.as_str()))
+                .ok_or_else(|| anyhow!("Cannot document Avro field '{column}': there is no such column"))?;
+            field["doc"] = doc.clone().into();
+        }
+        Ok((df, schema))
+    }
+}
+
+/// Avro names start with a letter or `_`, followed by letters, digits or `_`.
+fn check_name(name: &str, what: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        bail!("Invalid Avro {what} '{name}'");
+    }
+    Ok(())
+}
+
+/// A record with a nullable field per column, typed by `avro_type` or as
+/// uuid for columns of UUIDs, and the frame cast to the fields' types. Lists and structs keep Polars' mapping.
+fn derive_record(df: &DataFrame) -> Result<(DataFrame, Record)> {
+    let mut columns = Vec::with_capacity(df.width());
+    let mut fields = Vec::with_capacity(df.width());
+    for column in df.get_columns() {
+        let name = column.name().to_string();
+        let value = match is_uuid_column(column)? {
+            true => Some(AvroSchema::String(Some(StringLogical::Uuid))),
+            false => avro_type(column.dtype()),
+        };
+        let schema = match value {
+            Some(value) => {
+                let dtype = value_dtype(&name, &value)?;
+                columns.push(
+                    column
+                        .strict_cast(&dtype)
+                        .with_context(|| format!("Cannot write column '{name}' as {dtype} in Avro"))?,
+                );
+                AvroSchema::Union(vec![AvroSchema::Null, value])
+            }
+            None => {
+                let arrow = ArrowSchema::from_iter([column.field().to_arrow(CompatLevel::oldest())]);
+                columns.push(column.clone());
+                to_record(&arrow, String::new())?.fields.remove(0).schema
+            }
+        };
+        fields.push(AvroField::new(name, schema));
+    }
+    Ok((DataFrame::new(columns)?, Record::new(DEFAULT_RECORD_NAME.to_string(), fields)))
+}
+
+/// Conform the frame to the record: columns in field order, cast to the
+/// field types, with no nulls in fields that are not nullable.
+fn coerce(df: &DataFrame, record: &Record) -> Result<DataFrame> {
+    if let Some(extra) = df
+        .get_column_names()
+        .into_iter()
+        .find(|name| !record.fields.iter().any(|f| f.name == name.as_str()))
+    {
+        bail!("Column '{extra}' is not a field of the Avro schema");
+    }
+
+    let columns = record
+        .fields
+        .iter()
+        .map(|field| {
+            let (dtype, nullable) = field_dtype(&field.name, &field.schema)?;
+            let column = df
+                .column(&field.name)
+                .map_err(|_| anyhow!("The Avro schema has a field '{}' but the data has no such column", field.name))?;
+            let column = column
+                .strict_cast(&dtype)
+                .with_context(|| format!("Cannot convert column '{}' from {} to {dtype}", field.name, column.dtype()))?;
+            if !nullable && column.null_count() > 0 {
+                bail!(
+                    "Column '{}' has {} null values but its Avro field is not nullable",
+                    field.name,
+                    column.null_count()
+                );
+            }
+            Ok(column)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Encode the frame as blocks of records, one per chunk.
+fn write_records<W: Write>(writer: &mut W, header: &Header, record: &Record, df: &DataFrame) -> Result<()> {
+    let df = df
+        .get_columns()
+        .iter()
+        .zip(&record.fields)
+        .map(|(column, field)| to_physical(column, &field.schema))
+        .collect::<Result<Vec<_>>>()?;
+    let df = DataFrame::new(df)?;
+    for chunk in df.iter_chunks(CompatLevel::oldest(), true) {
+        let mut serializers = chunk
+            .arrays()
+            .iter()
+            .zip(&record.fields)
+            .map(|(array, field)| new_serializer(array.as_ref(), &field.schema))
+            .collect::<Vec<_>>();
+        let mut block = Block::new(chunk.len(), Vec::new());
+        serialize(&mut serializers, &mut block);
+        let data = header.compress(block.number_of_rows, block.data)?;
+        header.write_block(writer, block.number_of_rows, &data)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn check_append_fields(existing: &Record, new: &Record) -> Result<()> {
+    let names = |record: &Record| record.fields.iter().map(|f| f.name.clone()).collect::<Vec<_>>();
+    check_append_columns(&names(existing), &names(new))?;
+    for (old, new) in existing.fields.iter().zip(&new.fields) {
+        if old.schema != new.schema {
+            bail!(
+                "Cannot append: column '{}' is {:?} in the target but {:?} in the new data",
+                old.name,
+                old.schema,
+                new.schema
+            );
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_utils::helpers::*;
-    use polars_io::avro::AvroReader;
+    use crate::from::avro::AvroReaderImpl;
+    use crate::from::FromFile;
+    use crate::to::ToFile;
+    use polars_io::avro::{AvroReader, AvroWriter};
     use std::error::Error;
+    use std::fs::File;
+    use std::io::Write;
 
     fn read_fn(path: &str) -> Result<DataFrame, Box<dyn Error>> {
         let file = File::open(path)?;
@@ -41,6 +237,12 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sink_valid_file() -> Result<()> {
+        test_sink_read_compare(&writer(), read_fn)?;
+        Ok(())
+    }
+
     #[test]
     fn test_write_to_nonexistent_directory() -> Result<()> {
         test_write_should_fail(&writer());
@@ -52,4 +254,286 @@ mod tests {
         test_write_overwrite(&writer(), read_fn)?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_write_append_file() -> Result<()> {
+        test_write_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_append_file() -> Result<()> {
+        test_sink_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_rejects_schema_mismatch() -> Result<()> {
+        test_append_schema_mismatch(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_keeps_existing_codec() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let mut df = create_sample_df()?;
+        AvroWriter::new(File::create(path)?)
+            .with_compression(Some(polars_io::avro::AvroCompression::Deflate))
+            .finish(&mut df)?;
+
+        writer().write_data(path, &df, true)?;
+        assert_dataframes_equal(&df.vstack(&df)?, &read_fn(path).unwrap());
+        Ok(())
+    }
+
+    fn header(path: &str) -> Result<Header> {
+        Header::read(&mut File::open(path)?)
+    }
+
+    fn write_avsc(schema: &Value) -> Result<tempfile::NamedTempFile> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(schema.to_string().as_bytes())?;
+        Ok(file)
+    }
+
+    #[test]
+    fn test_write_codecs() -> Result<()> {
+        let df = create_sample_df()?;
+        for codec in [AvroCodec::Deflate, AvroCodec::Snappy, AvroCodec::Zstd] {
+            let temp_file = tempfile::NamedTempFile::new()?;
+            let path = temp_file.path().to_str().unwrap();
+            let writer = AvroWriterImpl {
+                codec: Some(codec),
+                ..writer()
+            };
+            writer.write_data(path, &df, false)?;
+            writer.write_data(path, &df, true)?;
+
+            assert_eq!(header(path)?.codec, Some(codec));
+            let out = AvroReaderImpl::default().read_data(path)?.collect()?;
+            assert_dataframes_equal(&df.vstack(&df)?, &out);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_name_namespace_and_field_docs() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = AvroWriterImpl {
+            record_name: Some("Person".to_string()),
+            namespace: Some("com.example.people".to_string()),
+            field_docs: vec![("age".to_string(), "Age in years".to_string())],
+            ..writer()
+        };
+        writer.write_data(path, &create_sample_df()?, false)?;
+
+        let schema = header(path)?.schema;
+        assert_eq!(schema["name"], "Person");
+
+        assert_eq!(schema["namespace"], "com.example.people");
+        assert_eq!(schema["fields"][1]["doc"], "Age in years");
+        assert!(schema["fields"][0].get("doc").is_none());
+        assert_dataframes_equal(&create_sample_df()?, &read_fn(path).unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_derived_record_name_and_sync_marker() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        writer().write_data(path, &create_sample_df()?, false)?;
+        let first = header(path)?;
+        assert_eq!(first.schema["name"], "Record");
+
+        // every file gets its own sync marker
+        writer().write_data(path, &create_sample_df()?, false)?;
+        assert_ne!(header(path)?.marker, first.marker);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsigned_longs_and_uuids() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let df = df! {
+            "count" => &[Some(u64::MAX), Some(40000), None],
+            "id" => &[Some("6f1c2a4e-3b1d-4c7e-9a52-0d3e8f7b1a21"), None, Some("00000000-0000-0000-0000-000000000000")]
+        }?;
+        writer().write_data(path, &df, false)?;
+
+        let schema = header(path)?.schema;
+        assert_eq!(schema["fields"][0]["type"][1]["logicalType"], "decimal");
+        assert_eq!(schema["fields"][1]["type"][1]["logicalType"], "uuid");
+        let out = AvroReaderImpl::default().read_data(path)?.collect()?;
+        let counts = out.column("count")?.cast(&DataType::UInt64)?;
+        assert_eq!(counts.u64()?.into_iter().collect::<Vec<_>>(), [Some(u64::MAX), Some(40000), None]);
+        assert!(out.column("id")?.equals_missing(df.column("id")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_names_and_docs() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let df = create_sample_df().unwrap();
+        let bad_name = AvroWriterImpl {
+            record_name: Some("1st-record".to_string()),
+            ..writer()
+        };
+        assert!(bad_name.write_data(path, &df, false).is_err());
+        let bad_namespace = AvroWriterImpl {
+            namespace: Some("com..example".to_string()),
+            ..writer()
+        };
+        assert!(bad_namespace.write_data(path, &df, false).is_err());
+        let unknown_column = AvroWriterImpl {
+            field_docs: vec![("zip".to_string(), "Postal code".to_string())],
+            ..writer()
+        };
+        assert!(unknown_column.write_data(path, &df, false).is_err());
+    }
+
+    #[test]
+    fn test_schema_file_coerces_frame() -> Result<()> {
+        let avsc = serde_json::json!({
+            "type": "record",
+            "name": "Person",
+            "namespace": "com.example",
+            "doc": "A person",
+            "fields": [
+                {"name": "city", "type": ["null", "string"], "default": null},
+                {"name": "name", "type": "string", "doc": "Full name"},
+                {"name": "age", "type": "long"}
+            ]
+        });
+        let schema_file = write_avsc(&avsc)?;
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = AvroWriterImpl {
+            schema: Some(schema_file.path().to_str().unwrap().to_string()),
+            ..writer()
+        };
+        writer.write_data(path, &create_sample_df()?, false)?;
+
+        // the schema is written as given, defaults included
+        assert_eq!(header(path)?.schema, avsc);
+        let out = AvroReaderImpl::default().read_data(path)?.collect()?;
+        assert_eq!(out.get_column_names(), vec!["city", "name", "age"]);
+        assert_eq!(out.column("age")?.dtype(), &DataType::Int64);
+        assert_eq!(out.column("name")?.str()?.get(1), Some("Bob"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_file_mismatches() -> Result<()> {
+        let avsc = serde_json::json!({
+            "type": "record",
+            "name": "Person",
+            "fields": [
+                {"name": "name", "type": "string"},
+                {"name": "age", "type": "int"}
+            ]
+        });
+        let schema_file = write_avsc(&avsc)?;
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = AvroWriterImpl {
+            schema: Some(schema_file.path().to_str().unwrap().to_string()),
+            ..writer()
+        };
+
+        let extra_column = create_sample_df()?;
+        let err = writer.write_data(path, &extra_column, false).unwrap_err();
+        assert!(err.to_string().contains("'city' is not a field"));
+
+        let missing_column = df! { "name" => &["Alice"] }?;
+        let err = writer.write_data(path, &missing_column, false).unwrap_err();
+        assert!(err.to_string().contains("field 'age'"));
+
+        let null_name = df! { "name" => &[None::<&str>], "age" => &[1] }?;
+        let err = writer.write_data(path, &null_name, false).unwrap_err();
+        assert!(err.to_string().contains("not nullable"));
+
+        let not_a_number = df! { "name" => &["Alice"], "age" => &["thirty"] }?;
+        assert!(writer.write_data(path, &not_a_number, false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_logical_types_round_trip() -> Result<()> {
+        let df = df! {
+            "date" => &[Some(19_000), None],
+            "time" => &[Some(45_296_000_000_000i64), None],
+            "ms" => &[Some(1_700_000_000_123i64), None],
+            "us" => &[Some(1_700_000_000_123_456i64), None],
+            "utc" => &[Some(1_700_000_000_123_456i64), None],
+            "price" => &[Some(1234i64), None],
+            "id" => &[Some("6f1c2a4e-3b1d-4c7e-9a52-0d3e8f7b1a21"), None],
+        }?
+        .lazy()
+        .with_columns([
+            col("date").cast(DataType::Date),
+            col("time").cast(DataType::Time),
+            col("ms").cast(DataType::Datetime(TimeUnit::Milliseconds, None)),
+            col("us").cast(DataType::Datetime(TimeUnit::Microseconds, None)),
+            col("utc").cast(DataType::Datetime(TimeUnit::Microseconds, Some("UTC".into()))),
+            col("price").cast(DataType::Decimal(Some(12), Some(2))),
+        ])
+        .collect()?;
+        let temp = tempfile::NamedTempFile::new()?;
+        let path = temp.path().to_str().unwrap();
+        writer().write_data(path, &df, false)?;
+        let read = AvroReaderImpl::default().read_data(path)?.collect()?;
+        assert_eq!(read.schema(), df.schema());
+        assert!(read.equals_missing(&df));
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_file_logical_types() -> Result<()> {
+        let avsc = tempfile::NamedTempFile::new()?;
+        std::fs::write(
+            avsc.path(),
+            r#"{"type": "record", "name": "Event", "fields": [
+                {"name": "day", "type": {"type": "int", "logicalType": "date"}},
+                {"name": "at", "type": {"type": "int", "logicalType": "time-millis"}},
+                {"name": "ts", "type": ["null", {"type": "long", "logicalType": "timestamp-millis"}]},
+                {"name": "id", "type": {"type": "string", "logicalType": "uuid"}},
+                {"name": "amount", "type": ["null", {"type": "bytes", "logicalType": "decimal", "precision": 10, "scale": 2}]}
+            ]}"#,
+        )?;
+        let df = df! {
+            "day" => &["2024-02-29"],
+            "at" => &[45_296_789_000_000i64],
+            "ts" => &[Some(1_700_000_000_123i64)],
+            "id" => &["6f1c2a4e-3b1d-4c7e-9a52-0d3e8f7b1a21"],
+            "amount" => &[Some(12.5)],
+        }?
+        .lazy()
+        .with_columns([col("day").cast(DataType::Date), col("at").cast(DataType::Time)])
+        .collect()?;
+
+        let temp = tempfile::NamedTempFile::new()?;
+        let path = temp.path().to_str().unwrap();
+        let writer = AvroWriterImpl {
+            schema: Some(avsc.path().to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        writer.write_data(path, &df, false)?;
+        let read = AvroReaderImpl::default().read_data(path)?.collect()?;
+
+        let utc = DataType::Datetime(TimeUnit::Milliseconds, Some("UTC".into()));
+        let dtypes = read.dtypes();
+        assert_eq!(
+            dtypes,
+            [DataType::Date, DataType::Time, utc, DataType::String, DataType::Decimal(Some(10), Some(2))]
+        );
+        // time-millis keeps whole milliseconds only
+        assert_eq!(read.column("at")?.cast(&DataType::Int64)?.i64()?.get(0), Some(45_296_789_000_000));
+        assert_eq!(read.column("ts")?.cast(&DataType::Int64)?.i64()?.get(0), Some(1_700_000_000_123));
+        assert_eq!(read.column("amount")?.cast(&DataType::Float64)?.f64()?.get(0), Some(12.5));
+        Ok(())
+    }
+}
@@ -1,26 +1,223 @@
+use super::{check_append_columns, prepare_text_append, sink_append, write_compressed};
+use crate::compression::{output_compression, FileCompression};
 use crate::storage::Storage;
 use anyhow::Result;
+use clap::ValueEnum;
 use polars::prelude::*;
 use std::fs::File;
 
-#[derive(Default)]
-pub struct CsvWriterImpl;
+/// When the CSV writer wraps fields in quotes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CsvQuoteStyle {
+    /// Only fields containing a delimiter, quote or line break
+    #[default]
+    Necessary,
+    /// Every field
+    Always,
+    /// Every field that is not a number
+    NonNumeric,
+    /// No field, even if that makes the output ambiguous
+    Never,
+}
+
+impl From<CsvQuoteStyle> for QuoteStyle {
+    fn from(style: CsvQuoteStyle) -> Self {
+        match style {
+            CsvQuoteStyle::Necessary => QuoteStyle::Necessary,
+            CsvQuoteStyle::Always => QuoteStyle::Always,
+            CsvQuoteStyle::NonNumeric => QuoteStyle::NonNumeric,
+            CsvQuoteStyle::Never => QuoteStyle::Never,
+        }
+    }
+}
+
+/// Leading characters that make spreadsheet apps evaluate a cell as a formula.
+const FORMULA_PREFIXES: [&str; 6] = ["=", "+", "-", "@", "\t", "\r"];
+
+/// CSV output settings.
+///
+/// - `date_format`, `datetime_format`, `time_format`: chrono format strings
+/// - `float_precision`: number of decimals written for floats
+/// - `escape_formulas`: prefix string cells that start like a formula with
+///   `'`, so Excel shows them as text instead of evaluating them
+/// - `compression`: compress the whole file; taken from the extension of the
+///   target (e.g. `.csv.gz`) when `None`
+pub struct CsvWriterImpl {
+    pub delimiter: u8,
+    pub quote_style: CsvQuoteStyle,
+    pub null_value: String,
+    pub include_header: bool,
+    pub crlf: bool,
+    pub date_format: Option<String>,
+    pub datetime_format: Option<String>,
+    pub time_format: Option<String>,
+    pub float_precision: Option<usize>,
+    pub escape_formulas: bool,
+    pub compression: Option<FileCompression>,
+}
+
+impl Default for CsvWriterImpl {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote_style: CsvQuoteStyle::default(),
+            null_value: String::new(),
+            include_header: true,
+            crlf: false,
+            date_format: None,
+            datetime_format: None,
+            time_format: None,
+            float_precision: None,
+            escape_formulas: false,
+            compression: None,
+        }
+    }
+}
 
 impl super::ToFile for CsvWriterImpl {
-    fn write_data(&self, path: &str, df: &DataFrame, _append: bool) -> Result<()> {
+    fn write_data(&self, path: &str, df: &DataFrame, append: bool) -> Result<()> {
+        match output_compression(path, self.compression) {
+            Some(compression) => write_compressed(path, compression, append, |spool| self.write_plain(spool, df, append)),
+            None => self.write_plain(path, df, append),
+        }
+    }
+
+    fn sink_data(&self, path: &str, lf: LazyFrame, append: bool) -> Result<()> {
+        match output_compression(path, self.compression) {
+            Some(compression) => write_compressed(path, compression, append, |spool| self.sink_plain(spool, lf, append)),
+            None => self.sink_plain(path, lf, append),
+        }
+    }
+}
+
+impl CsvWriterImpl {
+    fn write_plain(&self, path: &str, df: &DataFrame, append: bool) -> Result<()> {
+        let mut df_to_write = if self.escape_formulas {
+            escape_formula_cells(df.clone().lazy())?.collect()?
+        } else {
+            df.clone()
+        };
+        let names = df_to_write.get_column_names().iter().map(|n| n.to_string()).collect();
+        let mut storage = Storage::new(path)?;
+        let (file, include_header) = self.open_target(&mut storage, append, names)?;
+        let options = self.serialize_options();
+        CsvWriter::new(file)
+            .include_header(include_header)
+            .with_separator(options.separator)
+            .with_quote_style(options.quote_style)
+            .with_null_value(options.null)
+            .with_line_terminator(options.line_terminator)
+            .with_date_format(options.date_format)
+            .with_datetime_format(options.datetime_format)
+            .with_time_format(options.time_format)
+            .with_float_precision(options.float_precision)
+            .finish(&mut df_to_write)?;
+        storage.finish_write()?;
+        Ok(())
+    }
+
+    fn sink_plain(&self, path: &str, lf: LazyFrame, append: bool) -> Result<()> {
+        let lf = if self.escape_formulas { escape_formula_cells(lf)? } else { lf };
+        if append {
+            return sink_append(
+                path,
+                lf,
+                |storage, names| self.open_target(storage, true, names),
+                |lf, spool, include_header| lf.sink_csv(spool, self.sink_options(include_header), None),
+            );
+        }
         let mut storage = Storage::new(path)?;
-        let file: File = storage.get_target_file()?;
-        let mut df_to_write = df.clone();
-        CsvWriter::new(file).finish(&mut df_to_write)?;
+        let target = storage.get_target_path()?;
+        lf.sink_csv(target, self.sink_options(self.include_header), None)?;
         storage.finish_write()?;
         Ok(())
     }
+
+    /// Open the target, returning whether to write a header. Appending to a
+    /// file that already has rows never repeats the header, and checks the
+    /// existing header against the new columns.
+    fn open_target(&self, storage: &mut Storage, append: bool, names: Vec<String>) -> Result<(File, bool)> {
+        if !append {
+            return Ok((storage.get_target_file()?, self.include_header));
+        }
+        let mut file = storage.get_append_file()?;
+        match prepare_text_append(&mut file, self.line_terminator())? {
+            None => Ok((file, self.include_header)),
+            Some(first_line) => {
+                if self.include_header {
+                    check_append_columns(&self.parse_header(&first_line)?, &names)?;
+                }
+                Ok((file, false))
+            }
+        }
+    }
+
+    fn parse_header(&self, line: &str) -> Result<Vec<String>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(false)
+            .from_reader(line.as_bytes());
+        match reader.records().next() {
+            Some(record) => Ok(record?.iter().map(str::to_string).collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn line_terminator(&self) -> &'static str {
+        if self.crlf { "\r\n" } else { "\n" }
+    }
+
+    fn sink_options(&self, include_header: bool) -> CsvWriterOptions {
+        CsvWriterOptions {
+            include_header,
+            serialize_options: self.serialize_options(),
+            ..Default::default()
+        }
+    }
+
+    fn serialize_options(&self) -> SerializeOptions {
+        SerializeOptions {
+            date_format: self.date_format.clone(),
+            time_format: self.time_format.clone(),
+            datetime_format: self.datetime_format.clone(),
+            float_precision: self.float_precision,
+            separator: self.delimiter,
+            null: self.null_value.clone(),
+            line_terminator: self.line_terminator().to_string(),
+            quote_style: self.quote_style.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Defend against CSV injection: string cells starting with `=`, `+`, `-`,
+/// `@`, tab or carriage return get a leading `'`. Other columns are untouched.
+fn escape_formula_cells(mut lf: LazyFrame) -> Result<LazyFrame> {
+    let schema = lf.collect_schema()?;
+    let escaped: Vec<Expr> = schema
+        .iter()
+        .filter(|(_, dtype)| **dtype == DataType::String)
+        .map(|(name, _)| {
+            let cell = col(name.clone());
+            let looks_like_formula = FORMULA_PREFIXES
+                .iter()
+                .map(|p| cell.clone().str().starts_with(lit(*p)))
+                .reduce(|a, b| a.or(b))
+                .unwrap();
+            when(looks_like_formula)
+                .then(concat_str([lit("'"), cell.clone()], "", false))
+                .otherwise(cell)
+                .alias(name.clone())
+        })
+        .collect();
+    Ok(lf.with_columns(escaped))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_utils::helpers::*;
+    use crate::to::ToFile;
     use std::error::Error;
 
     fn read_fn(path: &str) -> Result<DataFrame, Box<dyn Error>> {
@@ -39,6 +236,12 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sink_valid_file() -> Result<()> {
+        test_sink_read_compare(&writer(), read_fn)?;
+        Ok(())
+    }
+
     #[test]
     fn test_write_to_nonexistent_directory() -> Result<()> {
         test_write_should_fail(&writer());
@@ -50,4 +253,126 @@ mod tests {
         test_write_overwrite(&writer(), read_fn)?;
         Ok(())
     }
+
+    #[test]
+    fn test_write_append_file() -> Result<()> {
+        test_write_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_append_file() -> Result<()> {
+        test_sink_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_rejects_schema_mismatch() -> Result<()> {
+        test_append_schema_mismatch(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    fn sample_with_dates() -> Result<DataFrame> {
+        Ok(df! {
+            "name" => &["Alice", "Bob"],
+            "score" => &[Some(1.23456f64), None],
+            "joined" => &[chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(), chrono::NaiveDate::from_ymd_opt(2023, 6, 1).unwrap()]
+        }?)
+    }
+
+    #[test]
+    fn test_write_dialect() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = CsvWriterImpl {
+            delimiter: b';',
+            quote_style: CsvQuoteStyle::Always,
+            null_value: "NULL".to_string(),
+            crlf: true,
+            date_format: Some("%d/%m/%Y".to_string()),
+            float_precision: Some(2),
+            ..writer()
+        };
+        writer.write_data(path, &sample_with_dates()?, false)?;
+
+        let out = std::fs::read_to_string(path)?;
+        assert_eq!(
+            out,
+            "\"name\";\"score\";\"joined\"\r\n\"Alice\";\"1.23\";\"31/01/2024\"\r\n\"Bob\";\"NULL\";\"01/06/2023\"\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_without_header_or_quotes() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = CsvWriterImpl {
+            delimiter: b'\t',
+            quote_style: CsvQuoteStyle::Never,
+            include_header: false,
+            ..writer()
+        };
+        let df = df! { "note" => &["a,b", "c"] }?;
+        writer.sink_data(path, df.lazy(), false)?;
+
+        assert_eq!(std::fs::read_to_string(path)?, "a,b\nc\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_formulas() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = CsvWriterImpl {
+            escape_formulas: true,
+            ..writer()
+        };
+        let df = df! {
+            "cell" => &["=SUM(A1:A2)", "+1", "@cmd", "plain"],
+            "n" => &[-1i64, 2, 3, 4]
+        }?;
+        writer.write_data(path, &df, false)?;
+
+        // numbers stay numbers; only string cells are escaped
+        assert_eq!(
+            std::fs::read_to_string(path)?,
+            "cell,n\n'=SUM(A1:A2),-1\n'+1,2\n'@cmd,3\nplain,4\n"
+        );
+        Ok(())
+    }
+
+    fn read_compressed(path: &str) -> Result<DataFrame, Box<dyn Error>> {
+        let mut magic = [0u8; 2];
+        std::io::Read::read_exact(&mut File::open(path)?, &mut magic)?;
+        assert_ne!(&magic, b"na", "the output is not compressed");
+        let source = crate::compression::decompressed_source(path, path.into(), None)?;
+        read_fn(source.to_str().unwrap())
+    }
+
+    #[test]
+    fn test_write_compressed() -> Result<()> {
+        for compression in [FileCompression::Gzip, FileCompression::Zstd, FileCompression::Bzip2, FileCompression::Xz] {
+            let writer = CsvWriterImpl {
+                compression: Some(compression),
+                ..writer()
+            };
+            test_write_read_compare(&writer, read_compressed, false)?;
+            test_write_append(&writer, read_compressed)?;
+            test_sink_append(&writer, read_compressed)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_from_extension() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out.csv.gz");
+        let path = path.to_str().unwrap();
+        writer().write_data(path, &df! { "a" => &[1, 2] }?, false)?;
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(File::open(path)?), &mut text)?;
+        assert_eq!(text, "a\n1\n2\n");
+        Ok(())
+    }
 }
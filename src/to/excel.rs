@@ -0,0 +1,452 @@
+use super::check_append_columns;
+use crate::from::excel::read_workbook_sheets;
+use crate::storage::Storage;
+use anyhow::{bail, Result};
+use chrono::{DateTime, NaiveDate};
+use polars::prelude::*;
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+/// Excel caps a worksheet at 1,048,576 rows, one of which is our header.
+pub const EXCEL_MAX_DATA_ROWS: usize = 1_048_575;
+
+/// Writes `.xlsx` workbooks with typed cells, a bold frozen header row and an
+/// autofilter over the data.
+///
+/// - `sheet_name`: name of the sheet, or the prefix of numbered sheets when
+///   the rows do not fit on one
+/// - `split_by`: write one sheet per distinct value of this column
+/// - `max_rows_per_sheet`: start a new sheet after this many data rows
+///
+/// Appending rewrites the workbook: rows are added below those of the sheets
+/// they would be written to, and other sheets are kept as they are.
+pub struct ExcelWriterImpl {
+    pub sheet_name: String,
+    pub split_by: Option<String>,
+    pub max_rows_per_sheet: usize,
+}
+
+impl Default for ExcelWriterImpl {
+    fn default() -> Self {
+        Self {
+            sheet_name: "Sheet1".to_string(),
+            split_by: None,
+            max_rows_per_sheet: EXCEL_MAX_DATA_ROWS,
+        }
+    }
+}
+
+impl super::ToFile for ExcelWriterImpl {
+    fn write_data(&self, path: &str, df: &DataFrame, append: bool) -> Result<()> {
+        if self.max_rows_per_sheet == 0 || self.max_rows_per_sheet > EXCEL_MAX_DATA_ROWS {
+            bail!("Rows per sheet must be between 1 and {EXCEL_MAX_DATA_ROWS}");
+        }
+
+        let mut storage = Storage::new(path)?;
+        let (mut file, sheets) = if append {
+            let mut file = storage.get_append_file()?;
+            let mut existing = Vec::new();
+            file.read_to_end(&mut existing)?;
+            let sheets = match existing.is_empty() {
+                true => self.sheets(df)?,
+                false => self.append_sheets(read_workbook_sheets(existing)?, df)?,
+            };
+            // the workbook is a zip archive, so it is written again whole
+            file.set_len(0)?;
+            (file, sheets)
+        } else {
+            (storage.get_target_file()?, self.sheets(df)?)
+        };
+
+        let mut workbook = Workbook::new();
+        let mut used_names = HashSet::new();
+        for (name, sheet_df) in sheets {
+            let worksheet = workbook.add_worksheet();
+            worksheet.set_name(unique_sheet_name(&name, &mut used_names))?;
+            write_sheet(worksheet, &sheet_df)?;
+        }
+
+        let buf = workbook.save_to_buffer()?;
+        file.write_all(&buf)?;
+        file.flush()?;
+        storage.finish_write()?;
+        Ok(())
+    }
+}
+
+impl ExcelWriterImpl {
+    /// Split the frame into named sheets, by column value and then row limit.
+    fn sheets(&self, df: &DataFrame) -> Result<Vec<(String, DataFrame)>> {
+        let mut sheets = Vec::new();
+        for (name, group) in self.groups(df)? {
+            sheets.extend(self.split_rows(name, group));
+        }
+        if sheets.is_empty() {
+            // an empty split still needs one sheet to hold the header
+            sheets.push((self.sheet_name.clone(), df.clear()));
+        }
+        Ok(sheets)
+    }
+
+    /// The sheets of an existing workbook with `df` added: each group of new
+    /// rows goes below the rows of the sheets it was written to before, in
+    /// place of them, and groups with no sheet yet come last.
+    fn append_sheets(&self, existing: Vec<(String, DataFrame)>, df: &DataFrame) -> Result<Vec<(String, DataFrame)>> {
+        let groups = self.groups(df)?;
+        let mut stacked: Vec<Vec<LazyFrame>> = vec![Vec::new(); groups.len()];
+        let mut slots = Vec::new();
+        for (sheet, sheet_df) in existing {
+            let Some(i) = groups.iter().position(|(name, _)| continues_sheet(&sheet, name)) else {
+                slots.push(Slot::Kept(sheet, sheet_df));
+                continue;
+            };
+            let names = |df: &DataFrame| df.get_column_names().iter().map(|n| n.to_string()).collect::<Vec<_>>();
+            check_append_columns(&names(&sheet_df), &names(&groups[i].1))?;
+            if stacked[i].is_empty() {
+                // the group is written where its first sheet was
+                slots.push(Slot::Group(i));
+            }
+            stacked[i].push(sheet_df.lazy());
+        }
+        for (i, frames) in stacked.iter().enumerate() {
+            if frames.is_empty() {
+                slots.push(Slot::Group(i));
+            }
+        }
+
+        // cells read back from Excel may be typed more loosely than the new rows
+        let args = UnionArgs {
+            to_supertypes: true,
+            ..Default::default()
+        };
+        let mut sheets = Vec::new();
+        for slot in slots {
+            match slot {
+                Slot::Kept(sheet, sheet_df) => sheets.push((sheet, sheet_df)),
+                Slot::Group(i) => {
+                    let (name, group) = &groups[i];
+                    let mut frames = std::mem::take(&mut stacked[i]);
+                    frames.push(group.clone().lazy());
+                    sheets.extend(self.split_rows(name.clone(), concat(frames, args)?.collect()?));
+                }
+            }
+        }
+        Ok(sheets)
+    }
+
+    /// The rows of each sheet before the row limit: the whole frame, or one
+    /// group per distinct value of `split_by`.
+    fn groups(&self, df: &DataFrame) -> Result<Vec<(String, DataFrame)>> {
+        let Some(column) = &self.split_by else {
+            return Ok(vec![(self.sheet_name.clone(), df.clone())]);
+        };
+        let mut groups = Vec::new();
+        for part in df.partition_by_stable([column.as_str()], true)? {
+            let value = part.column(column)?.get(0)?;
+            let name = match value.get_str() {
+                Some(s) => s.to_string(),
+                None if value.is_null() => "null".to_string(),
+                None => value.to_string(),
+            };
+            groups.push((name, part));
+        }
+        Ok(groups)
+    }
+
+    /// Cut a group into sheets of at most `max_rows_per_sheet` rows, named
+    /// `name`, `name_2`, `name_3`, ...
+    fn split_rows(&self, name: String, group: DataFrame) -> Vec<(String, DataFrame)> {
+        if group.height() <= self.max_rows_per_sheet {
+            return vec![(name, group)];
+        }
+        let mut sheets = Vec::new();
+        let mut offset = 0;
+        let mut n = 1;
+        while offset < group.height() {
+            let chunk = group.slice(offset as i64, self.max_rows_per_sheet);
+            let chunk_name = if n == 1 { name.clone() } else { format!("{name}_{n}") };
+            sheets.push((chunk_name, chunk));
+            offset += self.max_rows_per_sheet;
+            n += 1;
+        }
+        sheets
+    }
+}
+
+/// A sheet of an appended workbook: one kept as it was, or the place of a
+/// group of rows in `ExcelWriterImpl::append_sheets`.
+enum Slot {
+    Kept(String, DataFrame),
+    Group(usize),
+}
+
+/// Whether `sheet` holds rows of the group `name`: it is the group's sheet,
+/// or one of the numbered sheets the row limit continues it on.
+fn continues_sheet(sheet: &str, name: &str) -> bool {
+    let (sheet, base) = (sheet.to_lowercase(), clean_sheet_name(name).to_lowercase());
+    sheet == base
+        || sheet
+            .strip_prefix(&format!("{base}_"))
+            .and_then(|n| n.parse::<usize>().ok())
+            .is_some_and(|n| n >= 2)
+}
+
+/// Excel sheet names are at most 31 characters, cannot contain `[]:*?/\`
+/// and must be unique ignoring case.
+fn unique_sheet_name(name: &str, used: &mut HashSet<String>) -> String {
+    let base = clean_sheet_name(name);
+
+    let mut candidate = base.clone();
+    let mut n = 2;
+    while used.contains(&candidate.to_lowercase()) {
+        let suffix = format!("_{n}");
+        let keep = 31 - suffix.len();
+        candidate = format!("{}{suffix}", base.chars().take(keep).collect::<String>());
+        n += 1;
+    }
+    used.insert(candidate.to_lowercase());
+    candidate
+}
+
+/// `name` with the characters Excel rejects replaced, cut to 31 characters.
+fn clean_sheet_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim_matches('\'');
+    if cleaned.is_empty() { "Sheet".to_string() } else { cleaned.chars().take(31).collect() }
+}
+
+fn write_sheet(worksheet: &mut Worksheet, df: &DataFrame) -> Result<()> {
+    let header_format = Format::new().set_bold();
+    let date_format = Format::new().set_num_format("yyyy-mm-dd");
+    let datetime_format = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+
+    for (col_idx, column) in df.get_columns().iter().enumerate() {
+        let col_idx = col_idx as u16;
+        worksheet.write_string_with_format(0, col_idx, column.name().as_str(), &header_format)?;
+        write_column(worksheet, col_idx, column, &date_format, &datetime_format)?;
+    }
+
+    if df.width() > 0 {
+        worksheet.set_freeze_panes(1, 0)?;
+        worksheet.autofilter(0, 0, df.height() as u32, (df.width() - 1) as u16)?;
+    }
+    Ok(())
+}
+
+/// Write one column below the header, keeping numbers, booleans and dates as
+/// native Excel cells. Nulls are left blank; other types are written as text.
+fn write_column(
+    worksheet: &mut Worksheet,
+    col_idx: u16,
+    column: &Column,
+    date_format: &Format,
+    datetime_format: &Format,
+) -> Result<()> {
+    let series = column.as_materialized_series();
+    match series.dtype() {
+        DataType::Boolean => {
+            for (i, v) in series.bool()?.into_iter().enumerate() {
+                if let Some(v) = v {
+                    worksheet.write_boolean(i as u32 + 1, col_idx, v)?;
+                }
+            }
+        }
+        dt if dt.is_primitive_numeric() => {
+            // Excel stores every number as a double
+            let floats = series.cast(&DataType::Float64)?;
+            for (i, v) in floats.f64()?.into_iter().enumerate() {
+                if let Some(v) = v {
+                    worksheet.write_number(i as u32 + 1, col_idx, v)?;
+                }
+            }
+        }
+        DataType::Date => {
+            let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+            let days = series.cast(&DataType::Int32)?;
+            for (i, v) in days.i32()?.into_iter().enumerate() {
+                if let Some(date) = v.and_then(|d| epoch.checked_add_signed(chrono::Duration::days(d as i64))) {
+                    worksheet.write_datetime_with_format(i as u32 + 1, col_idx, date, date_format)?;
+                }
+            }
+        }
+        DataType::Datetime(_, _) => {
+            // Excel has no time zones, so zoned values are written as UTC wall time
+            let millis = series.cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?;
+            let millis = millis.cast(&DataType::Int64)?;
+            for (i, v) in millis.i64()?.into_iter().enumerate() {
+                if let Some(dt) = v.and_then(DateTime::from_timestamp_millis) {
+                    worksheet.write_datetime_with_format(i as u32 + 1, col_idx, dt.naive_utc(), datetime_format)?;
+                }
+            }
+        }
+        _ => {
+            let strings = series.cast(&DataType::String)?;
+            for (i, v) in strings.str()?.into_iter().enumerate() {
+                if let Some(v) = v {
+                    worksheet.write_string(i as u32 + 1, col_idx, v)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from::{excel::ExcelReaderImpl, FromFile};
+    use crate::test_utils::helpers::*;
+    use crate::to::ToFile;
+    use calamine::{open_workbook_auto, Reader};
+    use tempfile::NamedTempFile;
+
+    fn writer() -> ExcelWriterImpl {
+        ExcelWriterImpl::default()
+    }
+
+    fn read_sheet(path: &str, sheet: &str) -> Result<DataFrame> {
+        let reader = ExcelReaderImpl {
+            sheet: Some(sheet.to_string()),
+            whole_numbers_as_integers: true,
+            ..ExcelReaderImpl::default()
+        };
+        Ok(reader.read_data(path)?.collect()?)
+    }
+
+    fn sheet_names(path: &str) -> Result<Vec<String>> {
+        Ok(open_workbook_auto(path)?.sheet_names())
+    }
+
+    #[test]
+    fn test_write_typed_cells() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let df = df! {
+            "name" => &["Alice", "Bob"],
+            "score" => &[1.5f64, 2.25],
+            "active" => &[Some(true), None],
+            "joined" => &[NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(), NaiveDate::from_ymd_opt(2023, 6, 1).unwrap()]
+        }?;
+        writer().write_data(path, &df, false)?;
+
+        let read_df = read_sheet(path, "Sheet1")?;
+        // the null boolean must come back as a blank cell, not `false`
+        assert!(df.equals_missing(&read_df));
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_column() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let df = df! {
+            "region" => &["EU", "US", "EU"],
+            "sales" => &[1i64, 2, 3]
+        }?;
+        let writer = ExcelWriterImpl {
+            split_by: Some("region".to_string()),
+            ..writer()
+        };
+        writer.write_data(path, &df, false)?;
+
+        assert_eq!(sheet_names(path)?, vec!["EU", "US"]);
+        let eu = read_sheet(path, "EU")?;
+        assert_eq!(eu.column("sales")?.i64()?.into_no_null_iter().collect::<Vec<_>>(), vec![1, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_row_limit() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let df = df! { "n" => &[1i64, 2, 3, 4, 5] }?;
+        let writer = ExcelWriterImpl {
+            sheet_name: "data".to_string(),
+            max_rows_per_sheet: 2,
+            ..writer()
+        };
+        writer.write_data(path, &df, false)?;
+
+        assert_eq!(sheet_names(path)?, vec!["data", "data_2", "data_3"]);
+        // every sheet repeats the header
+        assert_eq!(read_sheet(path, "data_3")?.get_column_names(), vec!["n"]);
+        assert_eq!(read_sheet(path, "data_3")?.height(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_sheet_name() {
+        let mut used = HashSet::new();
+        assert_eq!(unique_sheet_name("a/b", &mut used), "a_b");
+        assert_eq!(unique_sheet_name("A_B", &mut used), "A_B_2");
+        assert_eq!(unique_sheet_name(&"x".repeat(40), &mut used).len(), 31);
+    }
+
+    #[test]
+    fn test_write_to_nonexistent_directory() -> Result<()> {
+        test_write_should_fail(&writer());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_empty() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        writer().write_data(path, &DataFrame::default(), false)?;
+        assert_eq!(sheet_names(path)?, vec!["Sheet1"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_adds_rows_to_their_sheets() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = ExcelWriterImpl {
+            split_by: Some("region".to_string()),
+            max_rows_per_sheet: 2,
+            ..writer()
+        };
+        let first = df! {
+            "region" => &["EU", "US", "EU"],
+            "sales" => &[1i64, 2, 3]
+        }?;
+        writer.write_data(path, &first, true)?;
+        assert_eq!(sheet_names(path)?, vec!["EU", "US"]);
+
+        let second = df! {
+            "region" => &["APAC", "EU"],
+            "sales" => &[4i64, 5]
+        }?;
+        writer.write_data(path, &second, true)?;
+
+        // EU runs over the row limit onto a second sheet, APAC is new
+        assert_eq!(sheet_names(path)?, vec!["EU", "EU_2", "US", "APAC"]);
+        let eu_2 = read_sheet(path, "EU_2")?;
+        assert_eq!(eu_2.column("sales")?.i64()?.into_no_null_iter().collect::<Vec<_>>(), vec![5]);
+        assert_eq!(read_sheet(path, "US")?.height(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_schema_mismatch() -> Result<()> {
+        let temp_file = NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        writer().write_data(path, &create_sample_df()?, false)?;
+        let other = df! {
+            "name" => &["Carol"],
+            "score" => &[1.5]
+        }?;
+        assert!(writer().write_data(path, &other, true).is_err());
+        // the rejected append leaves the workbook untouched
+        assert_eq!(read_sheet(path, "Sheet1")?.height(), 2);
+
+        writer().write_data(path, &create_sample_df()?, true)?;
+        let df = read_sheet(path, "Sheet1")?;
+        assert_eq!(df.column("name")?.str()?.into_no_null_iter().collect::<Vec<_>>(), vec!["Alice", "Bob", "Alice", "Bob"]);
+        Ok(())
+    }
+}
@@ -1,20 +1,96 @@
+use super::{check_append_schema, dataset_part};
 use crate::storage::Storage;
 use anyhow::Result;
+use clap::ValueEnum;
 use polars::prelude::*;
 use std::fs::File;
+use std::path::Path;
 
+/// Arrow IPC buffer compression codecs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum IpcCodec {
+    Lz4,
+    Zstd,
+}
+
+impl From<IpcCodec> for IpcCompression {
+    fn from(codec: IpcCodec) -> Self {
+        match codec {
+            IpcCodec::Lz4 => IpcCompression::LZ4,
+            IpcCodec::Zstd => IpcCompression::ZSTD,
+        }
+    }
+}
+
+/// Writes the Arrow IPC file format, optionally with compressed buffers.
 #[derive(Default)]
-pub struct IpcWriterImpl;
+pub struct IpcWriterImpl {
+    pub compression: Option<IpcCodec>,
+}
 
 impl super::ToFile for IpcWriterImpl {
-    fn write_data(&self, path: &str, df: &DataFrame, _append: bool) -> Result<()> {
+    fn write_data(&self, path: &str, df: &DataFrame, append: bool) -> Result<()> {
         let mut storage = Storage::new(path)?;
-        let file: File = storage.get_target_file()?;
+        if append && storage.is_local_dir() {
+            let part = dataset_part(storage.local_path(), "arrow", df.schema(), read_schema)?;
+            self.writer(File::create(part)?).finish(&mut df.clone())?;
+            return Ok(());
+        }
+
         let mut df_to_write = df.clone();
-        IpcWriter::new(file).finish(&mut df_to_write)?;
+        let file = if append {
+            let file = storage.get_append_file()?;
+            if file.metadata()?.len() > 0 {
+                // the IPC file format ends in a footer, so the file is rewritten with the new rows
+                let existing = IpcReader::new(file.try_clone()?).finish()?;
+                check_append_schema(existing.schema(), df.schema())?;
+                df_to_write = existing.vstack(df)?;
+                file.set_len(0)?;
+            }
+            file
+        } else {
+            storage.get_target_file()?
+        };
+        self.writer(file).finish(&mut df_to_write)?;
         storage.finish_write()?;
         Ok(())
     }
+
+    fn sink_data(&self, path: &str, mut lf: LazyFrame, append: bool) -> Result<()> {
+        let mut storage = Storage::new(path)?;
+        if append && storage.is_local_dir() {
+            let schema = lf.collect_schema()?;
+            let part = dataset_part(storage.local_path(), "arrow", &schema, read_schema)?;
+            lf.sink_ipc(part, self.sink_options(), None)?;
+            return Ok(());
+        }
+        if append {
+            return self.write_data(path, &lf.collect()?, true);
+        }
+
+        let target = storage.get_target_path()?;
+        lf.sink_ipc(target, self.sink_options(), None)?;
+        storage.finish_write()?;
+        Ok(())
+    }
+}
+
+impl IpcWriterImpl {
+    fn writer(&self, file: File) -> IpcWriter<File> {
+        IpcWriter::new(file).with_compression(self.compression.map(Into::into))
+    }
+
+    fn sink_options(&self) -> IpcWriterOptions {
+        IpcWriterOptions {
+            compression: self.compression.map(Into::into),
+            ..Default::default()
+        }
+    }
+}
+
+/// The schema of a part already in a dataset directory.
+fn read_schema(part: &Path) -> PolarsResult<SchemaRef> {
+    LazyFrame::scan_ipc(part, ScanArgsIpc::default())?.collect_schema()
 }
 
 #[cfg(test)]
@@ -39,6 +115,12 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sink_valid_file() -> Result<()> {
+        test_sink_read_compare(&writer(), read_fn)?;
+        Ok(())
+    }
+
     #[test]
     fn test_write_to_nonexistent_directory() -> Result<()> {
         test_write_should_fail(&writer());
@@ -51,9 +133,39 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_write_append_file() -> Result<()> {
+        test_write_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_append_file() -> Result<()> {
+        test_sink_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_rejects_schema_mismatch() -> Result<()> {
+        test_append_schema_mismatch(&writer(), read_fn)?;
+        Ok(())
+    }
+
     #[test]
     fn test_write_empty() -> Result<()> {
         test_write_empty_dataframe(&writer(), read_fn)?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_write_compressed() -> Result<()> {
+        for codec in [IpcCodec::Lz4, IpcCodec::Zstd] {
+            let writer = IpcWriterImpl {
+                compression: Some(codec),
+            };
+            test_write_read_compare(&writer, read_fn, false)?;
+            test_sink_read_compare(&writer, read_fn)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use super::check_append_schema;
+use super::ipc::IpcCodec;
+use crate::storage::Storage;
+use anyhow::Result;
+use polars::prelude::*;
+use std::io::Write;
+
+/// Writes the Arrow IPC stream format, to a file or to stdout with `-`, so
+/// the output can be piped into other Arrow tools.
+#[derive(Default)]
+pub struct IpcStreamWriterImpl {
+    pub compression: Option<IpcCodec>,
+}
+
+impl super::ToFile for IpcStreamWriterImpl {
+    fn write_data(&self, path: &str, df: &DataFrame, append: bool) -> Result<()> {
+        let mut df_to_write = df.clone();
+        let mut storage = Storage::new(path)?;
+        let mut file = if append {
+            let file = storage.get_append_file()?;
+            if file.metadata()?.len() > 0 {
+                // the stream ends in an end-of-stream marker, so it is rewritten with the new rows
+                let existing = IpcStreamReader::new(file.try_clone()?).finish()?;
+                check_append_schema(existing.schema(), df.schema())?;
+                df_to_write = existing.vstack(df)?;
+                file.set_len(0)?;
+            }
+            file
+        } else {
+            storage.get_target_file()?
+        };
+        self.write_stream(&mut file, &mut df_to_write)?;
+        storage.finish_write()?;
+        Ok(())
+    }
+}
+
+impl IpcStreamWriterImpl {
+    fn write_stream<W: Write>(&self, writer: W, df: &mut DataFrame) -> Result<()> {
+        IpcStreamWriter::new(writer)
+            .with_compression(self.compression.map(Into::into))
+            .finish(df)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::helpers::*;
+    use std::error::Error;
+    use std::fs::File;
+
+    fn read_fn(path: &str) -> Result<DataFrame, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let df = IpcStreamReader::new(file).finish()?;
+        Ok(df)
+    }
+
+    fn writer() -> IpcStreamWriterImpl {
+        IpcStreamWriterImpl::default()
+    }
+
+    #[test]
+    fn test_write_valid_file() -> Result<()> {
+        test_write_read_compare(&writer(), read_fn, false)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_valid_file() -> Result<()> {
+        test_sink_read_compare(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_to_nonexistent_directory() -> Result<()> {
+        test_write_should_fail(&writer());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_append_file() -> Result<()> {
+        test_write_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_rejects_schema_mismatch() -> Result<()> {
+        test_append_schema_mismatch(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_compressed() -> Result<()> {
+        for codec in [IpcCodec::Lz4, IpcCodec::Zstd] {
+            let writer = IpcStreamWriterImpl {
+                compression: Some(codec),
+            };
+            test_write_read_compare(&writer, read_fn, false)?;
+        }
+        Ok(())
+    }
+}
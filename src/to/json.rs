@@ -1,26 +1,272 @@
+use super::{check_append_columns, prepare_text_append, sink_append, write_compressed};
+use crate::compression::{output_compression, FileCompression};
 use crate::storage::Storage;
-use anyhow::Result;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
 use polars::prelude::*;
+use serde_json::{Map, Value};
 use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Seek, SeekFrom, Write};
 
+/// Layout of the JSON output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum JsonWriteFormat {
+    /// One JSON object per line (NDJSON)
+    #[default]
+    Lines,
+    /// A single JSON array of records
+    Json,
+}
+
+/// How dates and datetimes are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum JsonTemporalFormat {
+    /// ISO-8601 strings, e.g. "2024-01-31" and "2024-01-31T09:30:00"
+    #[default]
+    Iso,
+    /// Seconds since 1970-01-01 UTC
+    EpochSeconds,
+    /// Milliseconds since 1970-01-01 UTC
+    EpochMillis,
+}
+
+/// JSON output settings.
+///
+/// - `pretty`: indent the document; only for `JsonWriteFormat::Json`
+/// - `omit_nulls`: leave out fields whose value is null instead of writing `null`
+/// - `wrapper_key`: nest the array under this key, as in `{"data": [...]}`;
+///   only for `JsonWriteFormat::Json`
+/// - `compression`: compress the whole file; taken from the extension of the
+///   target (e.g. `.jsonl.zst`) when `None`
 #[derive(Default)]
-pub struct JsonWriterImpl;
+pub struct JsonWriterImpl {
+    pub format: JsonWriteFormat,
+    pub pretty: bool,
+    pub omit_nulls: bool,
+    pub temporal_format: JsonTemporalFormat,
+    pub wrapper_key: Option<String>,
+    pub compression: Option<FileCompression>,
+}
 
 impl super::ToFile for JsonWriterImpl {
-    fn write_data(&self, path: &str, df: &DataFrame, _append: bool) -> Result<()> {
+    fn write_data(&self, path: &str, df: &DataFrame, append: bool) -> Result<()> {
+        match output_compression(path, self.compression) {
+            Some(compression) => write_compressed(path, compression, append, |spool| self.write_plain(spool, df, append)),
+            None => self.write_plain(path, df, append),
+        }
+    }
+
+    fn sink_data(&self, path: &str, lf: LazyFrame, append: bool) -> Result<()> {
+        match output_compression(path, self.compression) {
+            Some(compression) => write_compressed(path, compression, append, |spool| self.sink_plain(spool, lf, append)),
+            None => self.sink_plain(path, lf, append),
+        }
+    }
+}
+
+impl JsonWriterImpl {
+    fn write_plain(&self, path: &str, df: &DataFrame, append: bool) -> Result<()> {
+        self.check_options()?;
+        let mut df_to_write = self.format_temporals(df.clone().lazy())?.collect()?;
+        let names: Vec<String> = df.get_column_names().iter().map(|n| n.to_string()).collect();
         let mut storage = Storage::new(path)?;
-        let file: File = storage.get_target_file()?;
-        JsonWriter::new(file)
-            .with_json_format(JsonFormat::JsonLines).finish(&mut df.clone())?;
+        match self.format {
+            JsonWriteFormat::Lines => {
+                let file = open_target(&mut storage, append, names)?;
+                if self.omit_nulls {
+                    let mut out = BufWriter::new(file);
+                    for record in self.records(&mut df_to_write)? {
+                        serde_json::to_writer(&mut out, &record)?;
+                        out.write_all(b"\n")?;
+                    }
+                    out.flush()?;
+                } else {
+                    JsonWriter::new(file)
+                        .with_json_format(JsonFormat::JsonLines).finish(&mut df_to_write)?;
+                }
+            }
+            JsonWriteFormat::Json => {
+                let mut rows = Vec::new();
+                let file = if append {
+                    // a JSON document cannot be extended in place, so it is rewritten
+                    let mut file = storage.get_append_file()?;
+                    rows = self.existing_rows(&mut file, &names)?;
+                    file.set_len(0)?;
+                    file
+                } else {
+                    storage.get_target_file()?
+                };
+                rows.extend(self.records(&mut df_to_write)?.into_iter().map(Value::Object));
+                self.write_document(file, rows)?;
+            }
+        }
         storage.finish_write()?;
         Ok(())
     }
+
+    fn sink_plain(&self, path: &str, lf: LazyFrame, append: bool) -> Result<()> {
+        self.check_options()?;
+        if self.format == JsonWriteFormat::Json || self.omit_nulls {
+            // only plain JSON Lines has a Polars sink
+            return self.write_plain(path, &lf.collect()?, append);
+        }
+        let lf = self.format_temporals(lf)?;
+        if append {
+            return sink_append(
+                path,
+                lf,
+                |storage, names| Ok((open_target(storage, true, names)?, ())),
+                |lf, spool, ()| lf.sink_json(spool, JsonWriterOptions::default(), None),
+            );
+        }
+        let mut storage = Storage::new(path)?;
+        let target = storage.get_target_path()?;
+        lf.sink_json(target, JsonWriterOptions::default(), None)?;
+        storage.finish_write()?;
+        Ok(())
+    }
+
+    fn check_options(&self) -> Result<()> {
+        if self.format == JsonWriteFormat::Lines && (self.pretty || self.wrapper_key.is_some()) {
+            bail!("Pretty printing and a wrapper key need a JSON array output (--output-json-format json)");
+        }
+        Ok(())
+    }
+
+    /// Write dates and datetimes as ISO-8601 strings or epoch numbers, so the
+    /// output does not depend on how Polars happens to serialize them.
+    fn format_temporals(&self, mut lf: LazyFrame) -> Result<LazyFrame> {
+        let schema = lf.collect_schema()?;
+        let mut formatted = Vec::new();
+        for (name, dtype) in schema.iter() {
+            let column = col(name.clone());
+            let expr = match (dtype, self.temporal_format) {
+                (DataType::Date, JsonTemporalFormat::Iso) => column.dt().strftime("%Y-%m-%d"),
+                (DataType::Datetime(_, None), JsonTemporalFormat::Iso) => column.dt().strftime("%Y-%m-%dT%H:%M:%S%.f"),
+                (DataType::Datetime(_, Some(_)), JsonTemporalFormat::Iso) => {
+                    column.dt().strftime("%Y-%m-%dT%H:%M:%S%.f%:z")
+                }
+                (DataType::Date | DataType::Datetime(_, _), JsonTemporalFormat::EpochMillis) => {
+                    epoch_millis(column)
+                }
+                (DataType::Date | DataType::Datetime(_, _), JsonTemporalFormat::EpochSeconds) => {
+                    epoch_millis(column).floor_div(lit(1000i64))
+                }
+                // times of day have no epoch, and Polars cannot write them as JSON
+                (DataType::Time, _) => column.dt().strftime("%H:%M:%S%.f"),
+                _ => continue,
+            };
+            formatted.push(expr.alias(name.clone()));
+        }
+        Ok(lf.with_columns(formatted))
+    }
+
+    /// Serialize the rows as JSON objects, in column order.
+    fn records(&self, df: &mut DataFrame) -> Result<Vec<Map<String, Value>>> {
+        let mut lines = Vec::new();
+        JsonWriter::new(&mut lines).with_json_format(JsonFormat::JsonLines).finish(df)?;
+        let mut records = Vec::with_capacity(df.height());
+        for line in lines.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            let mut record: Map<String, Value> = serde_json::from_slice(line)?;
+            if self.omit_nulls {
+                drop_nulls(&mut record);
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// Rows already in a JSON array target, after checking that its first
+    /// record has the same fields as the new columns.
+    fn existing_rows(&self, file: &mut File, names: &[String]) -> Result<Vec<Value>> {
+        if file.metadata()?.len() == 0 {
+            return Ok(Vec::new());
+        }
+        file.seek(SeekFrom::Start(0))?;
+        let document: Value = serde_json::from_reader(BufReader::new(&mut *file))?;
+        let rows = match (&self.wrapper_key, document) {
+            (None, Value::Array(rows)) => rows,
+            (Some(key), Value::Object(mut wrapper)) => match wrapper.remove(key) {
+                Some(Value::Array(rows)) => rows,
+                _ => bail!("Cannot append: the target has no '{key}' array"),
+            },
+            _ => bail!("Cannot append: the target is not a JSON array of records"),
+        };
+        if let Some(Value::Object(first)) = rows.first() {
+            let existing: Vec<String> = first.keys().cloned().collect();
+            if self.omit_nulls {
+                // null fields are missing from the target, so only check what is there
+                if let Some(extra) = existing.iter().find(|k| !names.contains(k)) {
+                    bail!("Cannot append: the target has column '{extra}', which the new data lacks");
+                }
+            } else {
+                check_append_columns(&existing, names)?;
+            }
+        }
+        Ok(rows)
+    }
+
+    fn write_document(&self, file: File, rows: Vec<Value>) -> Result<()> {
+        let document = match &self.wrapper_key {
+            Some(key) => Value::Object(Map::from_iter([(key.clone(), Value::Array(rows))])),
+            None => Value::Array(rows),
+        };
+        let mut out = BufWriter::new(file);
+        if self.pretty {
+            serde_json::to_writer_pretty(&mut out, &document)?;
+        } else {
+            serde_json::to_writer(&mut out, &document)?;
+        }
+        out.write_all(b"\n")?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn epoch_millis(column: Expr) -> Expr {
+    column
+        .cast(DataType::Datetime(TimeUnit::Milliseconds, None))
+        .cast(DataType::Int64)
+}
+
+/// Remove null fields, including those of nested objects.
+fn drop_nulls(record: &mut Map<String, Value>) {
+    record.retain(|_, value| !value.is_null());
+    for value in record.values_mut() {
+        match value {
+            Value::Object(nested) => drop_nulls(nested),
+            Value::Array(items) => {
+                for item in items.iter_mut() {
+                    if let Value::Object(nested) = item {
+                        drop_nulls(nested);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Open the target. When appending to a file that already has rows, the keys
+/// of its first record must match the new columns.
+fn open_target(storage: &mut Storage, append: bool, names: Vec<String>) -> Result<File> {
+    if !append {
+        return storage.get_target_file();
+    }
+    let mut file = storage.get_append_file()?;
+    if let Some(first_line) = prepare_text_append(&mut file, "\n")? {
+        let first = JsonLineReader::new(Cursor::new(first_line)).finish()?;
+        let existing: Vec<String> = first.get_column_names().iter().map(|n| n.to_string()).collect();
+        check_append_columns(&existing, &names)?;
+    }
+    Ok(file)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_utils::helpers::*;
+    use crate::to::ToFile;
     use polars::io::json::JsonReader;
     use std::error::Error;
     use std::fs::File;
@@ -35,12 +281,37 @@ mod tests {
         JsonWriterImpl::default()
     }
 
+    fn array_writer() -> JsonWriterImpl {
+        JsonWriterImpl {
+            format: JsonWriteFormat::Json,
+            ..writer()
+        }
+    }
+
+    fn sample_with_dates() -> Result<DataFrame> {
+        let joined = df! {
+            "id" => &[1i64, 2],
+            "note" => &[Some("x"), None],
+            "joined" => &[
+                chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+                chrono::NaiveDate::from_ymd_opt(1970, 1, 2).unwrap()
+            ]
+        }?;
+        Ok(joined)
+    }
+
     #[test]
     fn test_write_valid_file() -> Result<()> {
         test_write_read_compare(&writer(), read_fn, false)?;
         Ok(())
     }
 
+    #[test]
+    fn test_sink_valid_file() -> Result<()> {
+        test_sink_read_compare(&writer(), read_fn)?;
+        Ok(())
+    }
+
     #[test]
     fn test_write_to_nonexistent_directory() -> Result<()> {
         test_write_should_fail(&writer());
@@ -52,4 +323,140 @@ mod tests {
         test_write_overwrite(&writer(), read_fn)?;
         Ok(())
     }
+
+    #[test]
+    fn test_write_append_file() -> Result<()> {
+        test_write_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_append_file() -> Result<()> {
+        test_sink_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_rejects_schema_mismatch() -> Result<()> {
+        test_append_schema_mismatch(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_array_with_wrapper_key() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = JsonWriterImpl {
+            wrapper_key: Some("data".to_string()),
+            omit_nulls: true,
+            ..array_writer()
+        };
+        writer.write_data(path, &sample_with_dates()?, false)?;
+
+        assert_eq!(
+            std::fs::read_to_string(path)?,
+            "{\"data\":[{\"id\":1,\"note\":\"x\",\"joined\":\"2024-01-31\"},{\"id\":2,\"joined\":\"1970-01-02\"}]}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_pretty_epoch() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = JsonWriterImpl {
+            pretty: true,
+            temporal_format: JsonTemporalFormat::EpochSeconds,
+            ..array_writer()
+        };
+        let df = sample_with_dates()?.select(["id", "joined"])?;
+        writer.write_data(path, &df, false)?;
+
+        let expected = "[\n  {\n    \"id\": 1,\n    \"joined\": 1706659200\n  },\n  {\n    \"id\": 2,\n    \"joined\": 86400\n  }\n]\n";
+        assert_eq!(std::fs::read_to_string(path)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_lines_with_epoch_millis() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = JsonWriterImpl {
+            temporal_format: JsonTemporalFormat::EpochMillis,
+            ..writer()
+        };
+        let df = sample_with_dates()?.select(["joined"])?;
+        writer.sink_data(path, df.lazy(), false)?;
+
+        assert_eq!(std::fs::read_to_string(path)?, "{\"joined\":1706659200000}\n{\"joined\":86400000}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_to_array() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = JsonWriterImpl {
+            wrapper_key: Some("items".to_string()),
+            ..array_writer()
+        };
+        let df = create_sample_df()?;
+        writer.write_data(path, &df, true)?;
+        writer.sink_data(path, df.clone().lazy(), true)?;
+
+        let document: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        assert_eq!(document["items"].as_array().map(Vec::len), Some(4));
+
+        let other = df! { "name" => &["Carol"] }?;
+        assert!(writer.write_data(path, &other, true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pretty_lines_rejected() {
+        let writer = JsonWriterImpl {
+            pretty: true,
+            ..writer()
+        };
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let result = writer.write_data(temp_file.path().to_str().unwrap(), &create_sample_df().unwrap(), false);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_write_iso_datetimes() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let at = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap().and_hms_milli_opt(9, 30, 0, 250).unwrap();
+        let df = df! { "at" => &[at] }?;
+        writer().write_data(path, &df, false)?;
+
+        assert_eq!(std::fs::read_to_string(path)?, "{\"at\":\"2024-01-31T09:30:00.250\"}\n");
+
+        // times are text whatever the temporal format
+        let time = chrono::NaiveTime::from_hms_milli_opt(12, 34, 56, 789).unwrap();
+        let df = df! { "time" => &[time] }?;
+        JsonWriterImpl { temporal_format: JsonTemporalFormat::EpochMillis, ..writer() }.write_data(path, &df, false)?;
+        assert_eq!(std::fs::read_to_string(path)?, "{\"time\":\"12:34:56.789\"}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_zstd_lines_and_array() -> Result<()> {
+        for writer in [writer(), array_writer()] {
+            let writer = JsonWriterImpl {
+                compression: Some(FileCompression::Zstd),
+                ..writer
+            };
+            let temp_file = tempfile::NamedTempFile::new()?;
+            let path = temp_file.path().to_str().unwrap();
+            let df = df! { "id" => &[1, 2] }?;
+            writer.write_data(path, &df, false)?;
+            writer.sink_data(path, df.lazy(), true)?;
+
+            let text = String::from_utf8(zstd::decode_all(File::open(path)?)?)?;
+            assert_eq!(text.matches("\"id\":2").count(), 2, "{text}");
+        }
+        Ok(())
+    }
 }
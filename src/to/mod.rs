@@ -1,8 +1,20 @@
-use anyhow::Result;
+use crate::compression::{compress, FileCompression};
+use crate::storage::Storage;
+use anyhow::{anyhow, bail, Result};
 use polars::prelude::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
 
 pub trait ToFile {
     fn write_data(&self, path: &str, df: &DataFrame, append: bool) -> Result<()>;
+
+    /// Run the lazy query straight into the target file in streaming mode.
+    /// Formats without a Polars sink fall back to collecting the frame first.
+    fn sink_data(&self, path: &str, lf: LazyFrame, append: bool) -> Result<()> {
+        self.write_data(path, &lf.collect()?, append)
+    }
 }
 
 // The `DataWriter` enum acts as a dispatcher for multiple writer implementations
@@ -11,7 +23,9 @@ pub enum DataWriter {
     Parquet(parquet::ParquetWriterImpl),
     Avro(avro::AvroWriterImpl),
     Ipc(ipc::IpcWriterImpl),
+    IpcStream(ipc_stream::IpcStreamWriterImpl),
     Json(json::JsonWriterImpl),
+    Excel(excel::ExcelWriterImpl),
 }
 
 impl DataWriter {
@@ -21,13 +35,245 @@ impl DataWriter {
             DataWriter::Parquet(w) => w.write_data(path, df, append),
             DataWriter::Avro(w) => w.write_data(path, df, append),
             DataWriter::Ipc(w) => w.write_data(path, df, append),
+            DataWriter::IpcStream(w) => w.write_data(path, df, append),
             DataWriter::Json(w) => w.write_data(path, df, append),
+            DataWriter::Excel(w) => w.write_data(path, df, append),
+        }
+    }
+
+    /// Run the query into the target through the format's sink. A plan the
+    /// streaming engine cannot run end to end is collected and written
+    /// instead, since sinks refuse it before they write anything.
+    pub fn sink_data(&self, path: &str, lf: LazyFrame, append: bool) -> Result<()> {
+        let result = match self {
+            DataWriter::Csv(w) => w.sink_data(path, lf.clone(), append),
+            DataWriter::Parquet(w) => w.sink_data(path, lf.clone(), append),
+            DataWriter::Avro(w) => w.sink_data(path, lf.clone(), append),
+            DataWriter::Ipc(w) => w.sink_data(path, lf.clone(), append),
+            DataWriter::IpcStream(w) => w.sink_data(path, lf.clone(), append),
+            DataWriter::Json(w) => w.sink_data(path, lf.clone(), append),
+            DataWriter::Excel(w) => w.sink_data(path, lf.clone(), append),
+        };
+        match result {
+            Err(err) if err.downcast_ref::<PolarsError>().is_some_and(is_not_streamable) => {
+                self.write_data(path, &lf.collect()?, append)
+            }
+            result => result,
+        }
+    }
+
+    /// The extension of files this writer produces, such as `csv.gz`, for
+    /// the files of a dataset it names itself.
+    pub fn extension(&self) -> String {
+        let (ext, compression) = match self {
+            DataWriter::Csv(w) => ("csv", w.compression),
+            DataWriter::Parquet(_) => ("parquet", None),
+            DataWriter::Avro(_) => ("avro", None),
+            DataWriter::Ipc(_) => ("arrow", None),
+            DataWriter::IpcStream(_) => ("arrows", None),
+            DataWriter::Json(w) if w.format == json::JsonWriteFormat::Lines => ("jsonl", w.compression),
+            DataWriter::Json(w) => ("json", w.compression),
+            DataWriter::Excel(_) => ("xlsx", None),
+        };
+        match compression.and_then(FileCompression::extension) {
+            Some(compressed) => format!("{ext}.{compressed}"),
+            None => ext.to_string(),
         }
     }
 }
 
+/// Fail an append whose column names differ from the data already in the
+/// target. For formats that do not store types, such as CSV and NDJSON.
+pub(crate) fn check_append_columns(existing: &[String], new: &[String]) -> Result<()> {
+    if existing != new {
+        bail!(
+            "Cannot append: the target has columns [{}] but the new data has [{}]",
+            existing.join(", "),
+            new.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Fail an append whose column names or types differ from the data already
+/// in the target.
+pub(crate) fn check_append_schema(existing: &Schema, new: &Schema) -> Result<()> {
+    let names = |schema: &Schema| schema.iter_names().map(|n| n.to_string()).collect::<Vec<_>>();
+    check_append_columns(&names(existing), &names(new))?;
+    for ((name, old), (_, new)) in existing.iter().zip(new.iter()) {
+        if old != new {
+            bail!("Cannot append: column '{name}' is {old} in the target but {new} in the new data");
+        }
+    }
+    Ok(())
+}
+
+/// Prepare a text target opened by `Storage::get_append_file`: return its
+/// first line, or `None` if it is empty, and terminate a last line that lacks
+/// a line break so appended rows start on a line of their own.
+pub(crate) fn prepare_text_append(file: &mut File, line_terminator: &str) -> Result<Option<String>> {
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(None);
+    }
+    let mut last = [0u8];
+    file.seek(SeekFrom::Start(len - 1))?;
+    file.read_exact(&mut last)?;
+
+    let mut first = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    BufReader::new(&mut *file).read_line(&mut first)?;
+
+    if last[0] != b'\n' {
+        file.write_all(line_terminator.as_bytes())?;
+    }
+    Ok(Some(first.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Write a compressed text target: `write` produces the plain output in a
+/// local spool, which is then compressed onto `path` through `Storage`, so
+/// streaming sinks and remote targets work as for plain files. When
+/// appending, the spool starts out as the decompressed existing target, and
+/// the whole file is compressed again.
+pub(crate) fn write_compressed(
+    path: &str,
+    compression: FileCompression,
+    append: bool,
+    write: impl FnOnce(&str) -> Result<()>,
+) -> Result<()> {
+    let spool = tempfile::NamedTempFile::new()?;
+    let mut storage = Storage::new(path)?;
+    let mut file = if append {
+        let mut file = storage.get_append_file()?;
+        if file.metadata()?.len() > 0 {
+            let mut magic = Vec::with_capacity(6);
+            (&mut file).take(6).read_to_end(&mut magic)?;
+            file.seek(SeekFrom::Start(0))?;
+            let current = FileCompression::from_magic(&magic).unwrap_or(FileCompression::Uncompressed);
+            io::copy(&mut current.decoder(&mut file)?, &mut spool.as_file())?;
+        }
+        file
+    } else {
+        storage.get_target_file()?
+    };
+
+    let spool_path = spool.path().to_str().ok_or_else(|| anyhow!("Non UTF-8 temporary path"))?;
+    write(spool_path)?;
+    // the existing contents are in the spool, so only now is the target emptied
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    compress(compression, &mut BufReader::new(spool.reopen()?), BufWriter::new(&mut file))?.flush()?;
+    storage.finish_write()?;
+    Ok(())
+}
+
+/// Append the output of a text format's sink to `path`. Sinks always
+/// truncate, so the query streams into a spool that is copied onto the end
+/// of the target. `open` opens the target for appending, given the new
+/// column names, and returns what `sink` needs to know about it, such as
+/// whether to write a header.
+pub(crate) fn sink_append<T>(
+    path: &str,
+    mut lf: LazyFrame,
+    open: impl FnOnce(&mut Storage, Vec<String>) -> Result<(File, T)>,
+    sink: impl FnOnce(LazyFrame, &Path, T) -> PolarsResult<()>,
+) -> Result<()> {
+    let mut storage = Storage::new(path)?;
+    let names = lf.collect_schema()?.iter_names().map(|n| n.to_string()).collect();
+    let (mut file, opened) = open(&mut storage, names)?;
+    let spool = NamedTempFile::new()?;
+    sink(lf, spool.path(), opened)?;
+    io::copy(&mut spool.reopen()?, &mut file)?;
+    storage.finish_write()?;
+    Ok(())
+}
+
+/// Whether a sink refused the query because the streaming engine cannot run
+/// all of it. Polars says so while planning, before the sink opens its file.
+fn is_not_streamable(err: &PolarsError) -> bool {
+    let message = err.to_string();
+    message.contains("cannot run the whole query in a streaming order")
+        || message.contains("not yet supported in standard engine")
+}
+
+/// Run the query once into a local Arrow IPC spool, through the streaming
+/// sink when the plan allows it and collected otherwise, so its result can
+/// be read back in parts without running the query again.
+pub(crate) fn spool_query(lf: LazyFrame) -> Result<NamedTempFile> {
+    let spool = tempfile::Builder::new().suffix(".arrow").tempfile()?;
+    match lf.clone().sink_ipc(spool.path(), IpcWriterOptions::default(), None) {
+        Err(err) if is_not_streamable(&err) => IpcWriter::new(spool.reopen()?).finish(&mut lf.collect()?)?,
+        result => result?,
+    }
+    Ok(spool)
+}
+
+/// Part files of a dataset directory with the given extension, in name order.
+pub(crate) fn dataset_parts(dir: &Path, ext: &str) -> Result<Vec<PathBuf>> {
+    let mut parts = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == ext) {
+            parts.push(path);
+        }
+    }
+    parts.sort();
+    Ok(parts)
+}
+
+/// Path of a new part file for appending to a dataset directory, after
+/// checking the schema against the parts already there, as `read_schema`
+/// reads it from the first one.
+pub(crate) fn dataset_part(
+    dir: &Path,
+    ext: &str,
+    schema: &Schema,
+    read_schema: impl FnOnce(&Path) -> PolarsResult<SchemaRef>,
+) -> Result<PathBuf> {
+    if let Some(part) = dataset_parts(dir, ext)?.first() {
+        check_append_schema(read_schema(part)?.as_ref(), schema)?;
+    }
+    Ok(next_dataset_part(dir, ext))
+}
+
+/// The first unused `part-NNNNN.<ext>` name in a dataset directory.
+fn next_dataset_part(dir: &Path, ext: &str) -> PathBuf {
+    (0..)
+        .map(|n| dir.join(format!("part-{n:05}.{ext}")))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
 pub mod csv;
 pub mod parquet;
 pub mod avro;
 pub mod ipc;
-pub mod json;
\ No newline at end of file
+pub mod ipc_stream;
+pub mod json;
+pub mod excel;
+pub mod partition;
+pub mod split;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sink_falls_back_to_collecting() -> Result<()> {
+        // a frame-level map is opaque to the streaming engine, so sinks refuse it
+        let lf = df! { "a" => &[1i64, 2] }?.lazy().map(
+            |df| Ok(df.head(Some(1))),
+            AllowedOptimizations::default(),
+            None,
+            Some("first row"),
+        );
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        DataWriter::Csv(csv::CsvWriterImpl::default()).sink_data(path, lf.clone(), false)?;
+        assert_eq!(std::fs::read_to_string(path)?, "a\n1\n");
+
+        let spool = spool_query(lf)?;
+        let df = IpcReader::new(File::open(spool.path())?).finish()?;
+        assert_eq!(df.height(), 1);
+        Ok(())
+    }
+}
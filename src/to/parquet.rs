@@ -1,26 +1,196 @@
+use super::{check_append_schema, dataset_part};
 use crate::storage::Storage;
-use anyhow::Result;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
 use polars::prelude::*;
+use polars_parquet::write::{
+    transverse, Encoding, FileWriter, RowGroupIterator, StatisticsOptions, Version, WriteOptions,
+};
+use serde::Deserialize;
 use std::fs::File;
+use std::io::Write;
+use std::path::Path;
 
-#[derive(Default)]
-pub struct ParquetWriterImpl;
+/// Rows per row group when none is configured, matching Polars.
+const DEFAULT_ROW_GROUP_SIZE: usize = 512 * 512;
+
+/// Parquet compression codecs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParquetCodec {
+    Uncompressed,
+    Snappy,
+    #[default]
+    Zstd,
+    Gzip,
+    Lz4,
+    Brotli,
+}
+
+/// Parquet output settings.
+///
+/// - `compression_level`: zstd 1-22, gzip 0-9 or brotli 0-11; codec default if unset
+/// - `row_group_size`: rows per row group
+/// - `data_page_size`: target size of a data page in bytes
+/// - `statistics`: write min/max and null count statistics per column chunk
+/// - `dictionary`: allow dictionary encoding where Polars finds it pays off;
+///   when off every column is plain encoded
+pub struct ParquetWriterImpl {
+    pub codec: ParquetCodec,
+    pub compression_level: Option<u32>,
+    pub row_group_size: Option<usize>,
+    pub data_page_size: Option<usize>,
+    pub statistics: bool,
+    pub dictionary: bool,
+}
+
+impl Default for ParquetWriterImpl {
+    fn default() -> Self {
+        Self {
+            codec: ParquetCodec::default(),
+            compression_level: None,
+            row_group_size: None,
+            data_page_size: None,
+            statistics: true,
+            dictionary: true,
+        }
+    }
+}
 
 impl super::ToFile for ParquetWriterImpl {
-    fn write_data(&self, path: &str, df: &DataFrame, _append: bool) -> Result<()> {
+    fn write_data(&self, path: &str, df: &DataFrame, append: bool) -> Result<()> {
         let mut storage = Storage::new(path)?;
-        let file: File = storage.get_target_file()?;
+        if append && storage.is_local_dir() {
+            let part = dataset_part(storage.local_path(), "parquet", df.schema(), read_schema)?;
+            self.write_frame(File::create(part)?, &mut df.clone())?;
+            return Ok(());
+        }
+
         let mut df_to_write = df.clone();
-        ParquetWriter::new(file).finish(&mut df_to_write)?;
+        let file = if append {
+            let file = storage.get_append_file()?;
+            if file.metadata()?.len() > 0 {
+                // Parquet keeps its metadata in a footer, so the file is rewritten with the new rows
+                let existing = ParquetReader::new(file.try_clone()?).finish()?;
+                check_append_schema(existing.schema(), df.schema())?;
+                df_to_write = existing.vstack(df)?;
+                file.set_len(0)?;
+            }
+            file
+        } else {
+            storage.get_target_file()?
+        };
+        self.write_frame(file, &mut df_to_write)?;
+        storage.finish_write()?;
+        Ok(())
+    }
+
+    fn sink_data(&self, path: &str, mut lf: LazyFrame, append: bool) -> Result<()> {
+        if !self.dictionary {
+            // the Polars sink always chooses its own encodings
+            return self.write_data(path, &lf.collect()?, append);
+        }
+        let options = self.write_options()?;
+        let mut storage = Storage::new(path)?;
+        if append && storage.is_local_dir() {
+            let schema = lf.collect_schema()?;
+            let part = dataset_part(storage.local_path(), "parquet", &schema, read_schema)?;
+            lf.sink_parquet(&part, options, None)?;
+            return Ok(());
+        }
+        if append {
+            return self.write_data(path, &lf.collect()?, true);
+        }
+
+        let target = storage.get_target_path()?;
+        lf.sink_parquet(&target, options, None)?;
         storage.finish_write()?;
         Ok(())
     }
 }
 
+impl ParquetWriterImpl {
+    fn compression(&self) -> Result<ParquetCompression> {
+        let level = self.compression_level;
+        let compression = match (self.codec, level) {
+            (ParquetCodec::Uncompressed, None) => ParquetCompression::Uncompressed,
+            (ParquetCodec::Snappy, None) => ParquetCompression::Snappy,
+            (ParquetCodec::Lz4, None) => ParquetCompression::Lz4Raw,
+            (ParquetCodec::Zstd, _) => ParquetCompression::Zstd(level.map(|l| ZstdLevel::try_new(l as i32)).transpose()?),
+            (ParquetCodec::Gzip, _) => ParquetCompression::Gzip(level.map(|l| GzipLevel::try_new(l as u8)).transpose()?),
+            (ParquetCodec::Brotli, _) => ParquetCompression::Brotli(level.map(BrotliLevel::try_new).transpose()?),
+            (codec, Some(_)) => bail!("A compression level is not supported for {codec:?} Parquet compression"),
+        };
+        Ok(compression)
+    }
+
+    fn statistics(&self) -> StatisticsOptions {
+        if self.statistics { StatisticsOptions::default() } else { StatisticsOptions::empty() }
+    }
+
+    fn write_options(&self) -> Result<ParquetWriteOptions> {
+        Ok(ParquetWriteOptions {
+            compression: self.compression()?,
+            statistics: self.statistics(),
+            row_group_size: self.row_group_size,
+            data_page_size: self.data_page_size,
+            maintain_order: true,
+        })
+    }
+
+    fn write_frame<W: Write>(&self, writer: W, df: &mut DataFrame) -> Result<()> {
+        if !self.dictionary {
+            return self.write_plain(writer, df);
+        }
+        ParquetWriter::new(writer)
+            .with_compression(self.compression()?)
+            .with_statistics(self.statistics())
+            .with_row_group_size(self.row_group_size)
+            .with_data_page_size(self.data_page_size)
+            .finish(df)?;
+        Ok(())
+    }
+
+    /// Write with plain encoding for every column. `ParquetWriter` always
+    /// tries dictionary encoding, so this drives the Parquet encoder directly.
+    fn write_plain<W: Write>(&self, writer: W, df: &mut DataFrame) -> Result<()> {
+        let schema = df.schema().to_arrow(CompatLevel::newest());
+        let encodings: Vec<Vec<Encoding>> = schema
+            .iter_values()
+            .map(|field| transverse(&field.dtype, |_| Encoding::Plain))
+            .collect();
+        let options = WriteOptions {
+            statistics: self.statistics(),
+            version: Version::V1,
+            compression: self.compression()?.into(),
+            data_page_size: self.data_page_size,
+        };
+
+        let mut file_writer = FileWriter::try_new(writer, schema.clone(), options)?;
+        let row_group_size = self.row_group_size.unwrap_or(DEFAULT_ROW_GROUP_SIZE).max(1);
+        for offset in (0..df.height()).step_by(row_group_size) {
+            // one record batch, and so one row group, per slice
+            let group = df.slice(offset as i64, row_group_size).as_single_chunk().clone();
+            let batches = group.iter_chunks(CompatLevel::newest(), false).map(Ok);
+            for row_group in RowGroupIterator::try_new(batches, &schema, options, encodings.clone())? {
+                file_writer.write(row_group?)?;
+            }
+        }
+        file_writer.end(None)?;
+        Ok(())
+    }
+}
+
+/// The schema of a part already in a dataset directory.
+fn read_schema(part: &Path) -> PolarsResult<SchemaRef> {
+    LazyFrame::scan_parquet(part, ScanArgsParquet::default())?.collect_schema()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_utils::helpers::*;
+    use crate::to::{dataset_parts, ToFile};
     use std::error::Error;
 
     fn read_fn(path: &str) -> Result<DataFrame, Box<dyn Error>> {
@@ -39,6 +209,12 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sink_valid_file() -> Result<()> {
+        test_sink_read_compare(&writer(), read_fn)?;
+        Ok(())
+    }
+
     #[test]
     fn test_write_to_nonexistent_directory() -> Result<()> {
         test_write_should_fail(&writer());
@@ -51,9 +227,138 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_write_append_file() -> Result<()> {
+        test_write_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_append_file() -> Result<()> {
+        test_sink_append(&writer(), read_fn)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_rejects_schema_mismatch() -> Result<()> {
+        test_append_schema_mismatch(&writer(), read_fn)?;
+        Ok(())
+    }
+
     #[test]
     fn test_write_empty() -> Result<()> {
         test_write_empty_dataframe(&writer(), read_fn)?;
         Ok(())
     }
+
+    #[test]
+    fn test_append_to_dataset_directory() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().to_str().unwrap();
+        let df = create_sample_df()?;
+        writer().write_data(path, &df, true)?;
+        writer().sink_data(path, df.clone().lazy(), true)?;
+
+        let parts = dataset_parts(dir.path(), "parquet")?;
+        assert_eq!(parts, vec![dir.path().join("part-00000.parquet"), dir.path().join("part-00001.parquet")]);
+
+        let other = df! { "name" => &["Carol"] }?;
+        assert!(writer().write_data(path, &other, true).is_err());
+        assert_eq!(dataset_parts(dir.path(), "parquet")?.len(), 2);
+        Ok(())
+    }
+
+    /// Whether each chunk of the "name" column has statistics and is dictionary encoded.
+    fn name_chunks(path: &str) -> Result<Vec<(bool, bool)>> {
+        let mut reader = ParquetReader::new(File::open(path)?);
+        let metadata = reader.get_metadata()?;
+        Ok(metadata
+            .row_groups
+            .iter()
+            .flat_map(|rg| rg.columns_under_root_iter("name").unwrap())
+            .map(|c| {
+                let dictionary = c
+                    .column_encoding()
+                    .iter()
+                    .any(|e| Encoding::try_from(*e).ok() == Some(Encoding::RleDictionary));
+                (c.statistics().is_some(), dictionary)
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_write_every_codec() -> Result<()> {
+        let codecs = [
+            (ParquetCodec::Uncompressed, None),
+            (ParquetCodec::Snappy, None),
+            (ParquetCodec::Zstd, Some(19)),
+            (ParquetCodec::Gzip, Some(9)),
+            (ParquetCodec::Lz4, None),
+            (ParquetCodec::Brotli, Some(5)),
+        ];
+        for (codec, compression_level) in codecs {
+            let writer = ParquetWriterImpl {
+                codec,
+                compression_level,
+                ..writer()
+            };
+            test_write_read_compare(&writer, read_fn, false)?;
+            test_sink_read_compare(&writer, read_fn)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_compression_level() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let df = create_sample_df().unwrap();
+        for (codec, level) in [(ParquetCodec::Snappy, 1), (ParquetCodec::Zstd, 99)] {
+            let writer = ParquetWriterImpl {
+                codec,
+                compression_level: Some(level),
+                ..writer()
+            };
+            assert!(writer.write_data(path, &df, false).is_err());
+        }
+    }
+
+    #[test]
+    fn test_row_groups_without_statistics() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let writer = ParquetWriterImpl {
+            row_group_size: Some(1),
+            statistics: false,
+            ..writer()
+        };
+        writer.write_data(path, &create_sample_df()?, false)?;
+
+        let chunks = name_chunks(path)?;
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|(statistics, _)| !statistics));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_encoding_without_dictionary() -> Result<()> {
+        let temp_file = tempfile::NamedTempFile::new()?;
+        let path = temp_file.path().to_str().unwrap();
+        let df = df! { "name" => &["a", "a", "a", "b", "a"] }?;
+
+        writer().write_data(path, &df, false)?;
+        assert!(name_chunks(path)?[0].1);
+
+        let plain = ParquetWriterImpl {
+            dictionary: false,
+            row_group_size: Some(2),
+            ..writer()
+        };
+        plain.write_data(path, &df, false)?;
+        let chunks = name_chunks(path)?;
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|(_, dictionary)| !dictionary));
+        assert_dataframes_equal(&df, &read_fn(path).unwrap());
+        Ok(())
+    }
 }
@@ -0,0 +1,320 @@
+//! Hive-style partitioned output: one directory level per partition column,
+//! as in `year=2024/region=EU/part-00000.parquet`, each file written by the
+//! format's own writer through `Storage`, so object stores work as well.
+
+use super::{spool_query, DataWriter};
+use crate::storage::{Storage, STDIO_PATH};
+use anyhow::{bail, Result};
+use polars::io::HiveOptions;
+use polars::prelude::*;
+
+/// Directory value Hive uses for a partition whose value is null or empty.
+pub const HIVE_DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+const BUCKET_COLUMN: &str = "__bucket";
+const PASS_COLUMN: &str = "__pass";
+const ROWS_COLUMN: &str = "__rows";
+
+/// Rows collected at a time, as sized in the spool: about 512 MiB.
+const PASS_BYTES: u64 = 512 << 20;
+
+/// The layout of a partitioned dataset.
+/// - `columns`: one directory level per column, in order. The values are in
+///   the directory names, so the columns are left out of the files.
+/// - `bucket_by`: hash this column's values into `buckets` files per partition
+/// - `max_files_per_pass`: collect the rows of at most this many output files
+///   at a time
+#[derive(Debug, Clone, Default)]
+pub struct PartitionSpec {
+    pub columns: Vec<String>,
+    pub bucket_by: Option<String>,
+    pub buckets: usize,
+    pub max_files_per_pass: Option<usize>,
+}
+
+impl PartitionSpec {
+    /// Whether the output is a dataset directory rather than a single file.
+    pub fn is_partitioned(&self) -> bool {
+        !self.columns.is_empty() || self.bucket_by.is_some()
+    }
+}
+
+/// Write the query as a dataset under the directory `output`, one file per
+/// partition and bucket, named `part-NNNNN.<ext>` after the bucket. Existing
+/// files of the same name are replaced, or appended to with `append`.
+///
+/// The query runs once into a local Arrow spool, which is then read back a
+/// batch of files at a time, each batch holding about `PASS_BYTES` of rows
+/// and at most `max_files_per_pass` files.
+pub fn write_partitioned(
+    writer: &DataWriter,
+    output: &str,
+    mut lf: LazyFrame,
+    spec: &PartitionSpec,
+    append: bool,
+) -> Result<()> {
+    if output == STDIO_PATH {
+        bail!("Cannot write a partitioned dataset to stdout; give a directory with --output");
+    }
+    let schema = lf.collect_schema()?;
+    for column in spec.columns.iter().chain(&spec.bucket_by) {
+        if !schema.contains(column) {
+            bail!("Cannot partition by '{column}': there is no such column");
+        }
+    }
+    if spec.columns.len() >= schema.len() {
+        bail!("Cannot partition by every column; the files would have none left");
+    }
+    if spec.bucket_by.is_some() && spec.buckets == 0 {
+        bail!("The number of buckets must be at least 1");
+    }
+
+    let ext = writer.extension();
+    let mut by: Vec<&str> = spec.columns.iter().map(String::as_str).collect();
+    if let Some(key) = &spec.bucket_by {
+        lf = lf.with_column(bucket(key, spec.buckets).alias(BUCKET_COLUMN));
+        by.push(BUCKET_COLUMN);
+    }
+    let spool = spool_query(lf)?;
+    let args = ScanArgsIpc {
+        hive_options: HiveOptions {
+            enabled: Some(false),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let spooled = || LazyFrame::scan_ipc(spool.path(), args.clone());
+    let keys: Vec<Expr> = by.iter().map(|c| col(*c)).collect();
+    let files = spooled()?.group_by_stable(keys.clone()).agg([len().alias(ROWS_COLUMN)]).collect()?;
+
+    // number each file's pass, starting a new pass when the current one is full
+    let total: usize = files.column(ROWS_COLUMN)?.idx()?.into_no_null_iter().map(|n| n as usize).sum();
+    let row_bytes = (spool.as_file().metadata()?.len() / total.max(1) as u64).max(1);
+    let rows_per_pass = (PASS_BYTES / row_bytes).max(1) as usize;
+    let max_files = spec.max_files_per_pass.unwrap_or(usize::MAX);
+    let (mut pass, mut pass_files, mut pass_rows) = (0 as IdxSize, 0, 0);
+    let mut passes = Vec::with_capacity(files.height());
+    for rows in files.column(ROWS_COLUMN)?.idx()?.into_no_null_iter() {
+        let rows = rows as usize;
+        if pass_files > 0 && (pass_files == max_files || pass_rows + rows > rows_per_pass) {
+            (pass, pass_files, pass_rows) = (pass + 1, 0, 0);
+        }
+        (pass_files, pass_rows) = (pass_files + 1, pass_rows + rows);
+        passes.push(pass);
+    }
+    if pass == 0 {
+        return write_batch(writer, output, spooled()?.collect()?, spec, &by, &ext, append);
+    }
+
+    let mut numbered = files.drop(ROWS_COLUMN)?;
+    numbered.with_column(Column::new(PASS_COLUMN.into(), passes))?;
+    let args = JoinArgs {
+        join_nulls: true,
+        maintain_order: MaintainOrderJoin::Left,
+        ..JoinArgs::new(JoinType::Left)
+    };
+    let lf = spooled()?.join(numbered.lazy(), keys.clone(), keys, args);
+    for n in 0..=pass {
+        let batch = lf.clone().filter(col(PASS_COLUMN).eq(lit(n))).drop([PASS_COLUMN]).collect()?;
+        write_batch(writer, output, batch, spec, &by, &ext, append)?;
+    }
+    Ok(())
+}
+
+/// The bucket of each row, from the FNV-1a hash of the key's text; nulls go
+/// to bucket 0.
+fn bucket(key: &str, buckets: usize) -> Expr {
+    let buckets = buckets as u64;
+    col(key).cast(DataType::String).map(
+        move |values| {
+            let bucket: UInt32Chunked = values
+                .str()?
+                .iter()
+                .map(|value| Some(value.map_or(0, |v| (fnv1a(v.as_bytes()) % buckets) as u32)))
+                .collect();
+            Ok(Some(bucket.into_column()))
+        },
+        GetOutput::from_type(DataType::UInt32),
+    )
+}
+
+/// Split a collected batch of rows into its files, by the partition columns
+/// and bucket in `by`, and write them.
+fn write_batch(
+    writer: &DataWriter,
+    output: &str,
+    df: DataFrame,
+    spec: &PartitionSpec,
+    by: &[&str],
+    ext: &str,
+    append: bool,
+) -> Result<()> {
+    for part in df.partition_by_stable(by.to_vec(), true)? {
+        let mut dir = output.trim_end_matches('/').to_string();
+        for column in &spec.columns {
+            let value = part.column(column)?.cast(&DataType::String)?;
+            let value = match value.str()?.get(0) {
+                Some(value) if !value.is_empty() => escape_path_name(value),
+                _ => HIVE_DEFAULT_PARTITION.to_string(),
+            };
+            dir.push_str(&format!("/{}={value}", escape_path_name(column)));
+        }
+        let bucket = match spec.bucket_by {
+            Some(_) => part.column(BUCKET_COLUMN)?.u32()?.get(0).unwrap_or(0),
+            None => 0,
+        };
+        let path = format!("{dir}/part-{bucket:05}.{ext}");
+
+        let storage = Storage::new(&path)?;
+        if storage.is_local() {
+            std::fs::create_dir_all(&dir)?;
+        }
+        writer.write_data(&path, &part.drop_many(by.iter().copied()), append)?;
+    }
+    Ok(())
+}
+
+/// Percent-encode the characters Hive escapes in partition directory names.
+fn escape_path_name(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c < ' ' || "\"#%'*/:=?\\\x7f{[]^".contains(c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// 64-bit FNV-1a, a hash that stays the same across platforms and releases,
+/// so rows land in the same bucket every run.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to::csv::CsvWriterImpl;
+    use crate::to::parquet::ParquetWriterImpl;
+    use tempfile::TempDir;
+
+    fn sales() -> Result<DataFrame> {
+        Ok(df! {
+            "year" => &[2024i64, 2024, 2023, 2024],
+            "region" => &[Some("EU"), Some("US"), Some("EU"), None],
+            "sales" => &[1i64, 2, 3, 4]
+        }?)
+    }
+
+    fn listing(dir: &TempDir) -> Vec<String> {
+        let mut files = crate::storage::expand_input(dir.path().to_str().unwrap(), None).unwrap();
+        let root = dir.path().to_str().unwrap().len() + 1;
+        files.iter_mut().for_each(|f| *f = f[root..].replace('\\', "/"));
+        files
+    }
+
+    #[test]
+    fn test_hive_layout() -> Result<()> {
+        let dir = TempDir::new()?;
+        let writer = DataWriter::Csv(CsvWriterImpl::default());
+        let spec = PartitionSpec {
+            columns: vec!["year".to_string(), "region".to_string()],
+            ..Default::default()
+        };
+        write_partitioned(&writer, dir.path().to_str().unwrap(), sales()?.lazy(), &spec, false)?;
+
+        assert_eq!(
+            listing(&dir),
+            [
+                "year=2023/region=EU/part-00000.csv",
+                "year=2024/region=EU/part-00000.csv",
+                "year=2024/region=US/part-00000.csv",
+                "year=2024/region=__HIVE_DEFAULT_PARTITION__/part-00000.csv",
+            ]
+        );
+        let part = dir.path().join("year=2024/region=US/part-00000.csv");
+        assert_eq!(std::fs::read_to_string(part)?, "sales\n2\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_buckets_and_files_per_pass() -> Result<()> {
+        let df = df! {
+            "region" => (0..40).map(|i| ["EU", "US", "APAC"][i % 3]).collect::<Vec<_>>(),
+            "id" => (0..40i64).collect::<Vec<_>>()
+        }?;
+        let writer = DataWriter::Parquet(ParquetWriterImpl::default());
+        let spec = PartitionSpec {
+            columns: vec!["region".to_string()],
+            bucket_by: Some("id".to_string()),
+            buckets: 4,
+            max_files_per_pass: Some(5),
+        };
+        let dir = TempDir::new()?;
+        write_partitioned(&writer, dir.path().to_str().unwrap(), df.lazy(), &spec, false)?;
+
+        let files = listing(&dir);
+        assert_eq!(files.len(), 12);
+        assert!(files.iter().all(|f| f.ends_with(".parquet")));
+        let mut total = 0;
+        for file in &files {
+            let part = ParquetReader::new(std::fs::File::open(dir.path().join(file))?).finish()?;
+            assert_eq!(part.get_column_names(), ["id"]);
+            total += part.height();
+        }
+        assert_eq!(total, 40);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bucket_only_files_per_pass() -> Result<()> {
+        let df = df! {
+            "id" => (0..40i64).collect::<Vec<_>>(),
+            "amount" => (0..40i64).map(|i| i * 10).collect::<Vec<_>>()
+        }?;
+        let writer = DataWriter::Csv(CsvWriterImpl::default());
+        let one_pass = PartitionSpec {
+            bucket_by: Some("id".to_string()),
+            buckets: 4,
+            ..Default::default()
+        };
+        let batched = PartitionSpec {
+            max_files_per_pass: Some(1),
+            ..one_pass.clone()
+        };
+        let (whole, passes) = (TempDir::new()?, TempDir::new()?);
+        write_partitioned(&writer, whole.path().to_str().unwrap(), df.clone().lazy(), &one_pass, false)?;
+        write_partitioned(&writer, passes.path().to_str().unwrap(), df.lazy(), &batched, false)?;
+
+        let files = listing(&whole);
+        assert_eq!(files, ["part-00000.csv", "part-00001.csv", "part-00002.csv", "part-00003.csv"]);
+        assert_eq!(listing(&passes), files);
+        for file in &files {
+            let read = |dir: &TempDir| std::fs::read_to_string(dir.path().join(file));
+            assert_eq!(read(&passes)?, read(&whole)?, "{file}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_path_name() {
+        assert_eq!(escape_path_name("a/b=c"), "a%2Fb%3Dc");
+        assert_eq!(escape_path_name("2024-01-01 10:00:00"), "2024-01-01 10%3A00%3A00");
+        assert_eq!(escape_path_name("Zürich"), "Zürich");
+    }
+
+    #[test]
+    fn test_rejects_bad_specs() -> Result<()> {
+        let writer = DataWriter::Csv(CsvWriterImpl::default());
+        let spec = |columns: &[&str]| PartitionSpec {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        };
+        assert!(write_partitioned(&writer, "out", sales()?.lazy(), &spec(&["month"]), false).is_err());
+        assert!(write_partitioned(&writer, "out", sales()?.lazy(), &spec(&["year", "region", "sales"]), false).is_err());
+        assert!(write_partitioned(&writer, STDIO_PATH, sales()?.lazy(), &spec(&["year"]), false).is_err());
+        Ok(())
+    }
+}
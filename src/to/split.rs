@@ -0,0 +1,292 @@
+//! Output split over numbered part files, `out-00000.csv`, `out-00001.csv`,
+//! ..., for loaders with per-file limits. Every part is a complete file in
+//! the output format, with its own header or schema.
+
+use super::{spool_query, DataWriter};
+use crate::compression::FileCompression;
+use crate::storage::{Storage, STDIO_PATH};
+use anyhow::{bail, Result};
+use polars::prelude::*;
+use std::fs::File;
+use polars_arrow::io::ipc::read::{read_file_metadata, FileReader};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Limits on each part file; either or both may be set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SplitSpec {
+    pub max_rows: Option<usize>,
+    pub max_bytes: Option<u64>,
+}
+
+impl SplitSpec {
+    /// Whether the output is split at all.
+    pub fn is_split(&self) -> bool {
+        self.max_rows.is_some() || self.max_bytes.is_some()
+    }
+}
+
+/// The name of part `n` of `output`: the number goes before the extension,
+/// and before a compression extension, so `out.csv.gz` has `out-00000.csv.gz`.
+pub fn part_path(output: &str, n: usize) -> String {
+    let (stem, suffix) = split_extension(output);
+    format!("{stem}-{n:05}{suffix}")
+}
+
+/// `output` cut before its extensions, as `("dir/out", ".csv.gz")`.
+fn split_extension(output: &str) -> (&str, &str) {
+    let name_start = output.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    let name = &output[name_start..];
+    let mut stem_end = name.rfind('.').filter(|&i| i > 0).unwrap_or(name.len());
+    if FileCompression::from_extension(name).is_some() {
+        stem_end = name[..stem_end].rfind('.').filter(|&i| i > 0).unwrap_or(stem_end);
+    }
+    output.split_at(name_start + stem_end)
+}
+
+/// Write the query as numbered part files next to where `output` would be,
+/// each within the limits of `spec`. The query runs once into a local Arrow
+/// spool, whose record batches are gathered into parts and written as each
+/// part fills, so only about one part is in memory at a time. An empty
+/// result still gets one part, so the schema is written.
+pub fn write_split(writer: &DataWriter, output: &str, mut lf: LazyFrame, spec: SplitSpec) -> Result<()> {
+    if output == STDIO_PATH {
+        bail!("Cannot split output written to stdout; give a file name with --output");
+    }
+    if spec.max_rows == Some(0) || spec.max_bytes == Some(0) {
+        bail!("The rows and bytes per file must be at least 1");
+    }
+    let schema = lf.collect_schema()?;
+    let spool = spool_query(lf)?;
+
+    let mut parts = Parts {
+        writer,
+        output,
+        max_rows: spec.max_rows.unwrap_or(usize::MAX),
+        max_bytes: spec.max_bytes,
+        guess: None,
+        written: 0,
+        written_rows: 0,
+        written_bytes: 0,
+    };
+    let mut pending = DataFrame::empty_with_schema(&schema);
+    for frame in spooled_frames(spool.path())? {
+        pending.vstack_mut(&frame?)?;
+        parts.guess.get_or_insert_with(|| estimate(&pending, spec.max_bytes));
+        while pending.height() >= parts.rows() {
+            let rows = parts.write(&pending.slice(0, parts.rows()))?;
+            pending = pending.slice(rows as i64, usize::MAX);
+        }
+    }
+    while pending.height() > 0 || parts.written == 0 {
+        let rows = parts.write(&pending.slice(0, parts.rows()))?;
+        pending = pending.slice(rows as i64, usize::MAX);
+    }
+    Ok(())
+}
+
+/// The frames of an Arrow IPC file, one per record batch, in order.
+fn spooled_frames(path: &Path) -> Result<impl Iterator<Item = Result<DataFrame>>> {
+    let mut file = File::open(path)?;
+    let metadata = read_file_metadata(&mut file)?;
+    let schema = metadata.schema.clone();
+    let batches = FileReader::new(file, metadata, None, None);
+    Ok(batches.map(move |batch| Ok(DataFrame::try_from((batch?, schema.as_ref()))?)))
+}
+
+/// A first guess at the rows per part for `max_bytes`, from the size of the
+/// rows in memory, until written parts tell the real size.
+fn estimate(rows: &DataFrame, max_bytes: Option<u64>) -> usize {
+    let Some(max_bytes) = max_bytes else {
+        return usize::MAX;
+    };
+    let row_bytes = (rows.estimated_size() / rows.height().max(1)).max(1) as u64;
+    (max_bytes / row_bytes).max(1) as usize
+}
+
+/// The part files written so far, and how many rows the next one takes.
+struct Parts<'a> {
+    writer: &'a DataWriter,
+    output: &'a str,
+    max_rows: usize,
+    max_bytes: Option<u64>,
+    guess: Option<usize>,
+    written: usize,
+    written_rows: u64,
+    written_bytes: u64,
+}
+
+impl Parts<'_> {
+    /// Rows for the next part: the row limit, or fewer when the bytes per
+    /// row of the parts written so far say more would not fit.
+    fn rows(&self) -> usize {
+        self.max_rows.min(self.guess.unwrap_or(usize::MAX)).max(1)
+    }
+
+    /// Write the next part from the first of `rows`, returning how many of
+    /// them it took.
+    fn write(&mut self, rows: &DataFrame) -> Result<usize> {
+        let path = part_path(self.output, self.written);
+        self.written += 1;
+        let Some(max_bytes) = self.max_bytes else {
+            self.writer.write_data(&path, rows, false)?;
+            return Ok(rows.height());
+        };
+
+        // The size of a part is only known once it is written, so it goes to
+        // a local spool first and is shrunk if the guess was too high
+        let (_, suffix) = split_extension(self.output);
+        let mut part = rows.clone();
+        let spool = loop {
+            let spool = tempfile::Builder::new().suffix(suffix).tempfile()?;
+            self.writer.write_data(spool.path().to_str().unwrap(), &part, false)?;
+            let size = spool.as_file().metadata()?.len();
+            if size <= max_bytes || part.height() == 0 {
+                self.written_rows += part.height() as u64;
+                self.written_bytes += size;
+                break spool;
+            }
+            if part.height() == 1 {
+                let row = self.written_rows;
+                bail!("Row {row} alone takes {size} bytes, more than --max-bytes-per-file {max_bytes}");
+            }
+            // shrink in proportion, and by at least one row
+            let fitting = (part.height() as u64 * max_bytes / size) as usize * 95 / 100;
+            part = part.slice(0, fitting.clamp(1, part.height() - 1));
+        };
+        if let Some(row_bytes) = self.written_bytes.checked_div(self.written_rows) {
+            // leave room for the header or footer each part repeats
+            self.guess = Some((max_bytes / row_bytes.max(1)) as usize * 95 / 100);
+        }
+
+        let mut storage = Storage::new(&path)?;
+        let mut target = storage.get_target_file()?;
+        io::copy(&mut File::open(spool.path())?, &mut target)?;
+        target.flush()?;
+        storage.finish_write()?;
+        Ok(part.height())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to::csv::CsvWriterImpl;
+    use tempfile::TempDir;
+
+    fn numbers(rows: i64) -> Result<DataFrame> {
+        Ok(df! {
+            "id" => (0..rows).collect::<Vec<_>>(),
+            "name" => (0..rows).map(|i| format!("name-{i}")).collect::<Vec<_>>()
+        }?)
+    }
+
+    fn parts(dir: &TempDir) -> Result<Vec<String>> {
+        let mut names: Vec<String> = std::fs::read_dir(dir.path())?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_>>()?;
+        names.sort();
+        Ok(names)
+    }
+
+    #[test]
+    fn test_part_path() {
+        assert_eq!(part_path("out.csv", 0), "out-00000.csv");
+        assert_eq!(part_path("s3://bucket/exports/out.csv.gz", 12), "s3://bucket/exports/out-00012.csv.gz");
+        assert_eq!(part_path("dir.d/out", 1), "dir.d/out-00001");
+        assert_eq!(part_path(".hidden", 1), ".hidden-00001");
+    }
+
+    #[test]
+    fn test_split_by_rows() -> Result<()> {
+        let dir = TempDir::new()?;
+        let output = dir.path().join("out.csv");
+        let writer = DataWriter::Csv(CsvWriterImpl::default());
+        let spec = SplitSpec {
+            max_rows: Some(4),
+            ..Default::default()
+        };
+        write_split(&writer, output.to_str().unwrap(), numbers(10)?.lazy(), spec)?;
+
+        assert_eq!(parts(&dir)?, ["out-00000.csv", "out-00001.csv", "out-00002.csv"]);
+        let last = std::fs::read_to_string(dir.path().join("out-00002.csv"))?;
+        assert_eq!(last, "id,name\n8,name-8\n9,name-9\n");
+
+        // an exact multiple adds no empty part at the end
+        let dir = TempDir::new()?;
+        let output = dir.path().join("out.csv");
+        write_split(&writer, output.to_str().unwrap(), numbers(8)?.lazy(), spec)?;
+        assert_eq!(parts(&dir)?, ["out-00000.csv", "out-00001.csv"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_runs_once() -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let seen = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&seen);
+        let lf = numbers(1000)?.lazy().with_column(col("id").map(
+            move |c| {
+                counter.fetch_add(c.len(), Ordering::Relaxed);
+                Ok(Some(c))
+            },
+            GetOutput::same_type(),
+        ));
+        let dir = TempDir::new()?;
+        let output = dir.path().join("out.csv");
+        let writer = DataWriter::Csv(CsvWriterImpl::default());
+        let spec = SplitSpec {
+            max_rows: Some(100),
+            ..Default::default()
+        };
+        write_split(&writer, output.to_str().unwrap(), lf, spec)?;
+
+        assert_eq!(parts(&dir)?.len(), 10);
+        assert_eq!(seen.load(Ordering::Relaxed), 1000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_bytes() -> Result<()> {
+        let dir = TempDir::new()?;
+        let output = dir.path().join("out.csv");
+        let writer = DataWriter::Csv(CsvWriterImpl::default());
+        let spec = SplitSpec {
+            max_bytes: Some(200),
+            ..Default::default()
+        };
+        let df = numbers(100)?;
+        write_split(&writer, output.to_str().unwrap(), df.clone().lazy(), spec)?;
+
+        let mut rows = 0;
+        for part in parts(&dir)? {
+            let contents = std::fs::read_to_string(dir.path().join(part))?;
+            assert!(contents.len() <= 200);
+            assert!(contents.starts_with("id,name\n"));
+            rows += contents.lines().count() - 1;
+        }
+        assert_eq!(rows, 100);
+
+        let spec = SplitSpec {
+            max_bytes: Some(5),
+            ..Default::default()
+        };
+        assert!(write_split(&writer, output.to_str().unwrap(), df.lazy(), spec).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_frame_writes_one_part() -> Result<()> {
+        let dir = TempDir::new()?;
+        let output = dir.path().join("out.csv");
+        let writer = DataWriter::Csv(CsvWriterImpl::default());
+        for spec in [SplitSpec { max_rows: Some(10), max_bytes: None }, SplitSpec { max_rows: None, max_bytes: Some(100) }] {
+            write_split(&writer, output.to_str().unwrap(), numbers(0)?.lazy(), spec)?;
+            assert_eq!(parts(&dir)?, ["out-00000.csv"]);
+            assert_eq!(std::fs::read_to_string(dir.path().join("out-00000.csv"))?, "id,name\n");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+//! cast.rs
+use super::Transform;
+use crate::schema::{conversion_failures, convert, parse_dtype};
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use polars::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// What to do with a value that does not fit the type it is cast to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CastMode {
+    /// Fail the run, naming the column and a value that does not fit
+    #[default]
+    Strict,
+    /// Leave a null in its place, and report how many there were
+    Lenient,
+}
+
+/// Values of a column that could not be converted.
+#[derive(Debug, Clone, Default)]
+pub struct CastFailures {
+    pub count: usize,
+    pub example: Option<String>,
+}
+
+/// Failures of one column, added to by the cast as the query runs.
+#[derive(Debug, Default)]
+struct FailureCount {
+    count: AtomicUsize,
+    example: OnceLock<String>,
+}
+
+/// A Cast that converts each named column to a new type, parsing text and
+/// checking numbers so that no value silently changes.
+#[derive(Debug, Clone)]
+pub struct Cast {
+    pub columns: Vec<(String, DataType)>,
+    pub mode: CastMode,
+    failures: Arc<Vec<FailureCount>>,
+}
+
+impl Cast {
+    pub fn new(columns: Vec<(String, DataType)>, mode: CastMode) -> Self {
+        let failures = Arc::new(columns.iter().map(|_| FailureCount::default()).collect());
+        Self { columns, mode, failures }
+    }
+
+    /// Parse casts written as `name:type`, comma-separated, as in
+    /// `age:int32,ts:datetime[ms,UTC],price:decimal(12,2)`. Commas inside
+    /// brackets belong to the type.
+    pub fn parse(specs: &[String], mode: CastMode) -> Result<Self> {
+        let mut columns: Vec<(String, DataType)> = Vec::new();
+        for spec in specs.iter().flat_map(|s| split_top_level(s)) {
+            let Some((name, dtype)) = spec.split_once(':') else {
+                bail!("Invalid cast '{spec}'; expected column:type, as in age:int32");
+            };
+            let name = name.trim();
+            if columns.iter().any(|(c, _)| c == name) {
+                bail!("Column '{name}' is cast twice");
+            }
+            columns.push((name.to_string(), parse_dtype(dtype)?));
+        }
+        Ok(Self::new(columns, mode))
+    }
+
+    /// Values that a lenient cast left null, for the columns that had any.
+    pub fn failures(&self) -> Vec<(String, DataType, CastFailures)> {
+        self.columns
+            .iter()
+            .zip(self.failures.iter())
+            .filter_map(|((name, dtype), failures)| {
+                let count = failures.count.load(Ordering::Relaxed);
+                let example = failures.example.get().cloned();
+                (count > 0).then(|| (name.clone(), dtype.clone(), CastFailures { count, example }))
+            })
+            .collect()
+    }
+}
+
+/// Split on commas that are not inside `[...]` or `(...)`.
+fn split_top_level(spec: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0i32, 0);
+    for (i, c) in spec.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&spec[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&spec[start..]);
+    parts.into_iter().map(str::trim).filter(|p| !p.is_empty()).collect()
+}
+
+impl Transform for Cast {
+    fn transform(&self, mut lf: LazyFrame) -> Result<LazyFrame> {
+        let schema = lf.collect_schema()?;
+        let mut casts = Vec::with_capacity(self.columns.len());
+        for (index, (name, dtype)) in self.columns.iter().enumerate() {
+            if !schema.contains(name) {
+                bail!("Cannot cast '{name}': there is no such column");
+            }
+            let (name, dtype, mode) = (name.clone(), dtype.clone(), self.mode);
+            let output = GetOutput::from_type(dtype.clone());
+            let column = col(name.as_str());
+            let failures = Arc::clone(&self.failures);
+            let cast_column = move |c: Column| {
+                let out = convert(&c, &dtype, None)?;
+                let (failed, example) = conversion_failures(&c, &out)?;
+                if failed > 0 {
+                    if mode == CastMode::Strict {
+                        return Err(PolarsError::ComputeError(
+                            format!(
+                                "Cannot cast column '{name}' to {dtype}: {failed} value(s) do not fit, such as '{}'",
+                                example.unwrap_or_default()
+                            )
+                            .into(),
+                        ));
+                    }
+                    // the query runs once, so these are the output's nulls
+                    let failures = &failures[index];
+                    failures.count.fetch_add(failed, Ordering::Relaxed);
+                    if let Some(example) = example {
+                        let _ = failures.example.set(example);
+                    }
+                }
+                Ok(Some(out))
+            };
+            casts.push(column.map(cast_column, output));
+        }
+        Ok(lf.with_columns(casts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::Transform;
+
+    fn sample_df() -> DataFrame {
+        df! {
+            "age" => &[Some("30"), Some("unknown"), None],
+            "ts" => &["2024-01-01 10:00:00", "2024-06-30 23:59:59", "not a time"],
+            "price" => &[12.5, 3.25, 100.0]
+        }
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_casts() -> Result<()> {
+        let cast = Cast::parse(&["age:int32,ts:datetime[ms, UTC],price:decimal(12,2)".to_string()], CastMode::Strict)?;
+        assert_eq!(
+            cast.columns,
+            [
+                ("age".to_string(), DataType::Int32),
+                ("ts".to_string(), DataType::Datetime(TimeUnit::Milliseconds, Some("UTC".into()))),
+                ("price".to_string(), DataType::Decimal(Some(12), Some(2))),
+            ]
+        );
+        assert!(Cast::parse(&["age".to_string()], CastMode::Strict).is_err());
+        assert!(Cast::parse(&["age:int32".to_string(), "age:int64".to_string()], CastMode::Strict).is_err());
+        assert!(Cast::parse(&["age:integer".to_string()], CastMode::Strict).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient_cast_counts_failures() -> Result<()> {
+        let cast = Cast::parse(&["age:int32,ts:datetime[ms],price:decimal(8,2)".to_string()], CastMode::Lenient)?;
+        let result = cast.transform(sample_df().lazy())?.collect()?;
+
+        assert_eq!(result.column("age")?.dtype(), &DataType::Int32);
+        assert_eq!(result.column("age")?.i32()?.iter().collect::<Vec<_>>(), [Some(30), None, None]);
+        assert_eq!(result.column("ts")?.dtype(), &DataType::Datetime(TimeUnit::Milliseconds, None));
+        assert_eq!(result.column("price")?.dtype(), &DataType::Decimal(Some(8), Some(2)));
+
+        // the null that was already there is not a failure
+        let failures = cast.failures();
+        assert_eq!(failures.len(), 2);
+        assert_eq!((failures[0].0.as_str(), failures[0].2.count), ("age", 1));
+        assert_eq!(failures[0].2.example.as_deref(), Some("unknown"));
+        assert_eq!((failures[1].0.as_str(), failures[1].2.count), ("ts", 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_cast_fails() -> Result<()> {
+        let cast = Cast::parse(&["age:int32".to_string()], CastMode::Strict)?;
+        let err = cast.transform(sample_df().lazy())?.collect().unwrap_err();
+        assert!(err.to_string().contains("Cannot cast column 'age' to i32: 1 value(s) do not fit, such as 'unknown'"), "{err}");
+
+        // fractions are not truncated into integers
+        let cast = Cast::parse(&["price:int64".to_string()], CastMode::Strict)?;
+        assert!(cast.transform(sample_df().lazy())?.collect().is_err());
+
+        let cast = Cast::parse(&["missing:int32".to_string()], CastMode::Strict)?;
+        assert!(cast.transform(sample_df().lazy()).is_err());
+        Ok(())
+    }
+}
@@ -15,34 +15,32 @@ impl ColumnFilter {
 }
 
 impl Transform for ColumnFilter {
-    fn transform(&self, mut df: DataFrame) -> Result<DataFrame> {
-        // 1) If include is Some, select only those columns
-        if let Some(cols) = &self.include {
-            let column_names: Vec<String> = df
-                .get_column_names()
-                .iter()
-                .map(|pl_s| pl_s.as_str().to_string())
-                .filter(|name| cols.contains(name))
-                .collect();
-
-            df = df.select(column_names)?;
+    fn transform(&self, mut lf: LazyFrame) -> Result<LazyFrame> {
+        let schema = lf.collect_schema()?;
+        // 1) If include is Some, keep only those columns (in schema order)
+        // 2) If exclude is Some, drop those columns, ignoring unknown names
+        let kept: Vec<Expr> = schema
+            .iter_names()
+            .filter(|name| self.include.as_ref().is_none_or(|cols| cols.iter().any(|c| c == name.as_str())))
+            .filter(|name| self.exclude.as_ref().is_none_or(|cols| cols.iter().all(|c| c != name.as_str())))
+            .map(|name| col(name.clone()))
+            .collect();
+        if kept.len() == schema.len() {
+            return Ok(lf);
         }
 
-        // 2) If exclude is Some, drop those columns
-        if let Some(cols) = &self.exclude {
-            for col in cols {
-                // check if column name is in DataFrame
-                let contained = df
-                    .get_column_names()
-                    .iter()
-                    .any(|pl_s| pl_s.as_str() == col);
-                if contained {
-                    df = df.drop(col)?;
-                }
-            }
-        }
-
-        Ok(df)
+        // 3) A lazy select of no columns has no rows either, so keep the row
+        //    count by reading a single column and dropping it in a map
+        let Some(first) = schema.iter_names().next().filter(|_| kept.is_empty()) else {
+            return Ok(lf.select(kept));
+        };
+        let no_columns = |_: &Schema| Ok(Arc::new(Schema::default()));
+        Ok(lf.select([col(first.clone())]).map(
+            |df| df.select(Vec::<&str>::new()),
+            AllowedOptimizations::default(),
+            Some(Arc::new(no_columns)),
+            Some("drop all columns"),
+        ))
     }
 }
 
@@ -65,7 +63,7 @@ mod tests {
         let df = sample_dataframe();
         let filter = ColumnFilter::new(Some(vec!["name".to_string(), "city".to_string()]), None);
 
-        let result = filter.transform(df).unwrap();
+        let result = filter.transform(df.lazy()).unwrap().collect().unwrap();
 
         // Check if only the included columns are present
         assert_eq!(result.get_column_names(), vec!["name", "city"]);
@@ -76,7 +74,7 @@ mod tests {
         let df = sample_dataframe();
         let filter = ColumnFilter::new(None, Some(vec!["age".to_string()]));
 
-        let result = filter.transform(df).unwrap();
+        let result = filter.transform(df.lazy()).unwrap().collect().unwrap();
 
         // Check if the excluded column is missing
         assert_eq!(result.get_column_names(), vec!["name", "city"]);
@@ -90,7 +88,7 @@ mod tests {
             Some(vec!["city".to_string()]),
         );
 
-        let result = filter.transform(df).unwrap();
+        let result = filter.transform(df.lazy()).unwrap().collect().unwrap();
 
         // Check if the included columns minus the excluded ones are present
         assert_eq!(result.get_column_names(), vec!["name", "age"]);
@@ -101,7 +99,7 @@ mod tests {
         let df = sample_dataframe();
         let filter = ColumnFilter::new(Some(vec!["nonexistent".to_string()]), None);
 
-        let result = filter.transform(df);
+        let result = filter.transform(df.lazy()).and_then(|lf| Ok(lf.collect()?));
 
         // The DataFrame should have 0 columns but retain its original number of rows
         assert!(result.is_ok());
@@ -114,7 +112,7 @@ mod tests {
         let df = sample_dataframe();
         let filter = ColumnFilter::new(None, Some(vec!["name".to_string(), "age".to_string(), "city".to_string()]));
 
-        let result = filter.transform(df).unwrap();
+        let result = filter.transform(df.lazy()).unwrap().collect().unwrap();
 
         // The DataFrame should have 0 columns but retain its original number of rows
         assert_eq!(result.get_column_names(), Vec::<&str>::new());
@@ -126,9 +124,24 @@ mod tests {
         let df = sample_dataframe();
         let filter = ColumnFilter::new(None, None);
 
-        let result = filter.transform(df).unwrap();
+        let result = filter.transform(df.lazy()).unwrap().collect().unwrap();
 
         // Original DataFrame should remain unchanged
         assert_eq!(result.get_column_names(), vec!["name", "age", "city"]);
     }
+
+    #[test]
+    fn test_include_and_exclude_unknown_columns() {
+        let lf = sample_dataframe().lazy();
+        let filter = ColumnFilter::new(
+            Some(vec!["city".to_string(), "name".to_string(), "nonexistent".to_string()]),
+            Some(vec!["city".to_string(), "nonexistent".to_string()]),
+        );
+
+        let result = filter.transform(lf).unwrap().collect().unwrap();
+
+        // Schema order is kept and unknown names are ignored
+        assert_eq!(result.get_column_names(), vec!["name"]);
+        assert_eq!(result.height(), 3);
+    }
 }
@@ -1,9 +1,12 @@
 use anyhow::Result;
 use polars::prelude::*;
 
+/// Transforms rewrite the lazy query between reader and writer, so Polars can
+/// push projections and predicates down into the source scan.
 pub trait Transform {
-    fn transform(&self, df: DataFrame) -> Result<DataFrame>;
+    fn transform(&self, lf: LazyFrame) -> Result<LazyFrame>;
 }
 
+pub mod cast;
 pub mod column_filter;
 pub mod row_filter;
@@ -38,13 +38,10 @@ impl RowFilter {
             value,
         }
     }
-}
 
-/// Implement the same `Transform` trait as ColumnFilter uses.
-impl Transform for RowFilter {
-    fn transform(&self, df: DataFrame) -> Result<DataFrame> {
-        // Build a Polars expression for the filter
-        let filter_expr = match (&self.op, &self.value) {
+    /// Build the Polars predicate for this filter.
+    fn predicate(&self) -> Result<Expr> {
+        let expr = match (&self.op, &self.value) {
             // integer comparisons
             (RowFilterOp::Eq, RowFilterValue::Int(i)) => col(&self.column).eq(lit(*i)),
             (RowFilterOp::Gt, RowFilterValue::Int(i)) => col(&self.column).gt(lit(*i)),
@@ -61,10 +58,14 @@ impl Transform for RowFilter {
                 bail!("String '<' comparison not implemented")
             }
         };
+        Ok(expr)
+    }
+}
 
-        // Use lazy mode to apply the filter, then collect an eager DataFrame
-        let filtered = df.lazy().filter(filter_expr).collect()?;
-        Ok(filtered)
+/// Implement the same `Transform` trait as ColumnFilter uses.
+impl Transform for RowFilter {
+    fn transform(&self, lf: LazyFrame) -> Result<LazyFrame> {
+        Ok(lf.filter(self.predicate()?))
     }
 }
 
@@ -89,7 +90,7 @@ mod tests {
         let df = sample_df();
         // Keep rows where age == 25 -> "Bob"
         let filter = RowFilter::new("age", RowFilterOp::Eq, RowFilterValue::Int(25));
-        let out = filter.transform(df).unwrap();
+        let out = filter.transform(df.lazy()).unwrap().collect().unwrap();
         assert_eq!(out.shape(), (1, 3)); // 1 row, 3 columns
 
         // check row[0, "name"] => "Bob"
@@ -102,7 +103,7 @@ mod tests {
         let df = sample_df();
         // Keep rows where age > 30 -> "Charlie"
         let filter = RowFilter::new("age", RowFilterOp::Gt, RowFilterValue::Int(30));
-        let out = filter.transform(df).unwrap();
+        let out = filter.transform(df.lazy()).unwrap().collect().unwrap();
         assert_eq!(out.shape(), (1, 3));
 
         // row[0, "name"] => "Charlie"
@@ -115,7 +116,7 @@ mod tests {
         let df = sample_df();
         // Keep rows where city == "LA" -> "Bob"
         let filter = RowFilter::new("city", RowFilterOp::Eq, RowFilterValue::Str("LA".into()));
-        let out = filter.transform(df).unwrap();
+        let out = filter.transform(df.lazy()).unwrap().collect().unwrap();
         assert_eq!(out.shape(), (1, 3));
 
         let val_city = out.column("city").unwrap().get(0).unwrap().to_string();
@@ -129,7 +130,19 @@ mod tests {
         let df = sample_df();
         // keep rows where age == 99 => none
         let filter = RowFilter::new("age", RowFilterOp::Eq, RowFilterValue::Int(99));
-        let out = filter.transform(df).unwrap();
+        let out = filter.transform(df.lazy()).unwrap().collect().unwrap();
         assert_eq!(out.shape(), (0, 3));
     }
+
+    #[test]
+    fn test_lt_int() {
+        let lf = sample_df().lazy();
+        // Keep rows where age < 30 -> "Bob"
+        let filter = RowFilter::new("age", RowFilterOp::Lt, RowFilterValue::Int(30));
+        let out = filter.transform(lf).unwrap().collect().unwrap();
+        assert_eq!(out.shape(), (1, 3));
+
+        let val = out.column("name").unwrap().get(0).unwrap().to_string();
+        assert_eq!(val, "\"Bob\"");
+    }
 }
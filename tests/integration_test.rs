@@ -205,3 +205,876 @@ Charlie,35,CHI
     assert!(out_data.contains("Bob,30,SF"));
     assert!(out_data.contains("Charlie,35,CHI"));
 }
+
+#[test]
+fn test_csv_to_parquet_streaming_with_filters() {
+    let csv_data = "\
+name,age,city
+Alice,25,NYC
+Bob,30,SF
+Charlie,35,CHI
+";
+
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("input.csv");
+    let parquet_path = tmp.path().join("output.parquet");
+    let output_csv_path = tmp.path().join("output.csv");
+    fs::write(&input_csv_path, csv_data).expect("Unable to write test CSV");
+
+    // Stream CSV -> Parquet with both filters applied to the lazy plan
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "parquet",
+            input_csv_path.to_str().unwrap(),
+            "--output",
+            parquet_path.to_str().unwrap(),
+            "--streaming",
+            "--exclude-columns",
+            "city",
+            "--row-filter-col",
+            "age",
+            "--row-filter-op",
+            "gt",
+            "--row-filter-val",
+            "25",
+        ])
+        .assert()
+        .success();
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "parquet",
+            "csv",
+            parquet_path.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--streaming",
+        ])
+        .assert()
+        .success();
+
+    let out_data = fs::read_to_string(&output_csv_path).unwrap();
+    assert_csv_contains_lines(&out_data, &["name,age", "Bob,30", "Charlie,35"]);
+    assert_csv_excludes_lines(&out_data, &["Alice", "city"]);
+}
+
+#[test]
+fn test_parquet_row_filter_on_excluded_column() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("input.csv");
+    let parquet_path = tmp.path().join("input.parquet");
+    let output_csv_path = tmp.path().join("output.csv");
+    fs::write(&input_csv_path, SAMPLE_CSV_DATA).expect("Unable to write test CSV");
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "parquet",
+            input_csv_path.to_str().unwrap(),
+            "--output",
+            parquet_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+
+    // The row filter runs before the projection, so it may use a dropped column
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "parquet",
+            "csv",
+            parquet_path.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--include-columns",
+            "name",
+            "--row-filter-col",
+            "city",
+            "--row-filter-op",
+            "eq",
+            "--row-filter-val",
+            "SF",
+        ])
+        .assert()
+        .success();
+
+    let out_data = fs::read_to_string(&output_csv_path).unwrap();
+    assert_eq!(out_data.trim(), "name\nBob");
+
+    // an unknown operator is ignored with a single warning
+    let assert = Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "parquet",
+            "csv",
+            parquet_path.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--row-filter-col",
+            "city",
+            "--row-filter-op",
+            "like",
+            "--row-filter-val",
+            "SF",
+        ])
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).to_string();
+    assert_eq!(stderr.matches("Invalid row filter operator: like").count(), 1, "{stderr}");
+}
+
+#[test]
+fn test_headerless_tsv_with_null_tokens() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_path = tmp.path().join("input.tsv");
+    let output_csv_path = tmp.path().join("output.csv");
+    fs::write(&input_path, "Alice\t30\nBob\tNA\n").expect("Unable to write test TSV");
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "csv",
+            input_path.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--delimiter",
+            "tab",
+            "--no-header",
+            "--null-values",
+            "NA",
+        ])
+        .assert()
+        .success();
+
+    let out_data = fs::read_to_string(&output_csv_path).unwrap();
+    assert_csv_contains_lines(&out_data, &["column_1,column_2", "Alice,30", "Bob,\n"]);
+}
+
+#[test]
+fn test_csv_writer_dialect() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_path = tmp.path().join("input.csv");
+    let output_csv_path = tmp.path().join("output.csv");
+    fs::write(&input_path, "name,note\nAlice,=1+1\nBob,\n").expect("Unable to write test CSV");
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "csv",
+            input_path.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--output-delimiter",
+            ";",
+            "--output-null-value",
+            "NULL",
+            "--output-no-header",
+            "--output-crlf",
+            "--escape-formulas",
+        ])
+        .assert()
+        .success();
+
+    let out_data = fs::read_to_string(&output_csv_path).unwrap();
+    assert_eq!(out_data, "Alice;'=1+1\r\nBob;NULL\r\n");
+}
+
+#[test]
+fn test_csv_append_without_duplicate_header() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_path = tmp.path().join("input.csv");
+    let other_path = tmp.path().join("other.csv");
+    let output_csv_path = tmp.path().join("output.csv");
+    fs::write(&input_path, SAMPLE_CSV_DATA).expect("Unable to write test CSV");
+    fs::write(&other_path, "name,score\nCarol,1.5\n").expect("Unable to write test CSV");
+
+    let append = |input: &std::path::Path| {
+        Command::cargo_bin("frameblaze")
+            .unwrap()
+            .args([
+                "csv",
+                "csv",
+                input.to_str().unwrap(),
+                "--output",
+                output_csv_path.to_str().unwrap(),
+                "--append",
+            ])
+            .assert()
+    };
+    append(&input_path).success();
+    append(&input_path).success();
+    append(&other_path).failure();
+
+    let out_data = fs::read_to_string(&output_csv_path).unwrap();
+    assert_eq!(out_data, format!("{SAMPLE_CSV_DATA}Alice,30,NYC\nBob,25,SF\n"));
+}
+
+#[test]
+fn test_json_document_with_record_path_and_flatten() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_path = tmp.path().join("export.json");
+    let output_csv_path = tmp.path().join("output.csv");
+    fs::write(
+        &input_path,
+        r#"{"data": {"items": [{"id": 1, "user": {"name": "Alice"}}, {"id": 2, "user": {"name": "Bob"}}]}}"#,
+    )
+    .expect("Unable to write test JSON");
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "json",
+            "csv",
+            input_path.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--json-format",
+            "json",
+            "--record-path",
+            "data.items",
+            "--flatten",
+        ])
+        .assert()
+        .success();
+
+    let out_data = fs::read_to_string(&output_csv_path).unwrap();
+    assert_eq!(out_data, "id,user.name\n1,Alice\n2,Bob\n");
+}
+
+#[test]
+fn test_csv_to_json_array_with_wrapper_key() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_path = tmp.path().join("input.csv");
+    let output_path = tmp.path().join("output.json");
+    fs::write(&input_path, "name,age\nAlice,30\nBob,\n").expect("Unable to write test CSV");
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "json",
+            input_path.to_str().unwrap(),
+            "--output",
+            output_path.to_str().unwrap(),
+            "--output-json-format",
+            "json",
+            "--output-wrapper-key",
+            "data",
+            "--output-omit-nulls",
+        ])
+        .assert()
+        .success();
+
+    let out_data = fs::read_to_string(&output_path).unwrap();
+    assert_eq!(out_data, "{\"data\":[{\"name\":\"Alice\",\"age\":30},{\"name\":\"Bob\"}]}\n");
+}
+
+#[test]
+fn test_ipc_stream_through_a_pipe() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("input.csv");
+    let output_csv_path = tmp.path().join("output.csv");
+    fs::write(&input_csv_path, SAMPLE_CSV_DATA).expect("Unable to write test CSV");
+
+    let stream = Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "ipc-stream",
+            input_csv_path.to_str().unwrap(),
+            "--output",
+            "-",
+            "--ipc-compression",
+            "zstd",
+        ])
+        .output()
+        .expect("Unable to run frameblaze");
+    assert!(stream.status.success());
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["ipc-stream", "csv", "-", "--output", output_csv_path.to_str().unwrap()])
+        .write_stdin(stream.stdout)
+        .assert()
+        .success();
+
+    let out_data = fs::read_to_string(&output_csv_path).unwrap();
+    assert_eq!(out_data, SAMPLE_CSV_DATA);
+}
+
+/// Avro's logical types (date, time, timestamps, decimal) survive a
+/// conversion between every pair of typed formats.
+#[test]
+fn test_logical_types_round_trip_between_formats() {
+    use polars::prelude::*;
+
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let source_path = tmp.path().join("source.parquet");
+    let mut source = df! {
+        "day" => &[Some(19_000), None],
+        "at" => &[Some(45_296_789_123_000i64), None],
+        "local" => &[Some(1_700_000_000_123i64), None],
+        "utc" => &[Some(1_700_000_000_123_456i64), None],
+        "price" => &[Some(1234.56), None],
+        "id" => &[Some("6f1c2a4e-3b1d-4c7e-9a52-0d3e8f7b1a21"), None],
+    }
+    .unwrap()
+    .lazy()
+    .with_columns([
+        col("day").cast(DataType::Date),
+        col("at").cast(DataType::Time),
+        col("local").cast(DataType::Datetime(TimeUnit::Milliseconds, None)),
+        col("utc").cast(DataType::Datetime(TimeUnit::Microseconds, Some("UTC".into()))),
+        col("price").cast(DataType::Decimal(Some(12), Some(2))),
+    ])
+    .collect()
+    .unwrap();
+    ParquetWriter::new(fs::File::create(&source_path).unwrap())
+        .finish(&mut source)
+        .unwrap();
+
+    let convert = |from: &str, to: &str, input: &std::path::Path, output: &std::path::Path| {
+        Command::cargo_bin("frameblaze")
+            .unwrap()
+            .args([from, to, input.to_str().unwrap(), "--output", output.to_str().unwrap()])
+            .assert()
+            .success();
+    };
+
+    let formats = ["parquet", "avro", "ipc", "ipc-stream"];
+    for from in formats {
+        for to in formats.iter().filter(|&&to| to != from) {
+            let first = tmp.path().join(format!("{from}-{to}.first"));
+            let second = tmp.path().join(format!("{from}-{to}.second"));
+            let back = tmp.path().join(format!("{from}-{to}.parquet"));
+            convert("parquet", from, &source_path, &first);
+            convert(from, to, &first, &second);
+            convert(to, "parquet", &second, &back);
+
+            let result = ParquetReader::new(fs::File::open(&back).unwrap()).finish().unwrap();
+            assert_eq!(result.schema(), source.schema(), "{from} -> {to}");
+            assert!(result.equals_missing(&source), "{from} -> {to}:\n{result}");
+        }
+    }
+
+    // text formats keep the values as text, and --schema types them again
+    let schema_path = tmp.path().join("schema.toml");
+    fs::write(
+        &schema_path,
+        [
+            ("day", "date"),
+            ("at", "time"),
+            ("local", "datetime[ms]"),
+            ("utc", "datetime[us,UTC]"),
+            ("price", "decimal(12,2)"),
+            ("id", "string"),
+        ]
+        .iter()
+        .map(|(name, dtype)| format!("[[columns]]\nname = \"{name}\"\ntype = \"{dtype}\"\n"))
+        .collect::<String>(),
+    )
+    .unwrap();
+    for from in formats {
+        for to in ["csv", "json"] {
+            let first = tmp.path().join(format!("{from}-{to}.first"));
+            let text = tmp.path().join(format!("{from}.{to}"));
+            let back = tmp.path().join(format!("{from}-{to}.parquet"));
+            convert("parquet", from, &source_path, &first);
+            convert(from, to, &first, &text);
+            Command::cargo_bin("frameblaze")
+                .unwrap()
+                .args([to, "parquet", text.to_str().unwrap(), "--output", back.to_str().unwrap()])
+                .args(["--schema", schema_path.to_str().unwrap()])
+                .assert()
+                .success();
+
+            let result = ParquetReader::new(fs::File::open(&back).unwrap()).finish().unwrap();
+            assert_eq!(result.schema(), source.schema(), "{from} -> {to}");
+            assert!(result.equals_missing(&source), "{from} -> {to}:\n{result}");
+        }
+    }
+}
+
+#[test]
+fn test_compressed_input_is_decompressed() {
+    use std::io::Write;
+
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let gz_path = tmp.path().join("feed.csv.gz");
+    let mut encoder = flate2::write::GzEncoder::new(fs::File::create(&gz_path).unwrap(), flate2::Compression::default());
+    encoder.write_all(SAMPLE_CSV_DATA.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let output_csv_path = tmp.path().join("output.csv");
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["csv", "csv", gz_path.to_str().unwrap(), "--output", output_csv_path.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_csv_path).unwrap(), SAMPLE_CSV_DATA);
+
+    // a zstd feed without a telling extension, named explicitly
+    let zst_path = tmp.path().join("feed.jsonl");
+    let lines = "{\"name\":\"Alice\",\"age\":30}\n{\"name\":\"Bob\",\"age\":25}\n";
+    fs::write(&zst_path, zstd::encode_all(lines.as_bytes(), 0).unwrap()).unwrap();
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "json",
+            "csv",
+            zst_path.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--input-compression",
+            "zstd",
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_csv_path).unwrap(), "name,age\nAlice,30\nBob,25\n");
+
+    // detection can be turned off for a plain file with a misleading name
+    let misnamed_path = tmp.path().join("plain.csv.gz");
+    fs::write(&misnamed_path, SAMPLE_CSV_DATA).unwrap();
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "csv",
+            misnamed_path.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--input-compression",
+            "none",
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_csv_path).unwrap(), SAMPLE_CSV_DATA);
+}
+
+#[test]
+fn test_compressed_output() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("input.csv");
+    let gz_path = tmp.path().join("export.csv.gz");
+    let zst_path = tmp.path().join("export.jsonl");
+    let output_csv_path = tmp.path().join("output.csv");
+    fs::write(&input_csv_path, SAMPLE_CSV_DATA).expect("Unable to write test CSV");
+
+    // the extension picks gzip
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["csv", "csv", input_csv_path.to_str().unwrap(), "--output", gz_path.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(&fs::read(&gz_path).unwrap()[..2], &[0x1f, 0x8b]);
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["csv", "csv", gz_path.to_str().unwrap(), "--output", output_csv_path.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_csv_path).unwrap(), SAMPLE_CSV_DATA);
+
+    // the flag picks zstd, also when streaming
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "json",
+            input_csv_path.to_str().unwrap(),
+            "--output",
+            zst_path.to_str().unwrap(),
+            "--output-compression",
+            "zstd",
+            "--streaming",
+        ])
+        .assert()
+        .success();
+    let lines = zstd::decode_all(fs::File::open(&zst_path).unwrap()).unwrap();
+    assert_eq!(
+        String::from_utf8(lines).unwrap(),
+        "{\"name\":\"Alice\",\"age\":30,\"city\":\"NYC\"}\n{\"name\":\"Bob\",\"age\":25,\"city\":\"SF\"}\n"
+    );
+
+    // binary formats have their own codecs
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "parquet",
+            input_csv_path.to_str().unwrap(),
+            "--output",
+            tmp.path().join("out.parquet").to_str().unwrap(),
+            "--output-compression",
+            "gzip",
+        ])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_formats_detected_from_magic_bytes_and_extensions() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("input.csv");
+    // a Parquet file whose name does not say so
+    let parquet_path = tmp.path().join("export.dat");
+    let output_csv_path = tmp.path().join("output.csv");
+    fs::write(&input_csv_path, SAMPLE_CSV_DATA).expect("Unable to write test CSV");
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["csv", "parquet", input_csv_path.to_str().unwrap(), "-o", parquet_path.to_str().unwrap()])
+        .assert()
+        .success();
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([parquet_path.to_str().unwrap(), "-o", output_csv_path.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_csv_path).unwrap(), SAMPLE_CSV_DATA);
+}
+
+#[test]
+fn test_stdin_and_stdout_pipelines() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let parquet_path = tmp.path().join("out.parquet");
+    let lines = "{\"name\":\"Alice\",\"age\":30,\"city\":\"NYC\"}\n{\"name\":\"Bob\",\"age\":25,\"city\":\"SF\"}\n";
+
+    // curl ... | frameblaze json parquet - -o out.parquet
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["json", "parquet", "-", "-o", parquet_path.to_str().unwrap()])
+        .write_stdin(lines)
+        .assert()
+        .success();
+
+    // Parquet to CSV on stdout, also when streaming
+    for extra in [&[][..], &["--streaming"][..]] {
+        let output = Command::cargo_bin("frameblaze")
+            .unwrap()
+            .args(["parquet", "csv", parquet_path.to_str().unwrap(), "-o", "-"])
+            .args(extra)
+            .output()
+            .expect("Unable to run frameblaze");
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), SAMPLE_CSV_DATA);
+    }
+
+    // CSV through Avro and back, entirely in a pipe
+    let avro = Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["csv", "avro", "-", "-o", "-"])
+        .write_stdin(SAMPLE_CSV_DATA)
+        .output()
+        .expect("Unable to run frameblaze");
+    assert!(avro.status.success());
+    assert!(avro.stdout.starts_with(b"Obj\x01"));
+    let csv = Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["avro", "csv", "-", "-o", "-"])
+        .write_stdin(avro.stdout)
+        .output()
+        .expect("Unable to run frameblaze");
+    assert_eq!(String::from_utf8(csv.stdout).unwrap(), SAMPLE_CSV_DATA);
+
+    // stdout cannot be appended to, and stdin has no name to detect a format from
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["csv", "csv", "-", "-o", "-", "--append"])
+        .write_stdin(SAMPLE_CSV_DATA)
+        .assert()
+        .failure();
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["-", "-o", parquet_path.to_str().unwrap()])
+        .write_stdin(SAMPLE_CSV_DATA)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_glob_and_directory_inputs_with_schema_policy() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let dir = tmp.path().join("daily");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("2024-01-01.csv"), "name,age\nAlice,30\n").unwrap();
+    fs::write(dir.join("2024-01-02.csv"), "name,age,city\nBob,25,LA\n").unwrap();
+    fs::write(dir.join("_SUCCESS"), "").unwrap();
+    let output_csv_path = tmp.path().join("output.csv");
+
+    // the files differ, so the default strict policy refuses to combine them
+    let glob = format!("{}/2024-*.csv", dir.to_str().unwrap());
+    let assert = Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([glob.as_str(), "--output", output_csv_path.to_str().unwrap()])
+        .assert()
+        .failure();
+    assert!(String::from_utf8_lossy(&assert.get_output().stderr).contains("--schema-policy"));
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            glob.as_str(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--schema-policy",
+            "union-by-name",
+            "--source-column",
+            "file",
+            "--exclude-columns",
+            "file",
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_csv_path).unwrap(), "name,age,city\nAlice,30,\nBob,25,LA\n");
+
+    // a directory is read whole, with the format taken from its first file
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            dir.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--schema-policy",
+            "intersect",
+            "--source-column",
+            "file",
+        ])
+        .assert()
+        .success();
+    let output = fs::read_to_string(&output_csv_path).unwrap();
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines[0], "name,age,file");
+    assert!(lines[1].starts_with("Alice,30,") && lines[1].ends_with("2024-01-01.csv"));
+    assert!(lines[2].starts_with("Bob,25,") && lines[2].ends_with("2024-01-02.csv"));
+}
+
+#[test]
+fn test_partitioned_output() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("sales.csv");
+    fs::write(&input_csv_path, "year,region,amount\n2024,EU,1\n2024,US,2\n2023,EU,3\n2024,EU,4\n").unwrap();
+    let dataset = tmp.path().join("dataset");
+
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "csv",
+            input_csv_path.to_str().unwrap(),
+            "--output",
+            dataset.to_str().unwrap(),
+            "--partition-by",
+            "year,region",
+            "--max-files-per-pass",
+            "2",
+        ])
+        .assert()
+        .success();
+
+    let read = |partition: &str| fs::read_to_string(dataset.join(partition).join("part-00000.csv")).unwrap();
+    assert_eq!(read("year=2024/region=EU"), "amount\n1\n4\n");
+    assert_eq!(read("year=2024/region=US"), "amount\n2\n");
+    assert_eq!(read("year=2023/region=EU"), "amount\n3\n");
+
+    // partitions cannot be written to stdout
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["csv", "csv", input_csv_path.to_str().unwrap(), "--output", "-", "--partition-by", "year"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_partitioned_input_is_pruned() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("sales.csv");
+    fs::write(&input_csv_path, "year,region,amount\n2024,EU,1\n2024,US,2\n2023,EU,3\n2025,EU,4\n").unwrap();
+    let dataset = tmp.path().join("dataset");
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args(["csv", "parquet", input_csv_path.to_str().unwrap(), "--output", dataset.to_str().unwrap(), "--partition-by", "year,region"])
+        .assert()
+        .success();
+
+    // a file the filter rules out is never opened
+    fs::write(dataset.join("year=2023/region=EU/part-00000.parquet"), "not parquet").unwrap();
+
+    let output_csv_path = tmp.path().join("output.csv");
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "parquet",
+            "csv",
+            dataset.to_str().unwrap(),
+            "--output",
+            output_csv_path.to_str().unwrap(),
+            "--row-filter-col",
+            "year",
+            "--row-filter-op",
+            "gt",
+            "--row-filter-val",
+            "2023",
+        ])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&output_csv_path).unwrap(), "amount,year,region\n1,2024,EU\n2,2024,US\n4,2025,EU\n");
+}
+
+#[test]
+fn test_output_split_into_part_files() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("input.csv");
+    let rows: String = (0..50).map(|i| format!("{i},name-{i}\n")).collect();
+    fs::write(&input_csv_path, format!("id,name\n{rows}")).unwrap();
+
+    let output_path = tmp.path().join("out.csv.gz");
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([input_csv_path.to_str().unwrap(), "--output", output_path.to_str().unwrap(), "--max-rows-per-file", "20"])
+        .assert()
+        .success();
+
+    let mut total = String::new();
+    for n in 0..3 {
+        let part = fs::File::open(tmp.path().join(format!("out-{n:05}.csv.gz"))).unwrap();
+        let mut contents = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::MultiGzDecoder::new(part), &mut contents).unwrap();
+        let (header, rest) = contents.split_once('\n').unwrap();
+        assert_eq!(header, "id,name");
+        total.push_str(rest);
+    }
+    assert_eq!(total, rows);
+    assert!(!tmp.path().join("out-00003.csv.gz").exists());
+
+    // size-limited parts of Parquet output
+    let output_path = tmp.path().join("out.parquet");
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([input_csv_path.to_str().unwrap(), "--output", output_path.to_str().unwrap(), "--max-bytes-per-file", "1K"])
+        .assert()
+        .success();
+    assert!(fs::metadata(tmp.path().join("out-00000.parquet")).unwrap().len() <= 1024);
+}
+
+#[test]
+fn test_schema_file_types_untyped_input() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let schema_path = tmp.path().join("schema.toml");
+    fs::write(
+        &schema_path,
+        "[[columns]]\nname = \"zip\"\ntype = \"string\"\n\n[[columns]]\nname = \"joined\"\ntype = \"date\"\nformat = \"%d/%m/%Y\"\n",
+    )
+    .unwrap();
+    let input_csv_path = tmp.path().join("input.csv");
+    fs::write(&input_csv_path, "zip,joined\n01234,31/12/2024\n00501,01/02/2025\n").unwrap();
+
+    let output_json_path = tmp.path().join("output.jsonl");
+    Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            input_csv_path.to_str().unwrap(),
+            "--output",
+            output_json_path.to_str().unwrap(),
+            "--schema",
+            schema_path.to_str().unwrap(),
+        ])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(&output_json_path).unwrap(),
+        "{\"zip\":\"01234\",\"joined\":\"2024-12-31\"}\n{\"zip\":\"00501\",\"joined\":\"2025-02-01\"}\n"
+    );
+
+    // a date that does not exist fails the run, naming the column and value
+    fs::write(&input_csv_path, "zip,joined\n01234,31/02/2024\n").unwrap();
+    let assert = Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            input_csv_path.to_str().unwrap(),
+            "--output",
+            output_json_path.to_str().unwrap(),
+            "--schema",
+            schema_path.to_str().unwrap(),
+        ])
+        .assert()
+        .failure();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).to_string();
+    assert!(stderr.contains("Column 'joined'") && stderr.contains("31/02/2024"), "{stderr}");
+}
+
+#[test]
+fn test_cast_strict_and_lenient() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("input.csv");
+    fs::write(&input_csv_path, "age,price\n30,12.5\nunknown,3.25\n41,100\n").unwrap();
+    let output_json_path = tmp.path().join("output.jsonl");
+    let cast = |mode: &str| {
+        Command::cargo_bin("frameblaze")
+            .unwrap()
+            .args([
+                input_csv_path.to_str().unwrap(),
+                "--output",
+                output_json_path.to_str().unwrap(),
+                "--cast",
+                "age:int32,price:decimal(8,2)",
+                "--cast-mode",
+                mode,
+            ])
+            .assert()
+    };
+
+    let assert = cast("strict").failure();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).to_string();
+    assert!(stderr.contains("Cannot cast column 'age'") && stderr.contains("'unknown'"), "{stderr}");
+
+    let assert = cast("lenient").success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).to_string();
+    assert!(stderr.contains("Cast of 'age' to i32: 1 value(s) did not fit"), "{stderr}");
+    let output = fs::read_to_string(&output_json_path).unwrap();
+    assert!(output.starts_with("{\"age\":30,"), "{output}");
+    assert!(output.contains("{\"age\":null,"), "{output}");
+}
+
+#[test]
+fn test_lenient_cast_counts_once_across_partition_passes() {
+    let tmp = tempdir().expect("Unable to create temp dir");
+    let input_csv_path = tmp.path().join("input.csv");
+    fs::write(&input_csv_path, "region,age
+EU,30
+US,unknown
+APAC,41
+EU,n/a
+").unwrap();
+    let dataset = tmp.path().join("dataset");
+
+    // one pass to find the partitions, then one per partition
+    let assert = Command::cargo_bin("frameblaze")
+        .unwrap()
+        .args([
+            "csv",
+            "csv",
+            input_csv_path.to_str().unwrap(),
+            "--output",
+            dataset.to_str().unwrap(),
+            "--partition-by",
+            "region",
+            "--max-files-per-pass",
+            "1",
+            "--cast",
+            "age:int32",
+            "--cast-mode",
+            "lenient",
+        ])
+        .assert()
+        .success();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).to_string();
+    assert!(stderr.contains("Cast of 'age' to i32: 2 value(s) did not fit"), "{stderr}");
+    assert!(stderr.contains("such as 'unknown'"), "{stderr}");
+    assert_eq!(fs::read_to_string(dataset.join("region=EU/part-00000.csv")).unwrap(), "age\n30\n\n");
+}